@@ -0,0 +1,74 @@
+-- The Postgres schema from migrations/ for SQLite. Uuids are stored as blobs, timestamps as
+-- RFC 3339 text in UTC and time limits as whole minutes.
+
+CREATE TABLE rooms (
+    id blob NOT NULL,
+    name text NOT NULL,
+    room_id text NOT NULL,
+    capacity integer,
+    time_limit integer,
+    link text NOT NULL,
+    comments text,
+    timezone text DEFAULT 'UTC' NOT NULL,
+    location text,
+    archived_at text,
+    CONSTRAINT rooms_pkey PRIMARY KEY (id),
+    CONSTRAINT rooms_name_key UNIQUE (name),
+    CONSTRAINT rooms_room_id_key UNIQUE (room_id)
+);
+
+CREATE TABLE occupancies (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    occupied_room_id blob NOT NULL,
+    occupied_until text NOT NULL,
+    meeting_title text NOT NULL,
+    comments text,
+    started_at text NOT NULL,
+    checked_in boolean DEFAULT false NOT NULL,
+    headcount integer,
+    booked_by text,
+    CONSTRAINT occupancies_room_key UNIQUE (occupied_room_id),
+    CONSTRAINT fk_room FOREIGN KEY (occupied_room_id) REFERENCES rooms(id)
+);
+
+CREATE TABLE occupancy_history (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    occupied_room_id blob NOT NULL,
+    started_at text NOT NULL,
+    ended_at text NOT NULL,
+    occupied_until text NOT NULL,
+    meeting_title text NOT NULL,
+    comments text,
+    no_show boolean DEFAULT false NOT NULL,
+    headcount integer,
+    booked_by text,
+    CONSTRAINT fk_history_room FOREIGN KEY (occupied_room_id) REFERENCES rooms(id)
+);
+
+CREATE TABLE maintenance_windows (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    room_id blob NOT NULL,
+    starts_at text NOT NULL,
+    ends_at text NOT NULL,
+    reason text NOT NULL,
+    CONSTRAINT maintenance_windows_check CHECK (ends_at > starts_at),
+    CONSTRAINT fk_maintenance_room FOREIGN KEY (room_id) REFERENCES rooms(id)
+);
+
+CREATE TABLE room_opening_hours (
+    room_id blob NOT NULL,
+    weekday integer NOT NULL,
+    opens_at text NOT NULL,
+    closes_at text NOT NULL,
+    CONSTRAINT room_opening_hours_pkey PRIMARY KEY (room_id, weekday, opens_at),
+    CONSTRAINT room_opening_hours_check CHECK (closes_at > opens_at),
+    CONSTRAINT room_opening_hours_weekday_check CHECK (weekday >= 0 AND weekday <= 6),
+    CONSTRAINT fk_opening_hours_room FOREIGN KEY (room_id) REFERENCES rooms(id)
+);
+
+CREATE TABLE holidays (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    date text NOT NULL,
+    name text NOT NULL,
+    location text
+);
@@ -0,0 +1,27 @@
+-- Rooms and their current occupancies. Everything is created only if missing, so databases set
+-- up from the old db/init.sql can adopt the migrations as they are.
+
+CREATE EXTENSION IF NOT EXISTS "uuid-ossp" WITH SCHEMA public;
+
+CREATE TABLE IF NOT EXISTS rooms (
+    id uuid DEFAULT uuid_generate_v4() NOT NULL,
+    name character varying NOT NULL,
+    room_id character varying NOT NULL,
+    capacity integer,
+    time_limit interval,
+    link character varying NOT NULL,
+    comments character varying,
+    CONSTRAINT rooms_pkey PRIMARY KEY (id),
+    CONSTRAINT rooms_name_key UNIQUE (name),
+    CONSTRAINT rooms_room_id_key UNIQUE (room_id)
+);
+
+CREATE TABLE IF NOT EXISTS occupancies (
+    id serial NOT NULL,
+    occupied_room_id uuid NOT NULL,
+    occupied_until timestamp with time zone NOT NULL,
+    meeting_title character varying NOT NULL,
+    comments character varying,
+    CONSTRAINT occupancies_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_room FOREIGN KEY (occupied_room_id) REFERENCES rooms(id)
+);
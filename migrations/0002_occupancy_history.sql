@@ -0,0 +1,29 @@
+-- Check-ins, headcounts and the history of finished meetings
+
+ALTER TABLE occupancies
+    ADD COLUMN IF NOT EXISTS started_at timestamp with time zone DEFAULT now() NOT NULL,
+    ADD COLUMN IF NOT EXISTS checked_in boolean DEFAULT false NOT NULL,
+    ADD COLUMN IF NOT EXISTS headcount integer,
+    ADD COLUMN IF NOT EXISTS booked_by character varying;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'occupancies_room_key') THEN
+        ALTER TABLE occupancies ADD CONSTRAINT occupancies_room_key UNIQUE (occupied_room_id);
+    END IF;
+END $$;
+
+CREATE TABLE IF NOT EXISTS occupancy_history (
+    id serial NOT NULL,
+    occupied_room_id uuid NOT NULL,
+    started_at timestamp with time zone NOT NULL,
+    ended_at timestamp with time zone NOT NULL,
+    occupied_until timestamp with time zone NOT NULL,
+    meeting_title character varying NOT NULL,
+    comments character varying,
+    no_show boolean DEFAULT false NOT NULL,
+    headcount integer,
+    booked_by character varying,
+    CONSTRAINT occupancy_history_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_history_room FOREIGN KEY (occupied_room_id) REFERENCES rooms(id)
+);
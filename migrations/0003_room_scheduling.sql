@@ -0,0 +1,35 @@
+-- Maintenance windows, opening hours in the room's timezone and holidays per location
+
+ALTER TABLE rooms
+    ADD COLUMN IF NOT EXISTS timezone character varying DEFAULT 'UTC' NOT NULL,
+    ADD COLUMN IF NOT EXISTS location character varying;
+
+CREATE TABLE IF NOT EXISTS maintenance_windows (
+    id serial NOT NULL,
+    room_id uuid NOT NULL,
+    starts_at timestamp with time zone NOT NULL,
+    ends_at timestamp with time zone NOT NULL,
+    reason character varying NOT NULL,
+    CONSTRAINT maintenance_windows_pkey PRIMARY KEY (id),
+    CONSTRAINT maintenance_windows_check CHECK (ends_at > starts_at),
+    CONSTRAINT fk_maintenance_room FOREIGN KEY (room_id) REFERENCES rooms(id)
+);
+
+CREATE TABLE IF NOT EXISTS room_opening_hours (
+    room_id uuid NOT NULL,
+    weekday smallint NOT NULL,
+    opens_at time without time zone NOT NULL,
+    closes_at time without time zone NOT NULL,
+    CONSTRAINT room_opening_hours_pkey PRIMARY KEY (room_id, weekday, opens_at),
+    CONSTRAINT room_opening_hours_check CHECK (closes_at > opens_at),
+    CONSTRAINT room_opening_hours_weekday_check CHECK (weekday >= 0 AND weekday <= 6),
+    CONSTRAINT fk_opening_hours_room FOREIGN KEY (room_id) REFERENCES rooms(id)
+);
+
+CREATE TABLE IF NOT EXISTS holidays (
+    id serial NOT NULL,
+    date date NOT NULL,
+    name character varying NOT NULL,
+    location character varying,
+    CONSTRAINT holidays_pkey PRIMARY KEY (id)
+);
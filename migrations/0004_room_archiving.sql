@@ -0,0 +1,4 @@
+-- Rooms removed from the rooms definition file are archived instead of deleted
+
+ALTER TABLE rooms
+    ADD COLUMN IF NOT EXISTS archived_at timestamp with time zone;
@@ -0,0 +1,9 @@
+-- Token buckets of the rate limiter, shared by every replica when rate_limit_shared is on.
+-- Losing them in a crash only resets the limits, so they skip the write-ahead log.
+
+CREATE UNLOGGED TABLE IF NOT EXISTS rate_limit_buckets (
+    key character varying NOT NULL,
+    tokens double precision NOT NULL,
+    refilled_at timestamp with time zone NOT NULL,
+    CONSTRAINT rate_limit_buckets_pkey PRIMARY KEY (key)
+);
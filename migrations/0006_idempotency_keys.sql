@@ -0,0 +1,14 @@
+-- Responses to requests sent with an Idempotency-Key, replayed to their retries
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    key character varying NOT NULL,
+    request_hash bytea NOT NULL,
+    -- NULL until the first request has been answered
+    status smallint,
+    content_type character varying,
+    body bytea,
+    created_at timestamp with time zone NOT NULL,
+    CONSTRAINT idempotency_keys_pkey PRIMARY KEY (key)
+);
+
+CREATE INDEX IF NOT EXISTS idempotency_keys_created_at_idx ON idempotency_keys (created_at);
@@ -0,0 +1,10 @@
+-- Free-form tags per room, like "video" or "whiteboard", to narrow down analytics
+
+CREATE TABLE IF NOT EXISTS room_tags (
+    room_id uuid NOT NULL,
+    tag character varying NOT NULL,
+    CONSTRAINT room_tags_pkey PRIMARY KEY (room_id, tag),
+    CONSTRAINT fk_tags_room FOREIGN KEY (room_id) REFERENCES rooms(id)
+);
+
+CREATE INDEX IF NOT EXISTS room_tags_tag_idx ON room_tags (tag);
@@ -0,0 +1,6 @@
+-- Room names are still unique after every statement, unless a transaction defers the check to
+-- its end, like `sync` does to swap names between rooms
+
+ALTER TABLE rooms
+    DROP CONSTRAINT IF EXISTS rooms_name_key,
+    ADD CONSTRAINT rooms_name_key UNIQUE (name) DEFERRABLE INITIALLY IMMEDIATE;
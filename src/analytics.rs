@@ -0,0 +1,787 @@
+use chrono::Duration;
+use serde_json::json;
+
+use crate::{
+    errors::{
+        DateRangeTooLongError, InternalServerError, InvalidDateRangeError, InvalidTimezoneError,
+    },
+    models::{
+        AnalyticsGroup, BookerSizing, DailyPeak, HeatmapCell, HeatmapQuery, OversizedMeeting,
+        RoomSizing, SizingQuery, Utilization, UtilizationQuery,
+    },
+    schedule::is_valid_timezone,
+    telemetry::traced,
+    DBPool,
+};
+
+/// Longest range a heatmap is computed for
+const MAX_HEATMAP_DAYS: i64 = 366;
+
+/// Every meeting which actually took place, past and ongoing. Ongoing meetings count until now
+/// and no-shows are left out.
+const MEETINGS: &str = "meetings AS (
+  SELECT 
+    occupied_room_id, 
+    started_at, 
+    ended_at, 
+    meeting_title, 
+    headcount, 
+    booked_by 
+  FROM 
+    occupancy_history 
+  WHERE 
+    NOT no_show 
+  UNION ALL 
+  SELECT 
+    occupied_room_id, 
+    started_at, 
+    LEAST(occupied_until, NOW()), 
+    meeting_title, 
+    headcount, 
+    booked_by 
+  FROM 
+    occupancies
+) ";
+
+/// Booked and bookable hours per room for the range `$1..$2`, meetings are clipped to the range.
+/// Bookable hours come from the room's opening hours in its local timezone, rooms without any
+/// are bookable around the clock. Holidays of the room's location and its maintenance windows
+/// are taken out of them. Rooms archived before the range are left out, and rooms archived
+/// during it are only bookable until then.
+const UTILIZATION: &str = "booked AS (
+  SELECT 
+    occupied_room_id AS room_id, 
+    SUM(
+      EXTRACT(
+        EPOCH 
+        FROM 
+          LEAST(ended_at, $2) - GREATEST(started_at, $1)
+      )
+    ):: float8 / 3600 AS booked_hours, 
+    COUNT(*) AS meeting_count, 
+    SUM(
+      EXTRACT(
+        EPOCH 
+        FROM 
+          ended_at - started_at
+      )
+    ):: float8 / 60 AS meeting_minutes 
+  FROM 
+    meetings 
+  WHERE 
+    started_at < $2 
+    AND ended_at > $1 
+  GROUP BY 
+    occupied_room_id
+), 
+reported_rooms AS (
+  SELECT 
+    rooms.*, 
+    tstzrange(
+      $1, 
+      LEAST(
+        $2, 
+        COALESCE(rooms.archived_at, $2)
+      )
+    ) AS reported_range 
+  FROM 
+    rooms 
+  WHERE 
+    rooms.archived_at IS NULL 
+    OR rooms.archived_at > $1
+), 
+open_periods AS (
+  SELECT 
+    rooms.id AS room_id, 
+    tstzrange(
+      (
+        days.day :: date + room_opening_hours.opens_at
+      ) AT TIME ZONE rooms.timezone, 
+      (
+        days.day :: date + room_opening_hours.closes_at
+      ) AT TIME ZONE rooms.timezone
+    ) AS period 
+  FROM 
+    reported_rooms AS rooms 
+    JOIN room_opening_hours ON room_opening_hours.room_id = rooms.id 
+    CROSS JOIN LATERAL generate_series(
+      ($1 AT TIME ZONE rooms.timezone):: date, 
+      ($2 AT TIME ZONE rooms.timezone):: date, 
+      INTERVAL '1 day'
+    ) AS days(day) 
+  WHERE 
+    room_opening_hours.weekday = EXTRACT(
+      ISODOW 
+      FROM 
+        days.day :: date
+    ) - 1 
+  UNION ALL 
+  SELECT 
+    rooms.id, 
+    rooms.reported_range 
+  FROM 
+    reported_rooms AS rooms 
+  WHERE 
+    NOT EXISTS (
+      SELECT 
+        1 
+      FROM 
+        room_opening_hours 
+      WHERE 
+        room_opening_hours.room_id = rooms.id
+    )
+), 
+closed_periods AS (
+  SELECT 
+    rooms.id AS room_id, 
+    tstzrange(
+      holidays.date :: timestamp AT TIME ZONE rooms.timezone, 
+      (holidays.date + 1):: timestamp AT TIME ZONE rooms.timezone
+    ) AS period 
+  FROM 
+    reported_rooms AS rooms 
+    JOIN holidays ON holidays.location IS NULL 
+    OR holidays.location = rooms.location 
+  WHERE 
+    holidays.date BETWEEN ($1 AT TIME ZONE rooms.timezone):: date 
+    AND ($2 AT TIME ZONE rooms.timezone):: date 
+  UNION ALL 
+  SELECT 
+    room_id, 
+    tstzrange(starts_at, ends_at) 
+  FROM 
+    maintenance_windows 
+  WHERE 
+    starts_at < $2 
+    AND ends_at > $1
+), 
+bookable AS (
+  SELECT 
+    rooms.id AS room_id, 
+    rooms.name, 
+    rooms.location, 
+    COALESCE(
+      (
+        SELECT 
+          SUM(
+            EXTRACT(
+              EPOCH 
+              FROM 
+                upper(bookable_period) - lower(bookable_period)
+            )
+          ) 
+        FROM 
+          unnest(
+            COALESCE(
+              (
+                SELECT 
+                  range_agg(open_periods.period) 
+                FROM 
+                  open_periods 
+                WHERE 
+                  open_periods.room_id = rooms.id
+              ), 
+              '{}'
+            ) * tstzmultirange(rooms.reported_range) - COALESCE(
+              (
+                SELECT 
+                  range_agg(closed_periods.period) 
+                FROM 
+                  closed_periods 
+                WHERE 
+                  closed_periods.room_id = rooms.id
+              ), 
+              '{}'
+            )
+          ) AS bookable_period
+      ), 
+      0
+    ):: float8 / 3600 AS bookable_hours 
+  FROM 
+    reported_rooms AS rooms
+) ";
+
+/// Meetings between `$1` and `$2`, narrowed down to a room (`$4`), a location (`$5`) or rooms
+/// with a tag (`$6`) when sent. `$3` is the timezone used to bucket them into days and hours.
+const FILTERED_MEETINGS: &str = "filtered_meetings AS (
+  SELECT 
+    meetings.started_at, 
+    meetings.ended_at 
+  FROM 
+    meetings 
+    JOIN rooms ON rooms.id = meetings.occupied_room_id 
+  WHERE 
+    meetings.started_at < $2 
+    AND meetings.ended_at > $1 
+    AND (
+      $4 :: uuid IS NULL 
+      OR rooms.id = $4
+    ) 
+    AND (
+      $5 :: varchar IS NULL 
+      OR rooms.location = $5
+    ) 
+    AND (
+      $6 :: varchar IS NULL 
+      OR EXISTS (
+        SELECT 
+          1 
+        FROM 
+          room_tags 
+        WHERE 
+          room_tags.room_id = rooms.id 
+          AND room_tags.tag = $6
+      )
+    )
+) ";
+
+/// Meetings between `$1` and `$2` which recorded a headcount, with the capacity of their room.
+/// A meeting is oversized when it used at most half of the room's seats.
+const SIZED_MEETINGS: &str = "sized_meetings AS (
+  SELECT 
+    meetings.*, 
+    rooms.name AS room_name, 
+    rooms.capacity, 
+    meetings.headcount * 2 <= rooms.capacity AS oversized 
+  FROM 
+    meetings 
+    JOIN rooms ON rooms.id = meetings.occupied_room_id 
+  WHERE 
+    meetings.started_at < $2 
+    AND meetings.ended_at > $1 
+    AND meetings.headcount IS NOT NULL
+) ";
+
+pub async fn fetch_utilization(
+    query: UtilizationQuery,
+    db: DBPool,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if query.from >= query.to {
+        return Err(warp::reject::custom(InvalidDateRangeError));
+    }
+
+    let report_query = match query.group_by.unwrap_or(AnalyticsGroup::Room) {
+        AnalyticsGroup::Room => {
+            "SELECT 
+              bookable.room_id, 
+              bookable.name, 
+              bookable.location, 
+              COALESCE(booked.booked_hours, 0) AS booked_hours, 
+              bookable.bookable_hours, 
+              COALESCE(
+                booked.booked_hours * 100 / NULLIF(bookable.bookable_hours, 0), 
+                0
+              ) AS utilization_percentage, 
+              COALESCE(booked.meeting_count, 0) AS meeting_count, 
+              booked.meeting_minutes / booked.meeting_count AS average_meeting_minutes 
+            FROM 
+              bookable 
+              LEFT OUTER JOIN booked ON booked.room_id = bookable.room_id 
+            ORDER BY 
+              utilization_percentage, 
+              bookable.name"
+        }
+        AnalyticsGroup::Location => {
+            "SELECT 
+              NULL :: uuid AS room_id, 
+              NULL AS name, 
+              bookable.location, 
+              COALESCE(SUM(booked.booked_hours), 0) AS booked_hours, 
+              SUM(bookable.bookable_hours) AS bookable_hours, 
+              COALESCE(
+                SUM(booked.booked_hours) * 100 / NULLIF(SUM(bookable.bookable_hours), 0), 
+                0
+              ) AS utilization_percentage, 
+              COALESCE(SUM(booked.meeting_count), 0):: int8 AS meeting_count, 
+              SUM(booked.meeting_minutes) / SUM(booked.meeting_count) AS average_meeting_minutes 
+            FROM 
+              bookable 
+              LEFT OUTER JOIN booked ON booked.room_id = bookable.room_id 
+            GROUP BY 
+              bookable.location 
+            ORDER BY 
+              utilization_percentage, 
+              bookable.location"
+        }
+    };
+
+    let sql = format!("WITH {MEETINGS}, {UTILIZATION}{report_query}");
+
+    let query_result = sqlx::query_as::<_, Utilization>(&sql)
+        .bind(query.from)
+        .bind(query.to)
+        .fetch_all(traced(&db))
+        .await;
+
+    match query_result {
+        Ok(utilization) => {
+            let resp = json!({
+                "from": query.from,
+                "to": query.to,
+                "utilization": utilization,
+            });
+
+            Ok(warp::reply::json(&resp))
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to compute utilization");
+
+            Err(warp::reject::custom(InternalServerError))
+        }
+    }
+}
+
+pub async fn fetch_heatmap(
+    query: HeatmapQuery,
+    db: DBPool,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if query.from >= query.to {
+        return Err(warp::reject::custom(InvalidDateRangeError));
+    }
+
+    // Every hour of the range is a row, keep it to a year's worth
+    if query.to - query.from > Duration::days(MAX_HEATMAP_DAYS) {
+        return Err(warp::reject::custom(DateRangeTooLongError));
+    }
+
+    if !is_valid_timezone(query.timezone.as_deref()) {
+        return Err(warp::reject::custom(InvalidTimezoneError));
+    }
+
+    let timezone = query.timezone.unwrap_or_else(|| "UTC".to_string());
+
+    // Number of meetings overlapping every hour of the range, grouped by weekday and hour
+    let cells_sql = format!(
+        "WITH {MEETINGS}, {FILTERED_MEETINGS}, 
+        slots AS (
+          SELECT 
+            slot 
+          FROM 
+            generate_series(
+              date_trunc('hour', $1 AT TIME ZONE $3), 
+              $2 AT TIME ZONE $3, 
+              INTERVAL '1 hour'
+            ) AS slot 
+          WHERE 
+            slot < $2 AT TIME ZONE $3
+        ), 
+        concurrency AS (
+          SELECT 
+            slots.slot, 
+            COUNT(filtered_meetings.started_at) AS concurrent 
+          FROM 
+            slots 
+            LEFT OUTER JOIN filtered_meetings ON filtered_meetings.started_at < (slots.slot + INTERVAL '1 hour') AT TIME ZONE $3 
+            AND filtered_meetings.ended_at > slots.slot AT TIME ZONE $3 
+          GROUP BY 
+            slots.slot
+        ) 
+        SELECT 
+          (
+            EXTRACT(
+              ISODOW 
+              FROM 
+                slot
+            ) - 1
+          ):: int2 AS weekday, 
+          EXTRACT(
+            HOUR 
+            FROM 
+              slot
+          ):: int2 AS hour, 
+          MAX(concurrent) AS max_concurrency, 
+          AVG(concurrent):: float8 AS average_concurrency 
+        FROM 
+          concurrency 
+        GROUP BY 
+          weekday, 
+          hour"
+    );
+
+    // Running count of meetings at every start and end, with an empty event at the start of
+    // every day so meetings carried over from the previous day are counted too
+    let peaks_sql = format!(
+        "WITH {MEETINGS}, {FILTERED_MEETINGS}, 
+        days AS (
+          SELECT 
+            day 
+          FROM 
+            generate_series(
+              date_trunc('day', $1 AT TIME ZONE $3), 
+              $2 AT TIME ZONE $3, 
+              INTERVAL '1 day'
+            ) AS day
+        ), 
+        events AS (
+          SELECT 
+            started_at AS at, 
+            1 AS delta 
+          FROM 
+            filtered_meetings 
+          UNION ALL 
+          SELECT 
+            ended_at, 
+            -1 
+          FROM 
+            filtered_meetings 
+          UNION ALL 
+          SELECT 
+            day AT TIME ZONE $3, 
+            0 
+          FROM 
+            days
+        ), 
+        running AS (
+          SELECT 
+            at, 
+            SUM(delta) OVER (
+              ORDER BY 
+                at, 
+                delta
+            ) AS concurrent 
+          FROM 
+            events
+        ) 
+        SELECT 
+          (at AT TIME ZONE $3):: date AS date, 
+          MAX(concurrent):: int8 AS peak_concurrency 
+        FROM 
+          running 
+        WHERE 
+          at >= date_trunc('day', $1 AT TIME ZONE $3) AT TIME ZONE $3 
+          AND at < $2 
+        GROUP BY 
+          date 
+        ORDER BY 
+          date"
+    );
+
+    let cells_query = sqlx::query_as::<_, HeatmapCell>(&cells_sql)
+        .bind(query.from)
+        .bind(query.to)
+        .bind(&timezone)
+        .bind(query.room_id)
+        .bind(&query.location)
+        .bind(&query.tag)
+        .fetch_all(traced(&db))
+        .await;
+
+    let peaks_query = sqlx::query_as::<_, DailyPeak>(&peaks_sql)
+        .bind(query.from)
+        .bind(query.to)
+        .bind(&timezone)
+        .bind(query.room_id)
+        .bind(&query.location)
+        .bind(&query.tag)
+        .fetch_all(traced(&db))
+        .await;
+
+    let room_count_query = sqlx::query_scalar::<_, i64>(
+        "SELECT 
+          COUNT(*) 
+        FROM 
+          rooms 
+        WHERE 
+          archived_at IS NULL 
+          AND (
+            $1 :: uuid IS NULL 
+            OR id = $1
+          ) 
+          AND (
+            $2 :: varchar IS NULL 
+            OR location = $2
+          ) 
+          AND (
+            $3 :: varchar IS NULL 
+            OR EXISTS (
+              SELECT 
+                1 
+              FROM 
+                room_tags 
+              WHERE 
+                room_tags.room_id = rooms.id 
+                AND room_tags.tag = $3
+            )
+          )",
+    )
+    .bind(query.room_id)
+    .bind(&query.location)
+    .bind(&query.tag)
+    .fetch_one(traced(&db))
+    .await;
+
+    match (cells_query, peaks_query, room_count_query) {
+        (Ok(cells), Ok(daily_peaks), Ok(room_count)) => {
+            let mut max_concurrency = [[0i64; 24]; 7];
+            let mut average_concurrency = [[0f64; 24]; 7];
+
+            for cell in cells {
+                let (weekday, hour) = (cell.weekday as usize, cell.hour as usize);
+
+                max_concurrency[weekday][hour] = cell.max_concurrency;
+                average_concurrency[weekday][hour] = cell.average_concurrency;
+            }
+
+            let resp = json!({
+                "from": query.from,
+                "to": query.to,
+                "timezone": timezone,
+                "room_count": room_count,
+                "max_concurrency": max_concurrency,
+                "average_concurrency": average_concurrency,
+                "daily_peaks": daily_peaks,
+            });
+
+            Ok(warp::reply::json(&resp))
+        }
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            tracing::error!(error = %e, "Failed to compute heatmap");
+
+            Err(warp::reject::custom(InternalServerError))
+        }
+    }
+}
+
+pub async fn fetch_right_sizing(
+    query: SizingQuery,
+    db: DBPool,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if query.from >= query.to {
+        return Err(warp::reject::custom(InvalidDateRangeError));
+    }
+
+    // Rooms and bookers are consistently oversized when at least 3 of their meetings, and at
+    // least three quarters of them, were oversized
+    let rooms_sql = format!(
+        "WITH {MEETINGS}, {SIZED_MEETINGS} 
+        SELECT 
+          rooms.id AS room_id, 
+          rooms.name, 
+          rooms.capacity, 
+          COUNT(sized_meetings.headcount) AS meeting_count, 
+          AVG(sized_meetings.headcount):: float8 AS average_headcount, 
+          (
+            AVG(
+              sized_meetings.headcount :: float8 / NULLIF(rooms.capacity, 0)
+            ) * 100
+          ):: float8 AS average_seat_usage, 
+          COUNT(*) FILTER (
+            WHERE 
+              sized_meetings.oversized
+          ) AS oversized_count, 
+          COUNT(*) FILTER (
+            WHERE 
+              sized_meetings.oversized
+          ) >= 3 
+          AND COUNT(*) FILTER (
+            WHERE 
+              sized_meetings.oversized
+          ) * 4 >= COUNT(sized_meetings.headcount) * 3 AS consistently_oversized 
+        FROM 
+          rooms 
+          LEFT OUTER JOIN sized_meetings ON sized_meetings.occupied_room_id = rooms.id 
+        GROUP BY 
+          rooms.id 
+        ORDER BY 
+          average_seat_usage NULLS LAST, 
+          rooms.name"
+    );
+
+    let bookers_sql = format!(
+        "WITH {MEETINGS}, {SIZED_MEETINGS} 
+        SELECT 
+          booked_by, 
+          COUNT(*) AS meeting_count, 
+          AVG(headcount):: float8 AS average_headcount, 
+          AVG(capacity):: float8 AS average_capacity, 
+          COUNT(*) FILTER (
+            WHERE 
+              oversized
+          ) AS oversized_count, 
+          COUNT(*) FILTER (
+            WHERE 
+              oversized
+          ) >= 3 
+          AND COUNT(*) FILTER (
+            WHERE 
+              oversized
+          ) * 4 >= COUNT(*) * 3 AS consistently_oversized 
+        FROM 
+          sized_meetings 
+        WHERE 
+          booked_by IS NOT NULL 
+        GROUP BY 
+          booked_by 
+        ORDER BY 
+          oversized_count DESC, 
+          booked_by"
+    );
+
+    // Smaller rooms which could fit the meeting and had nothing booked at the same time
+    let meetings_sql = format!(
+        "WITH {MEETINGS}, {SIZED_MEETINGS} 
+        SELECT 
+          sized_meetings.occupied_room_id AS room_id, 
+          sized_meetings.room_name, 
+          sized_meetings.capacity, 
+          sized_meetings.meeting_title, 
+          sized_meetings.headcount, 
+          sized_meetings.booked_by, 
+          sized_meetings.started_at, 
+          sized_meetings.ended_at, 
+          COALESCE(
+            (
+              SELECT 
+                json_agg(
+                  json_build_object(
+                    'id', rooms.id, 'name', rooms.name, 
+                    'capacity', rooms.capacity
+                  ) 
+                  ORDER BY 
+                    rooms.capacity, 
+                    rooms.name
+                ) 
+              FROM 
+                rooms 
+              WHERE 
+                rooms.archived_at IS NULL 
+                AND rooms.capacity >= sized_meetings.headcount 
+                AND rooms.capacity < sized_meetings.capacity 
+                AND NOT EXISTS (
+                  SELECT 
+                    1 
+                  FROM 
+                    meetings 
+                  WHERE 
+                    meetings.occupied_room_id = rooms.id 
+                    AND meetings.started_at < sized_meetings.ended_at 
+                    AND meetings.ended_at > sized_meetings.started_at
+                )
+            ), 
+            '[]'
+          ) AS suggested_rooms 
+        FROM 
+          sized_meetings 
+        WHERE 
+          sized_meetings.oversized 
+        ORDER BY 
+          sized_meetings.started_at"
+    );
+
+    let rooms_query = sqlx::query_as::<_, RoomSizing>(&rooms_sql)
+        .bind(query.from)
+        .bind(query.to)
+        .fetch_all(traced(&db))
+        .await;
+
+    let bookers_query = sqlx::query_as::<_, BookerSizing>(&bookers_sql)
+        .bind(query.from)
+        .bind(query.to)
+        .fetch_all(traced(&db))
+        .await;
+
+    let meetings_query = sqlx::query_as::<_, OversizedMeeting>(&meetings_sql)
+        .bind(query.from)
+        .bind(query.to)
+        .fetch_all(traced(&db))
+        .await;
+
+    match (rooms_query, bookers_query, meetings_query) {
+        (Ok(rooms), Ok(bookers), Ok(oversized_meetings)) => {
+            let resp = json!({
+                "from": query.from,
+                "to": query.to,
+                "rooms": rooms,
+                "bookers": bookers,
+                "oversized_meetings": oversized_meetings,
+            });
+
+            Ok(warp::reply::json(&resp))
+        }
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            tracing::error!(error = %e, "Failed to compute right-sizing");
+
+            Err(warp::reject::custom(InternalServerError))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use sqlx::{postgres::PgPoolOptions, Row};
+
+    use super::*;
+    use crate::migrations;
+
+    /// Needs a Postgres database, run with `TEST_DB_STRING=postgres://... cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn opens_rooms_in_their_own_timezone() {
+        let db_string = std::env::var("TEST_DB_STRING").expect("TEST_DB_STRING is not set");
+        let db = PgPoolOptions::new().connect(&db_string).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+
+        // Nothing is committed, so other tests never see the room
+        let mut tx = db.begin().await.unwrap();
+
+        let room_id: uuid::Uuid = sqlx::query_scalar(
+            "INSERT INTO rooms (name, room_id, link, timezone) 
+            VALUES ('Kolkata analytics', 'kolkata-analytics', 'https://zoom.us/j/1', 'Asia/Kolkata') 
+            RETURNING id",
+        )
+        .fetch_one(&mut tx)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO room_opening_hours (room_id, weekday, opens_at, closes_at) 
+            VALUES ($1, 0, '09:00', '17:00')",
+        )
+        .bind(room_id)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        // Weekdays come from the room's dates, whatever the session's timezone is
+        sqlx::query("SET LOCAL TIME ZONE 'America/Los_Angeles'")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+
+        let sql = format!(
+            "WITH {MEETINGS}, {UTILIZATION}SELECT 
+              lower(open_periods.period) AS opens_at, 
+              upper(open_periods.period) AS closes_at, 
+              bookable.bookable_hours 
+            FROM 
+              open_periods 
+              JOIN bookable ON bookable.room_id = open_periods.room_id 
+            WHERE 
+              open_periods.room_id = $3"
+        );
+        let day = |time| {
+            format!("2024-01-01T{time}Z")
+                .parse::<DateTime<Utc>>()
+                .unwrap()
+        };
+        let periods = sqlx::query(&sql)
+            .bind(day("00:00:00"))
+            .bind(day("12:00:00"))
+            .bind(room_id)
+            .fetch_all(&mut tx)
+            .await
+            .unwrap();
+
+        // Monday 09:00 to 17:00 in Kolkata is 03:30 to 11:30 UTC
+        assert_eq!(periods.len(), 1);
+        assert_eq!(
+            periods[0].get::<DateTime<Utc>, _>("opens_at"),
+            day("03:30:00")
+        );
+        assert_eq!(
+            periods[0].get::<DateTime<Utc>, _>("closes_at"),
+            day("11:30:00")
+        );
+        assert_eq!(periods[0].get::<f64, _>("bookable_hours"), 8.0);
+    }
+}
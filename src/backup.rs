@@ -0,0 +1,826 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use serde_json::json;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    errors::{BackupConflictError, InternalServerError, InvalidBackupError, InvalidTimezoneError},
+    models::{
+        Backup, BackupOccupancy, BackupOccupancyHistory, BackupOpeningHours, BackupRoom,
+        BackupRoomTag, Holiday, MaintenanceWindow, RestoreQuery, RestoreStrategy,
+    },
+    schedule::is_valid_timezone,
+    telemetry::traced,
+    DBPool,
+};
+
+/// Bumped whenever the layout of the backup document changes
+const BACKUP_VERSION: u32 = 1;
+
+pub async fn fetch_backup(db: DBPool) -> Result<impl warp::Reply, warp::Rejection> {
+    // One snapshot for every table, so the backup is consistent
+    let mut tx = match db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to start backup transaction");
+
+            return Err(warp::reject::custom(InternalServerError));
+        }
+    };
+
+    if let Err(e) = sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+        .execute(traced(&mut tx))
+        .await
+    {
+        tracing::error!(error = %e, "Failed to set backup isolation level");
+
+        return Err(warp::reject::custom(InternalServerError));
+    }
+
+    match read_backup(&mut tx).await {
+        Ok(backup) => Ok(warp::reply::json(&backup)),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to read backup");
+
+            Err(warp::reject::custom(InternalServerError))
+        }
+    }
+}
+
+pub async fn restore_backup(
+    query: RestoreQuery,
+    backup: Backup,
+    db: DBPool,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let strategy = query.strategy.unwrap_or(RestoreStrategy::Replace);
+
+    check_backup(&backup)?;
+
+    let mut tx = match db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to start restore transaction");
+
+            return Err(warp::reject::custom(InternalServerError));
+        }
+    };
+
+    let restore_result = match strategy {
+        RestoreStrategy::Replace => match clear_tables(&mut tx).await {
+            Ok(()) => write_backup(&mut tx, &backup).await,
+            Err(e) => Err(e),
+        },
+        RestoreStrategy::Merge => merge_backup(&mut tx, &backup).await,
+    };
+
+    if let Err(e) = restore_result {
+        return Err(restore_rejection(e));
+    }
+
+    if let Err(e) = tx.commit().await {
+        return Err(restore_rejection(e));
+    }
+
+    let resp = json!({
+        "strategy": strategy,
+        "restored": {
+            "rooms": backup.rooms.len(),
+            "occupancies": backup.occupancies.len(),
+            "occupancy_history": backup.occupancy_history.len(),
+            "maintenance_windows": backup.maintenance_windows.len(),
+            "opening_hours": backup.opening_hours.len(),
+            "holidays": backup.holidays.len(),
+            "room_tags": backup.room_tags.len(),
+        }
+    });
+
+    Ok(warp::reply::json(&resp))
+}
+
+/// Turns down backups of another version, with unknown timezones or with rows of rooms which
+/// aren't in the backup, before anything is written.
+fn check_backup(backup: &Backup) -> Result<(), warp::Rejection> {
+    if backup.version != BACKUP_VERSION {
+        return Err(warp::reject::custom(InvalidBackupError));
+    }
+
+    let has_valid_timezones = backup
+        .rooms
+        .iter()
+        .all(|room| is_valid_timezone(Some(&room.timezone)));
+
+    if !has_valid_timezones {
+        return Err(warp::reject::custom(InvalidTimezoneError));
+    }
+
+    let room_ids: HashSet<Uuid> = backup.rooms.iter().map(|room| room.id).collect();
+
+    let mut referenced_room_ids = backup
+        .occupancies
+        .iter()
+        .map(|occupancy| occupancy.occupied_room_id)
+        .chain(
+            backup
+                .occupancy_history
+                .iter()
+                .map(|meeting| meeting.occupied_room_id),
+        )
+        .chain(
+            backup
+                .maintenance_windows
+                .iter()
+                .map(|window| window.room_id),
+        )
+        .chain(backup.opening_hours.iter().map(|hours| hours.room_id))
+        .chain(backup.room_tags.iter().map(|room_tag| room_tag.room_id));
+
+    if !referenced_room_ids.all(|room_id| room_ids.contains(&room_id)) {
+        return Err(warp::reject::custom(InvalidBackupError));
+    }
+
+    Ok(())
+}
+
+/// Backups which don't fit the schema or clash with existing rows are the caller's fault,
+/// anything else is ours.
+fn restore_rejection(err: sqlx::Error) -> warp::Rejection {
+    let code = match &err {
+        sqlx::Error::Database(db_err) => db_err.code().map(|code| code.to_string()),
+        _ => None,
+    };
+
+    match code.as_deref() {
+        Some("23505") => warp::reject::custom(BackupConflictError),
+        Some(code) if code.starts_with("22") || code.starts_with("23") => {
+            warp::reject::custom(InvalidBackupError)
+        }
+        _ => {
+            tracing::error!(error = %err, "Failed to restore backup");
+
+            warp::reject::custom(InternalServerError)
+        }
+    }
+}
+
+async fn read_backup(tx: &mut Transaction<'_, Postgres>) -> Result<Backup, sqlx::Error> {
+    let rooms = sqlx::query_as::<_, BackupRoom>(
+        "SELECT 
+          id, 
+          name, 
+          room_id, 
+          capacity, 
+          time_limit :: text AS time_limit, 
+          link, 
+          comments, 
+          timezone, 
+          location, 
+          archived_at 
+        FROM 
+          rooms 
+        ORDER BY 
+          name",
+    )
+    .fetch_all(traced(&mut *tx))
+    .await?;
+
+    let occupancies = sqlx::query_as::<_, BackupOccupancy>("SELECT * FROM occupancies ORDER BY id")
+        .fetch_all(traced(&mut *tx))
+        .await?;
+
+    let occupancy_history =
+        sqlx::query_as::<_, BackupOccupancyHistory>("SELECT * FROM occupancy_history ORDER BY id")
+            .fetch_all(traced(&mut *tx))
+            .await?;
+
+    let maintenance_windows =
+        sqlx::query_as::<_, MaintenanceWindow>("SELECT * FROM maintenance_windows ORDER BY id")
+            .fetch_all(traced(&mut *tx))
+            .await?;
+
+    let opening_hours = sqlx::query_as::<_, BackupOpeningHours>(
+        "SELECT * FROM room_opening_hours ORDER BY room_id, weekday, opens_at",
+    )
+    .fetch_all(traced(&mut *tx))
+    .await?;
+
+    let holidays = sqlx::query_as::<_, Holiday>("SELECT * FROM holidays ORDER BY id")
+        .fetch_all(traced(&mut *tx))
+        .await?;
+
+    let room_tags =
+        sqlx::query_as::<_, BackupRoomTag>("SELECT * FROM room_tags ORDER BY room_id, tag")
+            .fetch_all(traced(&mut *tx))
+            .await?;
+
+    Ok(Backup {
+        version: BACKUP_VERSION,
+        created_at: Utc::now(),
+        rooms,
+        occupancies,
+        occupancy_history,
+        maintenance_windows,
+        opening_hours,
+        holidays,
+        room_tags,
+    })
+}
+
+async fn clear_tables(tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "TRUNCATE rooms, occupancies, occupancy_history, maintenance_windows, room_opening_hours, holidays, room_tags",
+    )
+    .execute(traced(&mut *tx))
+    .await?;
+
+    Ok(())
+}
+
+/// Writes every row of the backup with its own ids, into tables which were emptied first.
+async fn write_backup(
+    tx: &mut Transaction<'_, Postgres>,
+    backup: &Backup,
+) -> Result<(), sqlx::Error> {
+    for room in &backup.rooms {
+        sqlx::query(
+            "INSERT INTO rooms(
+              id, name, room_id, capacity, time_limit, 
+              link, comments, timezone, location, 
+              archived_at
+            ) 
+            VALUES 
+              (
+                $1, $2, $3, $4, $5 :: interval, $6, $7, 
+                $8, $9, $10
+              )",
+        )
+        .bind(room.id)
+        .bind(&room.name)
+        .bind(&room.room_id)
+        .bind(room.capacity)
+        .bind(&room.time_limit)
+        .bind(&room.link)
+        .bind(&room.comments)
+        .bind(&room.timezone)
+        .bind(&room.location)
+        .bind(room.archived_at)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for occupancy in &backup.occupancies {
+        sqlx::query(
+            "INSERT INTO occupancies(
+              id, occupied_room_id, occupied_until, 
+              meeting_title, comments, started_at, 
+              checked_in, headcount, booked_by
+            ) 
+            VALUES 
+              ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(occupancy.id)
+        .bind(occupancy.occupied_room_id)
+        .bind(occupancy.occupied_until)
+        .bind(&occupancy.meeting_title)
+        .bind(&occupancy.comments)
+        .bind(occupancy.started_at)
+        .bind(occupancy.checked_in)
+        .bind(occupancy.headcount)
+        .bind(&occupancy.booked_by)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for meeting in &backup.occupancy_history {
+        sqlx::query(
+            "INSERT INTO occupancy_history(
+              id, occupied_room_id, started_at, ended_at, 
+              occupied_until, meeting_title, comments, 
+              no_show, headcount, booked_by
+            ) 
+            VALUES 
+              (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10
+              )",
+        )
+        .bind(meeting.id)
+        .bind(meeting.occupied_room_id)
+        .bind(meeting.started_at)
+        .bind(meeting.ended_at)
+        .bind(meeting.occupied_until)
+        .bind(&meeting.meeting_title)
+        .bind(&meeting.comments)
+        .bind(meeting.no_show)
+        .bind(meeting.headcount)
+        .bind(&meeting.booked_by)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for window in &backup.maintenance_windows {
+        sqlx::query(
+            "INSERT INTO maintenance_windows(
+              id, room_id, starts_at, ends_at, reason
+            ) 
+            VALUES 
+              ($1, $2, $3, $4, $5)",
+        )
+        .bind(window.id)
+        .bind(window.room_id)
+        .bind(window.starts_at)
+        .bind(window.ends_at)
+        .bind(&window.reason)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for hours in &backup.opening_hours {
+        sqlx::query(
+            "INSERT INTO room_opening_hours(room_id, weekday, opens_at, closes_at) 
+            VALUES 
+              ($1, $2, $3, $4)",
+        )
+        .bind(hours.room_id)
+        .bind(hours.weekday)
+        .bind(hours.opens_at)
+        .bind(hours.closes_at)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for holiday in &backup.holidays {
+        sqlx::query(
+            "INSERT INTO holidays(id, date, name, location) 
+            VALUES 
+              ($1, $2, $3, $4)",
+        )
+        .bind(holiday.id)
+        .bind(holiday.date)
+        .bind(&holiday.name)
+        .bind(&holiday.location)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for room_tag in &backup.room_tags {
+        sqlx::query(
+            "INSERT INTO room_tags(room_id, tag) 
+            VALUES 
+              ($1, $2) ON CONFLICT (room_id, tag) DO NOTHING",
+        )
+        .bind(room_tag.room_id)
+        .bind(&room_tag.tag)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    // Rows came with their own ids, move the sequences past them
+    for table in [
+        "occupancies",
+        "occupancy_history",
+        "maintenance_windows",
+        "holidays",
+    ] {
+        sqlx::query(&format!(
+            "SELECT setval('{table}_id_seq', COALESCE(MAX(id), 0) + 1, false) FROM {table}"
+        ))
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Writes the backup next to the rows already in the database. Rooms are matched on `room_id`
+/// and keep their local id, every other row is moved onto the matched room. Meetings,
+/// maintenance windows and holidays get fresh ids and are skipped when the same one is already
+/// there, and a room which is occupied keeps its current meeting.
+async fn merge_backup(
+    tx: &mut Transaction<'_, Postgres>,
+    backup: &Backup,
+) -> Result<(), sqlx::Error> {
+    let mut room_ids = HashMap::new();
+
+    for room in &backup.rooms {
+        let room_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO rooms(
+              id, name, room_id, capacity, time_limit, 
+              link, comments, timezone, location, 
+              archived_at
+            ) 
+            VALUES 
+              (
+                $1, $2, $3, $4, $5 :: interval, $6, $7, 
+                $8, $9, $10
+              ) ON CONFLICT (room_id) DO 
+            UPDATE 
+            SET 
+              name = EXCLUDED.name, 
+              capacity = EXCLUDED.capacity, 
+              time_limit = EXCLUDED.time_limit, 
+              link = EXCLUDED.link, 
+              comments = EXCLUDED.comments, 
+              timezone = EXCLUDED.timezone, 
+              location = EXCLUDED.location, 
+              archived_at = EXCLUDED.archived_at RETURNING id",
+        )
+        .bind(room.id)
+        .bind(&room.name)
+        .bind(&room.room_id)
+        .bind(room.capacity)
+        .bind(&room.time_limit)
+        .bind(&room.link)
+        .bind(&room.comments)
+        .bind(&room.timezone)
+        .bind(&room.location)
+        .bind(room.archived_at)
+        .fetch_one(traced(&mut *tx))
+        .await?;
+
+        room_ids.insert(room.id, room_id);
+    }
+
+    for occupancy in &backup.occupancies {
+        sqlx::query(
+            "INSERT INTO occupancies(
+              occupied_room_id, occupied_until, 
+              meeting_title, comments, started_at, 
+              checked_in, headcount, booked_by
+            ) 
+            VALUES 
+              ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (occupied_room_id) DO NOTHING",
+        )
+        .bind(room_ids[&occupancy.occupied_room_id])
+        .bind(occupancy.occupied_until)
+        .bind(&occupancy.meeting_title)
+        .bind(&occupancy.comments)
+        .bind(occupancy.started_at)
+        .bind(occupancy.checked_in)
+        .bind(occupancy.headcount)
+        .bind(&occupancy.booked_by)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for meeting in &backup.occupancy_history {
+        sqlx::query(
+            "INSERT INTO occupancy_history(
+              occupied_room_id, started_at, ended_at, 
+              occupied_until, meeting_title, comments, 
+              no_show, headcount, booked_by
+            ) 
+            SELECT 
+              $1, $2, $3, $4, $5, $6, $7, $8, $9 
+            WHERE 
+              NOT EXISTS (
+                SELECT 
+                  1 
+                FROM 
+                  occupancy_history 
+                WHERE 
+                  occupied_room_id = $1 
+                  AND started_at = $2
+              )",
+        )
+        .bind(room_ids[&meeting.occupied_room_id])
+        .bind(meeting.started_at)
+        .bind(meeting.ended_at)
+        .bind(meeting.occupied_until)
+        .bind(&meeting.meeting_title)
+        .bind(&meeting.comments)
+        .bind(meeting.no_show)
+        .bind(meeting.headcount)
+        .bind(&meeting.booked_by)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for window in &backup.maintenance_windows {
+        sqlx::query(
+            "INSERT INTO maintenance_windows(room_id, starts_at, ends_at, reason) 
+            SELECT 
+              $1, $2, $3, $4 
+            WHERE 
+              NOT EXISTS (
+                SELECT 
+                  1 
+                FROM 
+                  maintenance_windows 
+                WHERE 
+                  room_id = $1 
+                  AND starts_at = $2 
+                  AND ends_at = $3
+              )",
+        )
+        .bind(room_ids[&window.room_id])
+        .bind(window.starts_at)
+        .bind(window.ends_at)
+        .bind(&window.reason)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for hours in &backup.opening_hours {
+        sqlx::query(
+            "INSERT INTO room_opening_hours(room_id, weekday, opens_at, closes_at) 
+            VALUES 
+              ($1, $2, $3, $4) ON CONFLICT (room_id, weekday, opens_at) DO 
+            UPDATE 
+            SET 
+              closes_at = EXCLUDED.closes_at",
+        )
+        .bind(room_ids[&hours.room_id])
+        .bind(hours.weekday)
+        .bind(hours.opens_at)
+        .bind(hours.closes_at)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for holiday in &backup.holidays {
+        sqlx::query(
+            "INSERT INTO holidays(date, name, location) 
+            SELECT 
+              $1, $2, $3 
+            WHERE 
+              NOT EXISTS (
+                SELECT 
+                  1 
+                FROM 
+                  holidays 
+                WHERE 
+                  date = $1 
+                  AND name = $2 
+                  AND location IS NOT DISTINCT FROM $3
+              )",
+        )
+        .bind(holiday.date)
+        .bind(&holiday.name)
+        .bind(&holiday.location)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    for room_tag in &backup.room_tags {
+        sqlx::query(
+            "INSERT INTO room_tags(room_id, tag) 
+            VALUES 
+              ($1, $2) ON CONFLICT (room_id, tag) DO NOTHING",
+        )
+        .bind(room_ids[&room_tag.room_id])
+        .bind(&room_tag.tag)
+        .execute(traced(&mut *tx))
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::migrations;
+
+    const EVEREST: &str = "6d7c4a4e-2b8a-4c50-9d34-7f1c1a3e0b01";
+    const K2: &str = "6d7c4a4e-2b8a-4c50-9d34-7f1c1a3e0b02";
+
+    fn room(id: &str, name: &str, room_id: &str, capacity: i32) -> Value {
+        json!({
+            "id": id,
+            "name": name,
+            "room_id": room_id,
+            "capacity": capacity,
+            "time_limit": "01:30:00",
+            "link": format!("https://zoom.us/j/{room_id}"),
+            "comments": null,
+            "timezone": "Asia/Kolkata",
+            "location": "Pune",
+            "archived_at": null,
+        })
+    }
+
+    fn meeting(id: i32, room_id: &str, started_at: &str, ended_at: &str) -> Value {
+        json!({
+            "id": id,
+            "occupied_room_id": room_id,
+            "started_at": started_at,
+            "ended_at": ended_at,
+            "occupied_until": ended_at,
+            "meeting_title": "Standup",
+            "comments": null,
+            "no_show": false,
+            "headcount": 4,
+            "booked_by": "priya@example.com",
+        })
+    }
+
+    /// A backup of two rooms, with a row in every table
+    fn backup() -> Value {
+        json!({
+            "version": BACKUP_VERSION,
+            "created_at": "2024-03-01T12:00:00Z",
+            "rooms": [room(EVEREST, "Everest", "everest", 8), room(K2, "K2", "k2", 4)],
+            "occupancies": [{
+                "id": 1,
+                "occupied_room_id": EVEREST,
+                "occupied_until": "2024-03-01T13:00:00Z",
+                "meeting_title": "Planning",
+                "comments": null,
+                "started_at": "2024-03-01T11:00:00Z",
+                "checked_in": true,
+                "headcount": 6,
+                "booked_by": null,
+            }],
+            "occupancy_history": [meeting(1, K2, "2024-02-28T09:00:00Z", "2024-02-28T09:30:00Z")],
+            "maintenance_windows": [{
+                "id": 1,
+                "room_id": K2,
+                "starts_at": "2024-03-04T09:00:00Z",
+                "ends_at": "2024-03-04T12:00:00Z",
+                "reason": "New screen",
+            }],
+            "opening_hours": [{
+                "room_id": EVEREST,
+                "weekday": 0,
+                "opens_at": "09:00:00",
+                "closes_at": "17:00:00",
+            }],
+            "holidays": [{ "id": 1, "date": "2024-03-25", "name": "Holi", "location": "Pune" }],
+            "room_tags": [{ "room_id": EVEREST, "tag": "video" }],
+        })
+    }
+
+    fn parse(backup: Value) -> Result<Backup, serde_json::Error> {
+        serde_json::from_value(backup)
+    }
+
+    #[test]
+    fn reads_backups_without_room_tags() {
+        let mut document = backup();
+        document.as_object_mut().unwrap().remove("room_tags");
+
+        let backup = parse(document).unwrap();
+        assert!(backup.room_tags.is_empty());
+        assert!(check_backup(&backup).is_ok());
+    }
+
+    #[test]
+    fn rejects_backups_which_dont_fit_the_layout() {
+        let mut document = backup();
+        document.as_object_mut().unwrap().remove("rooms");
+        assert!(parse(document).is_err());
+
+        let mut document = backup();
+        document["occupancy_history"][0]["started_at"] = json!("yesterday");
+        assert!(parse(document).is_err());
+
+        let mut document = backup();
+        document["opening_hours"][0]["room_id"] = json!(42);
+        assert!(parse(document).is_err());
+    }
+
+    #[test]
+    fn rejects_backups_of_other_versions() {
+        let mut document = backup();
+        document["version"] = json!(BACKUP_VERSION + 1);
+
+        let rejection = check_backup(&parse(document).unwrap()).unwrap_err();
+        assert!(rejection.find::<InvalidBackupError>().is_some());
+    }
+
+    #[test]
+    fn rejects_backups_with_unknown_timezones() {
+        let mut document = backup();
+        document["rooms"][1]["timezone"] = json!("Mars/Olympus_Mons");
+
+        let rejection = check_backup(&parse(document).unwrap()).unwrap_err();
+        assert!(rejection.find::<InvalidTimezoneError>().is_some());
+    }
+
+    #[test]
+    fn rejects_rows_of_rooms_missing_from_the_backup() {
+        let unknown_room = "6d7c4a4e-2b8a-4c50-9d34-7f1c1a3e0b09";
+
+        for (table, column) in [
+            ("occupancies", "occupied_room_id"),
+            ("occupancy_history", "occupied_room_id"),
+            ("maintenance_windows", "room_id"),
+            ("opening_hours", "room_id"),
+            ("room_tags", "room_id"),
+        ] {
+            let mut document = backup();
+            document[table][0][column] = json!(unknown_room);
+
+            let rejection = check_backup(&parse(document).unwrap()).unwrap_err();
+            assert!(rejection.find::<InvalidBackupError>().is_some(), "{table}");
+        }
+    }
+
+    async fn restore(db: &DBPool, strategy: RestoreStrategy, backup: Value) {
+        let query = RestoreQuery {
+            strategy: Some(strategy),
+        };
+        let restored = restore_backup(query, parse(backup).unwrap(), db.clone()).await;
+        assert!(restored.is_ok());
+    }
+
+    /// The database as a backup document
+    async fn take_backup(db: &DBPool) -> Value {
+        let mut tx = db.begin().await.unwrap();
+
+        serde_json::to_value(read_backup(&mut tx).await.unwrap()).unwrap()
+    }
+
+    /// Backups are compared without the time they were taken
+    fn without_created_at(mut backup: Value) -> Value {
+        backup.as_object_mut().unwrap().remove("created_at");
+
+        backup
+    }
+
+    /// Needs a Postgres database which can be emptied, run with
+    /// `TEST_DB_STRING=postgres://... cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn restores_and_merges_backups_through_postgres() {
+        let db_string = std::env::var("TEST_DB_STRING").expect("TEST_DB_STRING is not set");
+        let db = PgPoolOptions::new().connect(&db_string).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+
+        // Replacing with a backup of the database leaves it as it was
+        restore(&db, RestoreStrategy::Replace, backup()).await;
+        let before = take_backup(&db).await;
+        restore(&db, RestoreStrategy::Replace, before.clone()).await;
+
+        let before = without_created_at(before);
+        assert_eq!(without_created_at(take_backup(&db).await), before);
+        assert_eq!(before, without_created_at(backup()));
+
+        // Another database, which shares the K2 room under another id and reuses every serial id
+        let k2 = "7b9a1f1e-55f4-4d1a-8c8e-000000000002";
+        let lhotse = "7b9a1f1e-55f4-4d1a-8c8e-000000000003";
+
+        let mut other = backup();
+        other["rooms"] = json!([
+            room(k2, "K2", "k2", 6),
+            room(lhotse, "Lhotse", "lhotse", 10)
+        ]);
+        other["occupancies"][0]["occupied_room_id"] = json!(lhotse);
+        other["occupancy_history"] = json!([
+            meeting(1, k2, "2024-02-29T09:00:00Z", "2024-02-29T10:00:00Z"),
+            meeting(2, k2, "2024-02-28T09:00:00Z", "2024-02-28T09:30:00Z"),
+        ]);
+        other["maintenance_windows"][0]["room_id"] = json!(lhotse);
+        other["opening_hours"][0]["room_id"] = json!(k2);
+        other["holidays"][0]["name"] = json!("Holi, second day");
+        other["room_tags"][0]["room_id"] = json!(lhotse);
+
+        restore(&db, RestoreStrategy::Merge, other.clone()).await;
+        let merged = without_created_at(take_backup(&db).await);
+
+        // K2 keeps its id and takes the other database's capacity, Everest is untouched
+        let rooms = merged["rooms"].as_array().unwrap();
+        assert_eq!(rooms.len(), 3);
+        assert_eq!(rooms[0], before["rooms"][0]);
+        assert_eq!(rooms[1]["id"], K2);
+        assert_eq!(rooms[1]["capacity"], 6);
+        assert_eq!(rooms[2]["id"], lhotse);
+
+        let room_ids = |table: &str, column: &str| -> Vec<Value> {
+            let rows = merged[table].as_array().unwrap();
+            rows.iter().map(|row| row[column].clone()).collect()
+        };
+
+        // Rows keep their own ids and the merged ones are added on the matched rooms, the
+        // meeting K2 already had isn't added twice
+        assert_eq!(
+            merged["occupancy_history"][0],
+            before["occupancy_history"][0]
+        );
+        assert_eq!(room_ids("occupancy_history", "id"), [json!(1), json!(2)]);
+        assert_eq!(
+            merged["occupancy_history"][1]["started_at"],
+            "2024-02-29T09:00:00Z"
+        );
+        assert_eq!(room_ids("occupancy_history", "occupied_room_id"), [K2, K2]);
+        assert_eq!(
+            room_ids("occupancies", "occupied_room_id"),
+            [EVEREST, lhotse]
+        );
+        assert_eq!(room_ids("maintenance_windows", "room_id"), [K2, lhotse]);
+        assert_eq!(room_ids("holidays", "id"), [json!(1), json!(2)]);
+        assert_eq!(room_ids("room_tags", "room_id"), [EVEREST, lhotse]);
+
+        let opening_hours = room_ids("opening_hours", "room_id");
+        assert_eq!(opening_hours.len(), 2);
+        assert!(opening_hours.contains(&json!(K2)));
+
+        // Merging the same backup again changes nothing
+        restore(&db, RestoreStrategy::Merge, other).await;
+        assert_eq!(without_created_at(take_backup(&db).await), merged);
+    }
+}
@@ -0,0 +1,432 @@
+use std::sync::Arc;
+
+use warp::{
+    http::{
+        header::{self, HeaderName, HeaderValue},
+        Method, StatusCode, Uri,
+    },
+    reply::Response,
+    Filter, Rejection, Reply,
+};
+
+use crate::{config::Config, errors::CorsForbiddenError};
+
+/// Response headers browsers let scripts read on cross-origin responses
+const EXPOSED_HEADERS: &str = "x-request-id, idempotent-replayed";
+
+/// An entry of `cors_origins`: any origin, one origin, or any subdomain of a domain
+#[derive(Debug, Clone, PartialEq)]
+pub enum OriginPattern {
+    Any,
+    Exact(Origin),
+    Subdomains(Origin),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    scheme: String,
+    host: String,
+    port: Option<u16>,
+}
+
+impl Origin {
+    /// `scheme://host[:port]`, as sent in the `Origin` header
+    fn parse(origin: &str) -> Option<Origin> {
+        let uri = origin.parse::<Uri>().ok()?;
+        let scheme = uri.scheme_str()?.to_ascii_lowercase();
+        let host = uri.host()?.to_ascii_lowercase();
+
+        let is_origin = matches!(scheme.as_str(), "http" | "https")
+            && !host.is_empty()
+            && uri.path() == "/"
+            && !origin.ends_with('/')
+            && uri.query().is_none();
+
+        is_origin.then(|| Origin {
+            scheme,
+            host,
+            port: uri.port_u16(),
+        })
+    }
+}
+
+impl OriginPattern {
+    /// `*`, `https://rooms.example.com` or `https://*.example.com`, optionally with a port
+    pub fn parse(pattern: &str) -> Option<OriginPattern> {
+        if pattern == "*" {
+            return Some(OriginPattern::Any);
+        }
+
+        match pattern.split_once("://*.") {
+            Some((scheme, domain)) => {
+                let origin = Origin::parse(&format!("{}://{}", scheme, domain))?;
+
+                // A bare top-level domain would match far more than meant
+                origin
+                    .host
+                    .contains('.')
+                    .then_some(OriginPattern::Subdomains(origin))
+            }
+            None => Origin::parse(pattern).map(OriginPattern::Exact),
+        }
+    }
+
+    fn matches(&self, origin: &Origin) -> bool {
+        match self {
+            OriginPattern::Any => true,
+            OriginPattern::Exact(allowed) => allowed == origin,
+            OriginPattern::Subdomains(domain) => {
+                domain.scheme == origin.scheme
+                    && domain.port == origin.port
+                    && matches!(
+                        origin.host.strip_suffix(&domain.host),
+                        Some(subdomain) if subdomain.len() > 1 && subdomain.ends_with('.')
+                    )
+            }
+        }
+    }
+}
+
+/// Which browsers may call the API from other origins, and how
+#[derive(Debug)]
+pub struct CorsPolicy {
+    origins: Vec<OriginPattern>,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    allow_credentials: bool,
+    max_age_secs: Option<u64>,
+}
+
+impl CorsPolicy {
+    /// The policy set by the `cors_*` settings, which have been validated already
+    pub fn from_config(config: &Config) -> CorsPolicy {
+        CorsPolicy {
+            origins: config
+                .cors_origins
+                .iter()
+                .filter_map(|origin| OriginPattern::parse(origin))
+                .collect(),
+            methods: config
+                .cors_methods
+                .iter()
+                .filter_map(|method| method.to_ascii_uppercase().parse().ok())
+                .collect(),
+            headers: config
+                .cors_headers
+                .iter()
+                .filter_map(|header| header.parse().ok())
+                .collect(),
+            allow_credentials: config.cors_allow_credentials,
+            max_age_secs: config.cors_max_age_secs,
+        }
+    }
+
+    fn allows_origin(&self, origin: &str) -> bool {
+        if self.origins.contains(&OriginPattern::Any) {
+            return true;
+        }
+
+        matches!(
+            Origin::parse(origin),
+            Some(origin) if self.origins.iter().any(|pattern| pattern.matches(&origin))
+        )
+    }
+
+    fn allows_headers(&self, requested: &str) -> bool {
+        requested
+            .split(',')
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .all(|header| {
+                self.headers
+                    .iter()
+                    .any(|allowed| allowed.as_str().eq_ignore_ascii_case(header))
+            })
+    }
+
+    /// The headers every response to an allowed origin carries. The origin is echoed rather
+    /// than answered with `*`, which browsers refuse along with credentials.
+    fn add_headers(&self, resp: &mut Response, origin: &str) {
+        let headers = resp.headers_mut();
+
+        if let Ok(origin) = HeaderValue::from_str(origin) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        }
+
+        if self.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+    }
+
+    fn preflight_response(&self, origin: &str) -> Response {
+        let mut resp = StatusCode::NO_CONTENT.into_response();
+
+        self.add_headers(&mut resp, origin);
+
+        let headers = resp.headers_mut();
+        let methods = join(self.methods.iter().map(Method::as_str));
+        let allowed_headers = join(self.headers.iter().map(HeaderName::as_str));
+
+        if let Ok(methods) = HeaderValue::from_str(&methods) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+
+        if let Ok(allowed_headers) = HeaderValue::from_str(&allowed_headers) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers);
+        }
+
+        if let Some(max_age) = self.max_age_secs {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+        }
+
+        resp
+    }
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    values.collect::<Vec<_>>().join(", ")
+}
+
+/// Answers preflight requests and adds the CORS headers to the responses of `routes`.
+/// Requests from origins the policy doesn't allow are rejected with `CorsForbiddenError`,
+/// requests without an `Origin` header are passed through as they are.
+pub fn apply<F, T>(
+    policy: Arc<CorsPolicy>,
+    routes: F,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (T,), Error = std::convert::Infallible> + Clone + Send + Sync + 'static,
+    T: Reply,
+{
+    let with_policy = warp::any().map(move || policy.clone());
+
+    let preflight = warp::options()
+        .and(warp::header::<String>("origin"))
+        .and(warp::header::<String>("access-control-request-method"))
+        .and(warp::header::optional::<String>(
+            "access-control-request-headers",
+        ))
+        .and(with_policy.clone())
+        .and_then(answer_preflight);
+
+    let request = warp::method()
+        .and(warp::header::optional::<String>("origin"))
+        .and(warp::header::optional::<String>(
+            "access-control-request-method",
+        ))
+        .and(with_policy)
+        .and_then(check_origin)
+        .and(routes)
+        .map(|origin: Option<(String, Arc<CorsPolicy>)>, reply: T| {
+            let mut resp = reply.into_response();
+
+            if let Some((origin, policy)) = origin {
+                policy.add_headers(&mut resp, &origin);
+
+                resp.headers_mut().insert(
+                    header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                    HeaderValue::from_static(EXPOSED_HEADERS),
+                );
+            }
+
+            resp
+        });
+
+    preflight.or(request).unify()
+}
+
+async fn answer_preflight(
+    origin: String,
+    method: String,
+    headers: Option<String>,
+    policy: Arc<CorsPolicy>,
+) -> Result<Response, Rejection> {
+    let allows_method = policy
+        .methods
+        .iter()
+        .any(|allowed| allowed.as_str().eq_ignore_ascii_case(&method));
+
+    if !policy.allows_origin(&origin)
+        || !allows_method
+        || !policy.allows_headers(headers.as_deref().unwrap_or_default())
+    {
+        return Err(warp::reject::custom(CorsForbiddenError));
+    }
+
+    Ok(policy.preflight_response(&origin))
+}
+
+/// The origin to answer with, if the request came from a browser on another origin
+async fn check_origin(
+    method: Method,
+    origin: Option<String>,
+    preflight_method: Option<String>,
+    policy: Arc<CorsPolicy>,
+) -> Result<Option<(String, Arc<CorsPolicy>)>, Rejection> {
+    let Some(origin) = origin else {
+        return Ok(None);
+    };
+
+    // Preflights are answered by `answer_preflight` alone, even when it turns them down
+    if method == Method::OPTIONS && preflight_method.is_some() {
+        return Err(warp::reject());
+    }
+
+    if !policy.allows_origin(&origin) {
+        return Err(warp::reject::custom(CorsForbiddenError));
+    }
+
+    Ok(Some((origin, policy)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use warp::{http::StatusCode, Filter};
+
+    use super::{apply, CorsPolicy, OriginPattern};
+    use crate::{config::ConfigArgs, errors::handle_rejection};
+
+    fn policy(origins: &[&str]) -> Arc<CorsPolicy> {
+        let config = ConfigArgs {
+            db_string: Some(String::from("postgres://db/zoomer")),
+            cors_origins: Some(origins.iter().map(|origin| origin.to_string()).collect()),
+            cors_allow_credentials: Some(!origins.contains(&"*")),
+            cors_max_age_secs: Some(600),
+            ..Default::default()
+        }
+        .resolve()
+        .unwrap();
+
+        Arc::new(CorsPolicy::from_config(&config))
+    }
+
+    fn preflight(origin: &str, method: &str, headers: &str) -> warp::test::RequestBuilder {
+        warp::test::request()
+            .method("OPTIONS")
+            .path("/rooms")
+            .header("origin", origin)
+            .header("access-control-request-method", method)
+            .header("access-control-request-headers", headers)
+    }
+
+    #[test]
+    fn parses_origin_patterns() {
+        assert_eq!(OriginPattern::parse("*"), Some(OriginPattern::Any));
+        assert!(matches!(
+            OriginPattern::parse("https://*.example.com"),
+            Some(OriginPattern::Subdomains(_))
+        ));
+
+        for invalid in [
+            "https://*.com",
+            "example.com",
+            "https://example.com/",
+            "ftp://a.b",
+        ] {
+            assert_eq!(OriginPattern::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_preflights_for_allowed_origins() {
+        let routes = apply(
+            policy(&["https://rooms.example.com", "https://*.example.org"]),
+            warp::any().map(|| "ok"),
+        )
+        .recover(handle_rejection);
+
+        for origin in ["https://rooms.example.com", "https://a.b.example.org"] {
+            let resp = preflight(origin, "POST", "content-type, x-request-id")
+                .reply(&routes)
+                .await;
+            let headers = resp.headers();
+
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert_eq!(headers["access-control-allow-origin"], origin);
+            assert_eq!(headers["access-control-allow-credentials"], "true");
+            assert_eq!(headers["access-control-allow-methods"], "GET, POST");
+            assert_eq!(headers["access-control-max-age"], "600");
+            assert_eq!(headers["vary"], "origin");
+        }
+
+        for origin in [
+            "https://example.org",
+            "http://rooms.example.org",
+            "https://rooms.example.org:8443",
+            "https://evil-example.org",
+            "https://rooms.example.com.evil.com",
+        ] {
+            let resp = preflight(origin, "POST", "content-type")
+                .reply(&routes)
+                .await;
+
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{}", origin);
+            assert!(!resp.headers().contains_key("access-control-allow-origin"));
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_preflights_for_other_methods_and_headers() {
+        let routes = apply(policy(&["*"]), warp::any().map(|| "ok")).recover(handle_rejection);
+
+        let resp = preflight("https://rooms.example.com", "DELETE", "content-type")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = preflight("https://rooms.example.com", "GET", "x-custom")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = preflight("https://rooms.example.com", "GET", "Accept")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert!(!resp
+            .headers()
+            .contains_key("access-control-allow-credentials"));
+    }
+
+    #[tokio::test]
+    async fn adds_headers_to_requests_from_allowed_origins() {
+        let routes = apply(
+            policy(&["https://rooms.example.com"]),
+            warp::any().map(|| "ok"),
+        )
+        .recover(handle_rejection);
+
+        let resp = warp::test::request()
+            .path("/rooms")
+            .header("origin", "https://rooms.example.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()["access-control-allow-origin"],
+            "https://rooms.example.com"
+        );
+        assert_eq!(
+            resp.headers()["access-control-expose-headers"],
+            "x-request-id, idempotent-replayed"
+        );
+
+        let resp = warp::test::request()
+            .path("/rooms")
+            .header("origin", "https://evil.example.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = warp::test::request().path("/rooms").reply(&routes).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!resp.headers().contains_key("access-control-allow-origin"));
+    }
+}
@@ -1,8 +1,10 @@
 use serde::Serialize;
 use std::convert::Infallible;
-use warp::http::StatusCode;
+use warp::http::{header::RETRY_AFTER, HeaderValue, StatusCode};
 use warp::{Rejection, Reply};
 
+use crate::store::StoreError;
+
 #[derive(Serialize)]
 struct ErrorMessage {
     success: bool,
@@ -39,7 +41,149 @@ pub struct RoomNotOccupiedError;
 
 impl warp::reject::Reject for RoomNotOccupiedError {}
 
-pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
+#[derive(Debug)]
+pub struct RoomAlreadyCheckedInError;
+
+impl warp::reject::Reject for RoomAlreadyCheckedInError {}
+
+#[derive(Debug)]
+pub struct RoomTooSmallError;
+
+impl warp::reject::Reject for RoomTooSmallError {}
+
+#[derive(Debug)]
+pub struct InvalidHeadcountError;
+
+impl warp::reject::Reject for InvalidHeadcountError {}
+
+#[derive(Debug)]
+pub struct RoomUnderMaintenanceError;
+
+impl warp::reject::Reject for RoomUnderMaintenanceError {}
+
+#[derive(Debug)]
+pub struct MaintenanceWindowNotFoundError;
+
+impl warp::reject::Reject for MaintenanceWindowNotFoundError {}
+
+#[derive(Debug)]
+pub struct InvalidMaintenanceWindowError;
+
+impl warp::reject::Reject for InvalidMaintenanceWindowError {}
+
+#[derive(Debug)]
+pub struct RoomClosedError;
+
+impl warp::reject::Reject for RoomClosedError {}
+
+#[derive(Debug)]
+pub struct InvalidTimezoneError;
+
+impl warp::reject::Reject for InvalidTimezoneError {}
+
+#[derive(Debug)]
+pub struct InvalidOpeningHoursError;
+
+impl warp::reject::Reject for InvalidOpeningHoursError {}
+
+#[derive(Debug)]
+pub struct HolidayError;
+
+impl warp::reject::Reject for HolidayError {}
+
+#[derive(Debug)]
+pub struct HolidayNotFoundError;
+
+impl warp::reject::Reject for HolidayNotFoundError {}
+
+#[derive(Debug)]
+pub struct InvalidDateRangeError;
+
+impl warp::reject::Reject for InvalidDateRangeError {}
+
+#[derive(Debug)]
+pub struct DateRangeTooLongError;
+
+impl warp::reject::Reject for DateRangeTooLongError {}
+
+#[derive(Debug)]
+pub struct InvalidTagsError;
+
+impl warp::reject::Reject for InvalidTagsError {}
+
+#[derive(Debug)]
+pub struct InvalidImportFileError;
+
+impl warp::reject::Reject for InvalidImportFileError {}
+
+#[derive(Debug)]
+pub struct UnauthorizedError;
+
+impl warp::reject::Reject for UnauthorizedError {}
+
+#[derive(Debug)]
+pub struct InvalidBackupError;
+
+impl warp::reject::Reject for InvalidBackupError {}
+
+#[derive(Debug)]
+pub struct BackupConflictError;
+
+impl warp::reject::Reject for BackupConflictError {}
+
+#[derive(Debug)]
+pub struct CorsForbiddenError;
+
+impl warp::reject::Reject for CorsForbiddenError {}
+
+#[derive(Debug)]
+pub struct InvalidIdempotencyKeyError;
+
+impl warp::reject::Reject for InvalidIdempotencyKeyError {}
+
+#[derive(Debug)]
+pub struct IdempotencyKeyInUseError;
+
+impl warp::reject::Reject for IdempotencyKeyInUseError {}
+
+#[derive(Debug)]
+pub struct IdempotencyKeyMismatchError;
+
+impl warp::reject::Reject for IdempotencyKeyMismatchError {}
+
+#[derive(Debug)]
+pub struct RateLimitedError {
+    pub retry_after_secs: u64,
+}
+
+impl warp::reject::Reject for RateLimitedError {}
+
+impl From<StoreError> for Rejection {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::RoomNotFound => warp::reject::custom(RoomNotFoundError),
+            StoreError::RoomWithNameExists => warp::reject::custom(RoomWithNameExistsError),
+            StoreError::RoomWithIdExists => warp::reject::custom(RoomWithIdExistsError),
+            StoreError::RoomOccupied => warp::reject::custom(RoomOccupiedError),
+            StoreError::RoomNotOccupied => warp::reject::custom(RoomNotOccupiedError),
+            StoreError::RoomAlreadyCheckedIn => warp::reject::custom(RoomAlreadyCheckedInError),
+            StoreError::RoomTooSmall => warp::reject::custom(RoomTooSmallError),
+            StoreError::InvalidHeadcount => warp::reject::custom(InvalidHeadcountError),
+            StoreError::RoomUnderMaintenance => warp::reject::custom(RoomUnderMaintenanceError),
+            StoreError::RoomClosed => warp::reject::custom(RoomClosedError),
+            StoreError::Holiday => warp::reject::custom(HolidayError),
+            StoreError::MaintenanceWindowNotFound => {
+                warp::reject::custom(MaintenanceWindowNotFoundError)
+            }
+            StoreError::HolidayNotFound => warp::reject::custom(HolidayNotFoundError),
+            StoreError::Internal => warp::reject::custom(InternalServerError),
+        }
+    }
+}
+
+/// Status code and message a rejection is answered with, `None` for rejections nothing here
+/// knows about
+pub fn rejection_status(err: &Rejection) -> Option<(StatusCode, &'static str)> {
     let code;
     let message;
 
@@ -61,27 +205,122 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
     } else if let Some(RoomNotOccupiedError) = err.find() {
         code = StatusCode::BAD_REQUEST;
         message = "Room is not occupied, check selected room";
+    } else if let Some(RoomAlreadyCheckedInError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Meeting in this room is already checked in";
+    } else if let Some(RoomTooSmallError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Room is too small for this meeting, check selected room";
+    } else if let Some(InvalidHeadcountError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Invalid headcount, a meeting needs at least 1 attendee";
+    } else if let Some(RoomUnderMaintenanceError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Room is under maintenance during this time, check selected room";
+    } else if let Some(MaintenanceWindowNotFoundError) = err.find() {
+        code = StatusCode::NOT_FOUND;
+        message = "Requested maintenance window does not exist";
+    } else if let Some(InvalidMaintenanceWindowError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Maintenance window must end after it starts";
+    } else if let Some(RoomClosedError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Room is not bookable at this time, check room's opening hours";
+    } else if let Some(InvalidTimezoneError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Invalid timezone, use an IANA timezone name like Europe/Berlin";
+    } else if let Some(InvalidOpeningHoursError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Invalid opening hours, weekday must be 0-6 and rooms must close after they open";
+    } else if let Some(HolidayError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Rooms can't be booked on a holiday, check the holiday calendar";
+    } else if let Some(HolidayNotFoundError) = err.find() {
+        code = StatusCode::NOT_FOUND;
+        message = "Requested holiday does not exist";
+    } else if let Some(InvalidDateRangeError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Invalid date range, from must be before to";
+    } else if let Some(DateRangeTooLongError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Date range too long, send at most 366 days";
+    } else if let Some(InvalidTagsError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Invalid tags, send tags of 1 to 64 characters without surrounding spaces";
+    } else if let Some(InvalidImportFileError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Invalid import file, send a CSV file or a JSON array of rooms";
+    } else if let Some(UnauthorizedError) = err.find() {
+        code = StatusCode::UNAUTHORIZED;
+        message = "Missing or invalid admin token";
+    } else if let Some(InvalidBackupError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Invalid backup, check its version and that every row fits the schema";
+    } else if let Some(BackupConflictError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Backup conflicts with existing data, check room names and room ids or use the replace strategy";
+    } else if let Some(CorsForbiddenError) = err.find() {
+        code = StatusCode::FORBIDDEN;
+        message = "Origin, method or headers not allowed by the CORS policy";
+    } else if let Some(InvalidIdempotencyKeyError) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Invalid Idempotency-Key, send 1 to 255 visible ASCII characters";
+    } else if let Some(IdempotencyKeyInUseError) = err.find() {
+        code = StatusCode::CONFLICT;
+        message = "A request with this Idempotency-Key is still being answered, retry later";
+    } else if let Some(IdempotencyKeyMismatchError) = err.find() {
+        code = StatusCode::UNPROCESSABLE_ENTITY;
+        message = "Idempotency-Key was already used for a different request";
+    } else if let Some(RateLimitedError { .. }) = err.find() {
+        code = StatusCode::TOO_MANY_REQUESTS;
+        message = "Too many requests, retry after the seconds in the Retry-After header";
     } else if let Some(InternalServerError) = err.find() {
         code = StatusCode::INTERNAL_SERVER_ERROR;
         message = "Internal server error";
-    } else if let Some(_) = err.find::<warp::filters::body::BodyDeserializeError>() {
+    } else if err
+        .find::<warp::filters::body::BodyDeserializeError>()
+        .is_some()
+    {
         code = StatusCode::BAD_REQUEST;
         message = "Invalid request payload, check if all fields are sent/correct";
-    } else if let Some(_) = err.find::<warp::reject::MethodNotAllowed>() {
+    } else if err.find::<warp::reject::InvalidQuery>().is_some() {
+        code = StatusCode::BAD_REQUEST;
+        message = "Invalid query parameters, check if all parameters are sent/correct";
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        code = StatusCode::PAYLOAD_TOO_LARGE;
+        message = "Request payload is too large";
+    } else if err.find::<warp::reject::LengthRequired>().is_some() {
+        code = StatusCode::LENGTH_REQUIRED;
+        message = "Content-Length header is required";
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
         code = StatusCode::NOT_FOUND;
         message = "Not found";
     } else {
+        return None;
+    }
+
+    Some((code, message))
+}
+
+pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
+    let (code, message) = rejection_status(&err).unwrap_or_else(|| {
         // catching all other errors
-        eprintln!("unhandled rejection: {:?}", err);
+        tracing::error!(rejection = ?err, "Unhandled rejection");
 
-        code = StatusCode::INTERNAL_SERVER_ERROR;
-        message = "Internal server error";
-    }
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+    });
 
     let json = warp::reply::json(&ErrorMessage {
         success: false,
         message: message.into(),
     });
 
-    Ok(warp::reply::with_status(json, code))
+    let mut resp = warp::reply::with_status(json, code).into_response();
+
+    if let Some(RateLimitedError { retry_after_secs }) = err.find() {
+        resp.headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(*retry_after_secs));
+    }
+
+    Ok(resp)
 }
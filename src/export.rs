@@ -0,0 +1,339 @@
+use futures::{stream, StreamExt};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use warp::{
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    hyper::Body,
+    reply::Response,
+};
+
+use crate::{
+    errors::InvalidDateRangeError,
+    models::{ExportFormat, HistoryExportQuery, OccupancyRecord, Room, RoomsExportQuery},
+    telemetry::traced,
+    DBPool,
+};
+
+type ExportError = Box<dyn std::error::Error + Send + Sync>;
+
+/// CSV header of the rooms export, the fields of `Room`
+const ROOM_COLUMNS: &[&str] = &[
+    "id",
+    "name",
+    "room_id",
+    "capacity",
+    "time_limit",
+    "link",
+    "comments",
+    "timezone",
+    "location",
+];
+
+/// CSV header of the occupancy history export, the fields of `OccupancyRecord`
+const OCCUPANCY_RECORD_COLUMNS: &[&str] = &[
+    "id",
+    "occupied_room_id",
+    "room_name",
+    "location",
+    "started_at",
+    "ended_at",
+    "occupied_until",
+    "meeting_title",
+    "comments",
+    "no_show",
+    "headcount",
+    "booked_by",
+];
+
+pub async fn export_rooms(
+    query: RoomsExportQuery,
+    db: DBPool,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (tx, rx) = mpsc::channel(64);
+
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as::<_, Room>(
+            "SELECT 
+              id, 
+              name, 
+              room_id, 
+              capacity, 
+              link, 
+              TO_CHAR(time_limit, 'HH24:MI:SS') as time_limit, 
+              comments, 
+              timezone, 
+              location 
+            FROM 
+              rooms 
+            WHERE 
+              archived_at IS NULL 
+            ORDER BY 
+              name",
+        )
+        .fetch(traced(&db));
+
+        while let Some(row) = rows.next().await {
+            // The client went away, stop reading
+            if tx.send(row).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(export_reply(
+        query.format.unwrap_or(ExportFormat::Csv),
+        "rooms",
+        ROOM_COLUMNS,
+        rx,
+    ))
+}
+
+pub async fn export_history(
+    query: HistoryExportQuery,
+    db: DBPool,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return Err(warp::reject::custom(InvalidDateRangeError));
+        }
+    }
+
+    let (tx, rx) = mpsc::channel(64);
+
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as::<_, OccupancyRecord>(
+            "SELECT 
+              occupancy_history.id, 
+              occupancy_history.occupied_room_id, 
+              rooms.name AS room_name, 
+              rooms.location, 
+              occupancy_history.started_at, 
+              occupancy_history.ended_at, 
+              occupancy_history.occupied_until, 
+              occupancy_history.meeting_title, 
+              occupancy_history.comments, 
+              occupancy_history.no_show, 
+              occupancy_history.headcount, 
+              occupancy_history.booked_by 
+            FROM 
+              occupancy_history 
+              INNER JOIN rooms ON rooms.id = occupancy_history.occupied_room_id 
+            WHERE 
+              ( 
+                $1 :: timestamptz IS NULL 
+                OR occupancy_history.started_at >= $1 
+              ) 
+              AND ( 
+                $2 :: timestamptz IS NULL 
+                OR occupancy_history.started_at < $2 
+              ) 
+              AND ( 
+                $3 :: uuid IS NULL 
+                OR occupancy_history.occupied_room_id = $3 
+              ) 
+            ORDER BY 
+              occupancy_history.started_at, 
+              occupancy_history.id",
+        )
+        .bind(query.from)
+        .bind(query.to)
+        .bind(query.room_id)
+        .fetch(traced(&db));
+
+        while let Some(row) = rows.next().await {
+            // The client went away, stop reading
+            if tx.send(row).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(export_reply(
+        query.format.unwrap_or(ExportFormat::Csv),
+        "occupancy-history",
+        OCCUPANCY_RECORD_COLUMNS,
+        rx,
+    ))
+}
+
+/// Encodes the rows as they come out of the database into a chunked response. A database error
+/// halfway through aborts the response, so a cut off export can't pass for a complete one.
+fn export_reply<T>(
+    format: ExportFormat,
+    name: &str,
+    columns: &'static [&'static str],
+    rows: mpsc::Receiver<Result<T, sqlx::Error>>,
+) -> Response
+where
+    T: Serialize + Send + 'static,
+{
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+
+    // The body is streamed after the handler returns, errors are still logged with its request
+    let span = tracing::Span::current();
+
+    // Written before the first row, so an export without rows still has its header
+    let header = match format {
+        ExportFormat::Csv => Some(encode_header(columns)),
+        ExportFormat::Ndjson => None,
+    };
+
+    let chunks = stream::unfold(rows, move |mut rows| {
+        let span = span.clone();
+
+        async move {
+            let chunk = match rows.recv().await? {
+                Ok(row) => encode_row(format, &row),
+                Err(e) => {
+                    span.in_scope(|| tracing::error!(error = %e, "Failed to stream export"));
+                    Err(e.into())
+                }
+            };
+
+            Some((chunk, rows))
+        }
+    });
+
+    let mut resp = Response::new(Body::wrap_stream(stream::iter(header).chain(chunks)));
+    let headers = resp.headers_mut();
+
+    headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+    headers.insert(
+        CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{name}.{extension}\"")
+            .parse()
+            .unwrap(),
+    );
+
+    resp
+}
+
+fn encode_header(columns: &[&str]) -> Result<Vec<u8>, ExportError> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    writer.write_record(columns)?;
+
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+fn encode_row<T: Serialize>(format: ExportFormat, row: &T) -> Result<Vec<u8>, ExportError> {
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(vec![]);
+
+            writer.serialize(row)?;
+
+            Ok(writer.into_inner().map_err(|e| e.into_error())?)
+        }
+        ExportFormat::Ndjson => {
+            let mut line = serde_json::to_vec(row)?;
+            line.push(b'\n');
+
+            Ok(line)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+
+    use super::{export_reply, OCCUPANCY_RECORD_COLUMNS, ROOM_COLUMNS};
+    use crate::models::{ExportFormat, OccupancyRecord, Room};
+
+    fn room() -> Room {
+        Room {
+            id: Uuid::nil(),
+            name: String::from("Everest"),
+            room_id: String::from("everest"),
+            capacity: 8,
+            time_limit: String::from("01:30:00"),
+            link: String::from("https://zoom.us/j/1"),
+            comments: String::new(),
+            timezone: String::from("Europe/Berlin"),
+            location: None,
+        }
+    }
+
+    async fn export<T>(
+        format: ExportFormat,
+        columns: &'static [&'static str],
+        rows: Vec<T>,
+    ) -> String
+    where
+        T: serde::Serialize + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(64);
+
+        for row in rows {
+            assert!(tx.send(Ok(row)).await.is_ok());
+        }
+        drop(tx);
+
+        let resp = export_reply(format, "export", columns, rx);
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn writes_the_csv_header_without_rows() {
+        let csv = export::<Room>(ExportFormat::Csv, ROOM_COLUMNS, vec![]).await;
+        assert_eq!(
+            csv,
+            "id,name,room_id,capacity,time_limit,link,comments,timezone,location\n"
+        );
+
+        let ndjson = export::<Room>(ExportFormat::Ndjson, ROOM_COLUMNS, vec![]).await;
+        assert_eq!(ndjson, "");
+    }
+
+    #[tokio::test]
+    async fn writes_the_csv_header_once_before_the_rows() {
+        let csv = export(ExportFormat::Csv, ROOM_COLUMNS, vec![room(), room()]).await;
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("id,name,"));
+        assert!(lines[1].starts_with("00000000-0000-0000-0000-000000000000,Everest,"));
+        assert_eq!(lines[1], lines[2]);
+    }
+
+    /// The header the csv crate derives from the fields of `row`
+    fn serialized_header<T: serde::Serialize>(row: &T) -> String {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(row).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        csv.lines().next().unwrap().to_string()
+    }
+
+    #[test]
+    fn columns_match_the_exported_fields() {
+        assert_eq!(serialized_header(&room()), ROOM_COLUMNS.join(","));
+        assert_eq!(
+            serialized_header(&OccupancyRecord {
+                id: 1,
+                occupied_room_id: Uuid::nil(),
+                room_name: String::from("Everest"),
+                location: None,
+                started_at: Utc::now(),
+                ended_at: Utc::now(),
+                occupied_until: Utc::now(),
+                meeting_title: String::from("Standup"),
+                comments: None,
+                no_show: false,
+                headcount: Some(4),
+                booked_by: None,
+            }),
+            OCCUPANCY_RECORD_COLUMNS.join(",")
+        );
+    }
+}
@@ -1,123 +1,161 @@
-use std::time::Duration;
+use std::collections::BTreeSet;
 
 use serde_json::json;
 use uuid::Uuid;
 
 use crate::{
     errors::{
-        InternalServerError, RoomNotFoundError, RoomNotOccupiedError, RoomOccupiedError,
-        RoomWithIdExistsError, RoomWithNameExistsError,
+        InternalServerError, InvalidHeadcountError, InvalidMaintenanceWindowError,
+        InvalidOpeningHoursError, InvalidTagsError, InvalidTimezoneError, RoomNotFoundError,
     },
-    models::{ActiveRoom, NewOccupancy, NewRoom, Occupancy, Room},
+    metrics,
+    models::{
+        NewHoliday, NewMaintenanceWindow, NewOccupancy, NewRoom, OccupancyTransfer, RoomNoShows,
+        RoomSchedule, RoomTags,
+    },
+    schedule::is_valid_timezone,
+    store::{is_valid_headcount, Store},
+    telemetry::traced,
     DBPool,
 };
 
-pub async fn fetch_current_state(db: DBPool) -> Result<impl warp::Reply, warp::Rejection> {
-    let available_rooms_query = sqlx::query_as::<_, Room>(
-        "SELECT 
-          rooms.id, 
-          rooms.name, 
-          rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments 
-        FROM 
-          rooms 
-          LEFT OUTER JOIN occupancies ON rooms.id = occupancies.occupied_room_id 
-        WHERE 
-          occupancies.id IS NULL",
-    )
-    .fetch_all(&db)
-    .await;
+pub async fn fetch_current_state(store: Store) -> Result<impl warp::Reply, warp::Rejection> {
+    let state = store.current_state().await?;
 
-    let active_rooms_query = sqlx::query_as::<_, ActiveRoom>(
-        "SELECT 
-          rooms.id, 
-          rooms.name, 
-          rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments,
-          TRUE as is_active,
-          occupancies.occupied_until,
-          occupancies.meeting_title,
-          occupancies.comments as meeting_comments
-        FROM 
-          rooms 
-          JOIN occupancies ON rooms.id = occupancies.occupied_room_id",
-    )
-    .fetch_all(&db)
-    .await;
+    let resp = json!({
+        "available_rooms": state.available_rooms,
+        "active_rooms": state.active_rooms,
+        "unavailable_rooms": state.unavailable_rooms
+    });
 
-    match (available_rooms_query, active_rooms_query) {
-        (Ok(available_rooms), Ok(active_rooms)) => {
-            let resp = json!({
-                "available_rooms": available_rooms,
-                "active_rooms": active_rooms
-            });
+    Ok(warp::reply::json(&resp))
+}
 
-            Ok(warp::reply::json(&resp))
-        }
-        _ => Err(warp::reject::custom(InternalServerError)),
-    }
+pub async fn fetch_available_rooms(store: Store) -> Result<impl warp::Reply, warp::Rejection> {
+    let rooms = store.available_rooms().await?;
+
+    let resp = json!({
+        "rooms": rooms,
+    });
+
+    Ok(warp::reply::json(&resp))
 }
 
-pub async fn fetch_available_rooms(db: DBPool) -> Result<impl warp::Reply, warp::Rejection> {
-    let query_result = sqlx::query_as::<_, Room>(
-        "SELECT 
-          rooms.id, 
-          rooms.name, 
-          rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments 
-        FROM 
-          rooms 
-          LEFT OUTER JOIN occupancies ON rooms.id = occupancies.occupied_room_id 
-        WHERE 
-          occupancies.id IS NULL",
-    )
-    .fetch_all(&db)
-    .await;
+pub async fn fetch_active_rooms(store: Store) -> Result<impl warp::Reply, warp::Rejection> {
+    let rooms = store.active_rooms().await?;
 
-    match query_result {
-        Ok(rooms) => {
-            let resp = json!({
-                "rooms": rooms,
-            });
+    let resp = json!({
+        "rooms": rooms,
+    });
 
-            Ok(warp::reply::json(&resp))
-        }
-        Err(e) => {
-            dbg!(e);
+    Ok(warp::reply::json(&resp))
+}
 
-            Err(warp::reject::custom(InternalServerError))
-        }
+pub async fn fetch_single_room(
+    room_id: Uuid,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let room = store.room(room_id).await?;
+
+    let resp = json!({ "room_details": room });
+
+    Ok(warp::reply::json(&resp))
+}
+
+pub async fn fetch_occupancies(store: Store) -> Result<impl warp::Reply, warp::Rejection> {
+    let occupancies = store.occupancies().await?;
+
+    let resp = json!({
+        "occupancies": occupancies,
+    });
+
+    Ok(warp::reply::json(&resp))
+}
+
+pub async fn create_new_room(
+    room_data: NewRoom,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !is_valid_timezone(room_data.timezone.as_deref()) {
+        return Err(warp::reject::custom(InvalidTimezoneError));
     }
+
+    let room = store.create_room(room_data).await?;
+
+    let resp = json!({ "room_details": room });
+
+    Ok(warp::reply::json(&resp))
+}
+
+pub async fn handle_occupy_room(
+    occupy_data: NewOccupancy,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !is_valid_headcount(occupy_data.headcount) {
+        return Err(warp::reject::custom(InvalidHeadcountError));
+    }
+
+    let active_room = store.occupy_room(occupy_data).await?;
+
+    metrics::OCCUPANCIES_CREATED.inc();
+
+    let resp = json!({
+        "room_details": active_room,
+    });
+
+    Ok(warp::reply::json(&resp))
+}
+
+pub async fn handle_freeup_room(
+    room_id: Uuid,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.free_room(room_id).await?;
+
+    metrics::OCCUPANCIES_FREED
+        .with_label_values(&["freed_up"])
+        .inc();
+
+    let res = json!({
+        "success": true,
+        "message": "Room freed up successfully",
+    });
+
+    Ok(warp::reply::json(&res))
 }
 
-pub async fn fetch_active_rooms(db: DBPool) -> Result<impl warp::Reply, warp::Rejection> {
-    let query_result = sqlx::query_as::<_, ActiveRoom>(
+pub async fn handle_checkin_room(
+    room_id: Uuid,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.check_in_room(room_id).await?;
+
+    let res = json!({
+        "success": true,
+        "message": "Checked in successfully",
+    });
+
+    Ok(warp::reply::json(&res))
+}
+
+pub async fn fetch_no_shows(db: DBPool) -> Result<impl warp::Reply, warp::Rejection> {
+    let query_result = sqlx::query_as::<_, RoomNoShows>(
         "SELECT 
           rooms.id, 
           rooms.name, 
           rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments,
-          TRUE as is_active,
-          occupancies.occupied_until,
-          occupancies.meeting_title,
-          occupancies.comments as meeting_comments
+          COUNT(occupancy_history.id) as no_show_count 
         FROM 
           rooms 
-          JOIN occupancies ON rooms.id = occupancies.occupied_room_id",
+          LEFT OUTER JOIN occupancy_history ON rooms.id = occupancy_history.occupied_room_id 
+          AND occupancy_history.no_show 
+        GROUP BY 
+          rooms.id 
+        ORDER BY 
+          no_show_count DESC, 
+          rooms.name",
     )
-    .fetch_all(&db)
+    .fetch_all(traced(&db))
     .await;
 
     match query_result {
@@ -129,448 +167,288 @@ pub async fn fetch_active_rooms(db: DBPool) -> Result<impl warp::Reply, warp::Re
             Ok(warp::reply::json(&resp))
         }
         Err(e) => {
-            dbg!(e);
+            tracing::error!(error = %e, "Failed to fetch no-shows");
 
             Err(warp::reject::custom(InternalServerError))
         }
     }
 }
 
-pub async fn fetch_single_room(
+pub async fn update_room_details(
     room_id: Uuid,
-    db: DBPool,
+    room_details: NewRoom,
+    store: Store,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let query_result = sqlx::query_as::<_, Room>(
-        "SELECT 
-          rooms.id, 
-          rooms.name, 
-          rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments 
-        FROM 
-          rooms 
-        WHERE 
-          id = $1",
-    )
-    .bind(room_id)
-    .fetch_one(&db)
-    .await;
+    if !is_valid_timezone(room_details.timezone.as_deref()) {
+        return Err(warp::reject::custom(InvalidTimezoneError));
+    }
 
-    match query_result {
-        Ok(room) => {
-            let resp = json!({ "room_details": room });
+    let updated_room = store.update_room(room_id, room_details).await?;
 
-            Ok(warp::reply::json(&resp))
-        }
-        Err(sqlx::Error::RowNotFound) => Err(warp::reject::custom(RoomNotFoundError)),
-        Err(e) => {
-            dbg!(e);
+    let resp = json!({ "updated_room": updated_room });
 
-            Err(warp::reject::custom(InternalServerError))
-        }
-    }
+    Ok(warp::reply::json(&resp))
 }
 
-pub async fn fetch_occupancies(db: DBPool) -> Result<impl warp::Reply, warp::Rejection> {
-    let query_result = sqlx::query_as::<_, Occupancy>("SELECT * FROM occupancies")
-        .fetch_all(&db)
-        .await;
+pub async fn handle_transfer_room(
+    transfer_data: OccupancyTransfer,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !is_valid_headcount(transfer_data.headcount) {
+        return Err(warp::reject::custom(InvalidHeadcountError));
+    }
 
-    match query_result {
-        Ok(occupancies) => {
-            let resp = json!({
-                "occupancies": occupancies,
-            });
+    let active_room = store.transfer_room(transfer_data).await?;
 
-            Ok(warp::reply::json(&resp))
-        }
-        Err(e) => {
-            dbg!(e);
+    // The meeting leaves one room and starts over in another
+    metrics::OCCUPANCIES_FREED
+        .with_label_values(&["transferred"])
+        .inc();
+    metrics::OCCUPANCIES_CREATED.inc();
 
-            Err(warp::reject::custom(InternalServerError))
-        }
-    }
+    let resp = json!({
+        "room_details": active_room,
+    });
+
+    Ok(warp::reply::json(&resp))
 }
 
-pub async fn create_new_room(
-    room_data: NewRoom,
-    db: DBPool,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    let check_query = sqlx::query_as::<_, Room>(
-        "SELECT 
-          rooms.id, 
-          rooms.name, 
-          rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments 
-        FROM 
-          rooms 
-        WHERE 
-          name = $1
-          OR room_id = $2",
-    )
-    .bind(&room_data.name)
-    .bind(&room_data.room_id)
-    .fetch_all(&db)
-    .await;
+pub async fn fetch_maintenance_windows(store: Store) -> Result<impl warp::Reply, warp::Rejection> {
+    let maintenance_windows = store.maintenance_windows().await?;
 
-    match check_query {
-        Ok(res) => {
-            if res.len() > 0 {
-                let found_room = &res[0];
-
-                if found_room.name == room_data.name {
-                    return Err(warp::reject::custom(RoomWithNameExistsError));
-                } else if found_room.room_id == room_data.room_id {
-                    return Err(warp::reject::custom(RoomWithIdExistsError));
-                }
-            }
-        }
-        Err(err) => {
-            dbg!(err);
+    let resp = json!({
+        "maintenance_windows": maintenance_windows,
+    });
 
-            return Err(warp::reject::custom(InternalServerError));
-        }
-    }
+    Ok(warp::reply::json(&resp))
+}
 
-    let interval = Duration::from_secs(room_data.time_limit * 60);
-
-    let query_result = sqlx::query_as::<_, Room>(
-        "INSERT INTO rooms(
-          name, room_id, capacity, time_limit, 
-          link, comments
-        ) 
-        VALUES 
-          ($1, $2, $3, $4 :: interval, $5, $6) RETURNING id, 
-          name, 
-          room_id, 
-          capacity, 
-          TO_CHAR(time_limit, 'HH24:MI:SS') as time_limit, 
-          link, 
-          comments",
-    )
-    .bind(room_data.name)
-    .bind(room_data.room_id)
-    .bind(room_data.capacity)
-    .bind(interval)
-    .bind(room_data.link)
-    .bind(room_data.comments)
-    .fetch_all(&db)
-    .await;
+pub async fn create_maintenance_window(
+    window_data: NewMaintenanceWindow,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if window_data.ends_at <= window_data.starts_at {
+        return Err(warp::reject::custom(InvalidMaintenanceWindowError));
+    }
 
-    match query_result {
-        Ok(room) => {
-            let resp = json!({ "room_details": room[0] });
+    let maintenance_window = store.create_maintenance_window(window_data).await?;
 
-            Ok(warp::reply::json(&resp))
-        }
-        Err(e) => {
-            dbg!(e);
+    let resp = json!({ "maintenance_window": maintenance_window });
 
-            Err(warp::reject::custom(InternalServerError))
-        }
-    }
+    Ok(warp::reply::json(&resp))
 }
 
-pub async fn handle_occupy_room(
-    occupy_data: NewOccupancy,
-    db: DBPool,
+pub async fn remove_maintenance_window(
+    window_id: i32,
+    store: Store,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let check_query = sqlx::query(
-        "SELECT 
-          occupied_room_id 
-        FROM 
-          occupancies
-        WHERE 
-          occupied_room_id = $1",
-    )
-    .bind(&occupy_data.occupied_room_id)
-    .fetch_one(&db)
-    .await;
+    store.remove_maintenance_window(window_id).await?;
 
-    match check_query {
-        Ok(_) => return Err(warp::reject::custom(RoomOccupiedError)),
-        Err(sqlx::Error::RowNotFound) => (),
-        Err(err) => {
-            dbg!(err);
+    let res = json!({
+        "success": true,
+        "message": "Maintenance window removed successfully",
+    });
 
-            return Err(warp::reject::custom(InternalServerError));
-        }
-    }
+    Ok(warp::reply::json(&res))
+}
 
-    let room_check_query = sqlx::query_as::<_, Room>(
-        "SELECT 
-          rooms.id, 
-          rooms.name, 
-          rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments 
-        FROM 
-          rooms 
-        WHERE 
-          id = $1",
-    )
-    .bind(&occupy_data.occupied_room_id)
-    .fetch_one(&db)
-    .await;
+pub async fn fetch_opening_hours(
+    room_id: Uuid,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (timezone, opening_hours) = store.opening_hours(room_id).await?;
 
-    let room_data = match room_check_query {
-        Ok(res) => res,
-        Err(sqlx::Error::RowNotFound) => return Err(warp::reject::custom(RoomNotFoundError)),
-        Err(err) => {
-            dbg!(err);
+    let resp = json!({
+        "timezone": timezone,
+        "opening_hours": opening_hours,
+    });
 
-            return Err(warp::reject::custom(InternalServerError));
-        }
-    };
+    Ok(warp::reply::json(&resp))
+}
 
-    let insert_query = sqlx::query_as::<_, Occupancy>(
-        "INSERT INTO occupancies(
-          occupied_room_id, occupied_until, 
-          meeting_title, comments
-        ) 
-        VALUES 
-          ($1, $2, $3, $4) RETURNING id, 
-          occupied_room_id, 
-          occupied_until, 
-          meeting_title, 
-          comments",
-    )
-    .bind(&occupy_data.occupied_room_id)
-    .bind(&occupy_data.occupied_until)
-    .bind(&occupy_data.meeting_title)
-    .bind(&occupy_data.comments)
-    .fetch_all(&db)
-    .await;
+pub async fn update_opening_hours(
+    room_id: Uuid,
+    schedule: RoomSchedule,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let is_valid_schedule = schedule
+        .opening_hours
+        .iter()
+        .all(|hours| (0..7).contains(&hours.weekday) && hours.opens_at < hours.closes_at);
 
-    match insert_query {
-        Ok(occupancy) => {
-            let active_room = ActiveRoom {
-                id: room_data.id,
-                name: room_data.name,
-                room_id: room_data.room_id,
-                capacity: room_data.capacity,
-                time_limit: room_data.time_limit,
-                link: room_data.link,
-                comments: room_data.comments,
-                is_active: true,
-                occupied_until: occupancy[0].occupied_until.clone(),
-                meeting_title: occupancy[0].meeting_title.clone(),
-                meeting_comments: occupancy[0].comments.clone(),
-            };
+    if !is_valid_schedule {
+        return Err(warp::reject::custom(InvalidOpeningHoursError));
+    }
 
-            let resp = json!({
-                "room_details": active_room,
-            });
-            Ok(warp::reply::json(&resp))
-        }
-        Err(e) => {
-            dbg!(e);
+    store
+        .set_opening_hours(room_id, &schedule.opening_hours)
+        .await?;
 
-            Err(warp::reject::custom(InternalServerError))
-        }
-    }
+    let resp = json!({ "opening_hours": schedule.opening_hours });
+
+    Ok(warp::reply::json(&resp))
 }
 
-pub async fn handle_freeup_room(
+pub async fn fetch_room_tags(
     room_id: Uuid,
     db: DBPool,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let room_check_query = sqlx::query_as::<_, Room>(
-        "SELECT 
-          rooms.id, 
-          rooms.name, 
-          rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments 
-        FROM 
-          rooms 
-        WHERE 
-          id = $1",
-    )
-    .bind(&room_id)
-    .fetch_one(&db)
-    .await;
+    let room_check_query = sqlx::query("SELECT id FROM rooms WHERE id = $1")
+        .bind(room_id)
+        .fetch_one(traced(&db))
+        .await;
 
     match room_check_query {
         Ok(_) => (),
         Err(sqlx::Error::RowNotFound) => return Err(warp::reject::custom(RoomNotFoundError)),
         Err(e) => {
-            dbg!(e);
+            tracing::error!(error = %e, "Failed to look up the room of tags");
 
             return Err(warp::reject::custom(InternalServerError));
         }
     }
 
-    let check_query = sqlx::query(
-        "SELECT 
-          occupied_room_id 
-        FROM 
-          occupancies
-        WHERE 
-          occupied_room_id = $1",
+    let query_result = sqlx::query_scalar::<_, String>(
+        "SELECT tag FROM room_tags WHERE room_id = $1 ORDER BY tag",
     )
-    .bind(&room_id)
-    .fetch_one(&db)
+    .bind(room_id)
+    .fetch_all(traced(&db))
     .await;
 
-    match check_query {
-        Ok(_) => (),
-        Err(sqlx::Error::RowNotFound) => return Err(warp::reject::custom(RoomNotOccupiedError)),
-        Err(e) => {
-            dbg!(e);
-
-            return Err(warp::reject::custom(InternalServerError));
-        }
-    }
-
-    let remove_query = sqlx::query("DELETE FROM occupancies WHERE occupied_room_id = $1")
-        .bind(&room_id)
-        .execute(&db)
-        .await;
-
-    match remove_query {
-        Ok(_num_rows) => {
-            let res = json!({
-                "success": true,
-                "message": "Room freed up successfully",
-            });
+    match query_result {
+        Ok(tags) => {
+            let resp = json!({ "tags": tags });
 
-            Ok(warp::reply::json(&res))
+            Ok(warp::reply::json(&resp))
         }
         Err(e) => {
-            dbg!(e);
+            tracing::error!(error = %e, "Failed to fetch room tags");
 
             Err(warp::reject::custom(InternalServerError))
         }
     }
 }
 
-pub async fn update_room_details(
+pub async fn update_room_tags(
     room_id: Uuid,
-    room_details: NewRoom,
+    room_tags: RoomTags,
     db: DBPool,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let check_query = sqlx::query_as::<_, Room>(
-        "SELECT 
-          rooms.id, 
-          rooms.name, 
-          rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments 
-        FROM 
-          rooms 
-        WHERE 
-          id = $1",
-    )
-    .bind(&room_id)
-    .fetch_one(&db)
-    .await;
+    let is_valid_tags = room_tags
+        .tags
+        .iter()
+        .all(|tag| !tag.is_empty() && tag.trim() == tag && tag.chars().count() <= 64);
 
-    match check_query {
-        Ok(_) => (),
-        Err(sqlx::Error::RowNotFound) => return Err(warp::reject::custom(RoomNotFoundError)),
+    if !is_valid_tags {
+        return Err(warp::reject::custom(InvalidTagsError));
+    }
+
+    let tags: BTreeSet<String> = room_tags.tags.into_iter().collect();
+
+    let mut tx = match db.begin().await {
+        Ok(tx) => tx,
         Err(e) => {
-            dbg!(e);
+            tracing::error!(error = %e, "Failed to start room tags transaction");
 
             return Err(warp::reject::custom(InternalServerError));
         }
-    }
+    };
 
-    let check_new_values = sqlx::query_as::<_, Room>(
-        "SELECT 
-          rooms.id, 
-          rooms.name, 
-          rooms.room_id, 
-          rooms.capacity, 
-          rooms.link, 
-          TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
-          rooms.comments 
-        FROM 
-          rooms 
-        WHERE 
-          (
-            name = $1 
-            OR room_id = $2
-          ) 
-          AND (id != $3)",
-    )
-    .bind(&room_details.name)
-    .bind(&room_details.room_id)
-    .bind(&room_id)
-    .fetch_all(&db)
-    .await;
+    let room_check_query = sqlx::query("SELECT id FROM rooms WHERE id = $1 FOR UPDATE")
+        .bind(room_id)
+        .fetch_one(traced(&mut tx))
+        .await;
 
-    match check_new_values {
-        Ok(found_rooms) => {
-            if found_rooms.len() > 0 {
-                let found_room = &found_rooms[0];
-
-                if found_room.name == room_details.name {
-                    return Err(warp::reject::custom(RoomWithNameExistsError));
-                } else if found_room.room_id == room_details.room_id {
-                    return Err(warp::reject::custom(RoomWithIdExistsError));
-                }
-            }
-        }
+    match room_check_query {
+        Ok(_) => (),
+        Err(sqlx::Error::RowNotFound) => return Err(warp::reject::custom(RoomNotFoundError)),
         Err(e) => {
-            dbg!(e);
+            tracing::error!(error = %e, "Failed to lock the room of tags");
 
             return Err(warp::reject::custom(InternalServerError));
         }
     }
 
-    let interval = Duration::from_secs(room_details.time_limit * 60);
+    let remove_query = sqlx::query("DELETE FROM room_tags WHERE room_id = $1")
+        .bind(room_id)
+        .execute(traced(&mut tx))
+        .await;
 
-    let update_query = sqlx::query_as::<_, Room>(
-        "UPDATE 
-          rooms 
-        SET 
-          name = $1, 
-          room_id = $2, 
-          capacity = $3, 
-          link = $4, 
-          time_limit = $5, 
-          comments = $6 
-        WHERE 
-          id = $7
-        RETURNING id, 
-          name, 
-          room_id, 
-          capacity, 
-          TO_CHAR(time_limit, 'HH24:MI:SS') as time_limit, 
-          link, 
-          comments",
-    )
-    .bind(&room_details.name)
-    .bind(&room_details.room_id)
-    .bind(&room_details.capacity)
-    .bind(&room_details.link)
-    .bind(&interval)
-    .bind(&room_details.comments)
-    .bind(&room_id)
-    .fetch_one(&db)
-    .await;
+    if let Err(e) = remove_query {
+        tracing::error!(error = %e, "Failed to clear room tags");
 
-    match update_query {
-        Ok(updated_room) => {
-            let resp = json!({ "updated_room": updated_room });
+        return Err(warp::reject::custom(InternalServerError));
+    }
+
+    for tag in &tags {
+        let insert_query = sqlx::query("INSERT INTO room_tags(room_id, tag) VALUES ($1, $2)")
+            .bind(room_id)
+            .bind(tag)
+            .execute(traced(&mut tx))
+            .await;
+
+        if let Err(e) = insert_query {
+            tracing::error!(error = %e, "Failed to insert room tag");
 
-            Ok(warp::reply::json(&resp))
-        }
-        Err(e) => {
-            dbg!(e);
             return Err(warp::reject::custom(InternalServerError));
         }
     }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!(error = %e, "Failed to commit room tags");
+
+        return Err(warp::reject::custom(InternalServerError));
+    }
+
+    let resp = json!({ "tags": tags });
+
+    Ok(warp::reply::json(&resp))
+}
+
+pub async fn fetch_holidays(store: Store) -> Result<impl warp::Reply, warp::Rejection> {
+    let holidays = store.holidays().await?;
+
+    let resp = json!({
+        "holidays": holidays,
+    });
+
+    Ok(warp::reply::json(&resp))
+}
+
+pub async fn create_holiday(
+    holiday_data: NewHoliday,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let holiday = store.create_holiday(holiday_data).await?;
+
+    let resp = json!({ "holiday": holiday });
+
+    Ok(warp::reply::json(&resp))
+}
+
+pub async fn remove_holiday(
+    holiday_id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.remove_holiday(holiday_id).await?;
+
+    let res = json!({
+        "success": true,
+        "message": "Holiday removed successfully",
+    });
+
+    Ok(warp::reply::json(&res))
+}
+
+pub async fn fetch_holiday_collisions(
+    holiday_id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let occupancies = store.holiday_collisions(holiday_id).await?;
+
+    let resp = json!({
+        "occupancies": occupancies,
+    });
+
+    Ok(warp::reply::json(&resp))
 }
@@ -0,0 +1,75 @@
+use std::{sync::Arc, time::Instant};
+
+use serde_json::json;
+use warp::http::StatusCode;
+
+use crate::{config::Config, migrations, telemetry::traced, DBPool};
+
+/// The process is up and serving requests, nothing else is checked
+pub async fn check_health() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&json!({ "status": "ok" })))
+}
+
+/// Ready once the database answers within the readiness timeout and its schema matches this
+/// binary. Anything else is a 503, so load balancers stop sending traffic until it recovers.
+pub async fn check_readiness(
+    db: DBPool,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let started = Instant::now();
+    let timeout = config.readiness_timeout();
+
+    let check = tokio::time::timeout(timeout, async {
+        sqlx::query("SELECT 1").execute(traced(&db)).await?;
+
+        migrations::applied_version(&db).await
+    })
+    .await;
+
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let latest_version = migrations::latest_version();
+
+    let (database, applied_version) = match check {
+        Ok(Ok(applied_version)) => (
+            json!({ "reachable": true, "latency_ms": latency_ms }),
+            Some(applied_version),
+        ),
+        Ok(Err(e)) => (
+            json!({ "reachable": false, "latency_ms": latency_ms, "error": e.to_string() }),
+            None,
+        ),
+        Err(_) => (
+            json!({
+                "reachable": false,
+                "latency_ms": latency_ms,
+                "error": format!("No answer within {}s", timeout.as_secs()),
+            }),
+            None,
+        ),
+    };
+
+    let is_migrated = applied_version == Some(latest_version);
+
+    let (code, status) = if is_migrated {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    let resp = json!({
+        "status": status,
+        "database": database,
+        "pool": {
+            "size": db.size(),
+            "idle": db.num_idle(),
+            "max_size": config.db_max_connections,
+        },
+        "migrations": {
+            "applied_version": applied_version,
+            "latest_version": latest_version,
+            "up_to_date": is_migrated,
+        },
+    });
+
+    Ok(warp::reply::with_status(warp::reply::json(&resp), code))
+}
@@ -0,0 +1,727 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hyper::{body::Bytes, service::Service, Body, Request};
+use sha2::{Digest, Sha256};
+use tracing::Instrument;
+use warp::{
+    http::{
+        header::{self, HeaderMap, HeaderValue},
+        Method, Response as HttpResponse, StatusCode, Uri,
+    },
+    path::FullPath,
+    reject::{LengthRequired, Reject},
+    reply::Response,
+    Filter, Rejection, Reply,
+};
+
+use crate::{
+    config::Config,
+    errors::{
+        handle_rejection, IdempotencyKeyInUseError, IdempotencyKeyMismatchError,
+        InternalServerError, InvalidIdempotencyKeyError,
+    },
+    rate_limit::RateLimiter,
+    server::ClientAddr,
+    telemetry::traced,
+    DBPool,
+};
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// The largest body any route accepts, that of `/admin/restore`
+const MAX_BODY_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Set on responses replayed from an earlier request
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// How often expired keys are removed
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A key whose first request hasn't been answered in this long was left behind by a crashed
+/// server, and is given to the next request sent with it
+const ABANDONED_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// The response to the first request sent with a key
+#[derive(Clone, Debug)]
+struct StoredResponse {
+    status: u16,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+impl StoredResponse {
+    fn replay(self) -> Response {
+        let mut resp = HttpResponse::new(self.body.into());
+
+        *resp.status_mut() =
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        let headers = resp.headers_mut();
+
+        if let Some(content_type) = self
+            .content_type
+            .and_then(|content_type| HeaderValue::from_str(&content_type).ok())
+        {
+            headers.insert(header::CONTENT_TYPE, content_type);
+        }
+
+        headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+
+        resp
+    }
+}
+
+/// What to do with a request sent with a key
+#[derive(Debug)]
+enum Begin {
+    /// It's the first, answer it
+    Answer,
+    Replay(StoredResponse),
+    /// The first is still being answered
+    InUse,
+    /// The key was sent with a different request before
+    Mismatch,
+}
+
+struct MemoryKey {
+    request_hash: Vec<u8>,
+    response: Option<StoredResponse>,
+    created_at: Instant,
+}
+
+enum Keys {
+    Memory(Mutex<HashMap<String, MemoryKey>>),
+    /// In the `idempotency_keys` table, shared by every replica
+    Postgres(DBPool),
+}
+
+/// Idempotency keys seen in the last `idempotency_window_secs`, with the request each came
+/// with and the response it got
+pub struct IdempotencyKeys {
+    keys: Keys,
+    window: Duration,
+    pruned_at: Mutex<Instant>,
+}
+
+impl IdempotencyKeys {
+    /// Keys kept in `db` on Postgres, in memory otherwise
+    pub fn from_config(config: &Config, db: Option<DBPool>) -> IdempotencyKeys {
+        let keys = match db {
+            Some(db) => Keys::Postgres(db),
+            None => Keys::Memory(Mutex::new(HashMap::new())),
+        };
+
+        IdempotencyKeys {
+            keys,
+            window: config.idempotency_window(),
+            pruned_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Claims `key` for the request hashed to `request_hash`, unless an earlier request did
+    async fn begin(&self, key: &str, request_hash: &[u8]) -> Result<Begin, sqlx::Error> {
+        let prune = self.is_prune_due();
+
+        match &self.keys {
+            Keys::Memory(keys) => {
+                let now = Instant::now();
+                let mut keys = keys.lock().unwrap();
+
+                if prune {
+                    keys.retain(|_, stored| {
+                        now.saturating_duration_since(stored.created_at) < self.window
+                    });
+                }
+
+                let existing = keys.get(key).filter(|stored| {
+                    let age = now.saturating_duration_since(stored.created_at);
+
+                    age < self.window && (stored.response.is_some() || age < ABANDONED_AFTER)
+                });
+
+                let begin = match existing {
+                    Some(stored) if stored.request_hash != request_hash => Begin::Mismatch,
+                    Some(stored) => match &stored.response {
+                        Some(response) => Begin::Replay(response.clone()),
+                        None => Begin::InUse,
+                    },
+                    None => {
+                        keys.insert(
+                            key.to_string(),
+                            MemoryKey {
+                                request_hash: request_hash.to_vec(),
+                                response: None,
+                                created_at: now,
+                            },
+                        );
+
+                        Begin::Answer
+                    }
+                };
+
+                Ok(begin)
+            }
+            Keys::Postgres(db) => {
+                if prune {
+                    tokio::spawn(forget_expired_keys(db.clone(), self.window));
+                }
+
+                begin_shared(db, key, request_hash, self.window).await
+            }
+        }
+    }
+
+    /// Stores the response to replay to the retries of the request sent with `key`
+    async fn finish(&self, key: &str, response: &StoredResponse) -> Result<(), sqlx::Error> {
+        match &self.keys {
+            Keys::Memory(keys) => {
+                if let Some(stored) = keys.lock().unwrap().get_mut(key) {
+                    stored.response = Some(response.clone());
+                }
+
+                Ok(())
+            }
+            Keys::Postgres(db) => {
+                sqlx::query(
+                    "UPDATE
+                      idempotency_keys
+                    SET
+                      status = $2,
+                      content_type = $3,
+                      body = $4
+                    WHERE
+                      key = $1",
+                )
+                .bind(key)
+                .bind(response.status as i16)
+                .bind(&response.content_type)
+                .bind(&response.body)
+                .execute(traced(db))
+                .await?;
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Lets the next request sent with `key` be answered afresh
+    async fn forget(&self, key: &str) -> Result<(), sqlx::Error> {
+        match &self.keys {
+            Keys::Memory(keys) => {
+                keys.lock().unwrap().remove(key);
+
+                Ok(())
+            }
+            Keys::Postgres(db) => {
+                sqlx::query("DELETE FROM idempotency_keys WHERE key = $1 AND status IS NULL")
+                    .bind(key)
+                    .execute(traced(db))
+                    .await?;
+
+                Ok(())
+            }
+        }
+    }
+
+    fn is_prune_due(&self) -> bool {
+        let now = Instant::now();
+        let mut pruned_at = self.pruned_at.lock().unwrap();
+
+        if now.saturating_duration_since(*pruned_at) < PRUNE_INTERVAL {
+            return false;
+        }
+
+        *pruned_at = now;
+
+        true
+    }
+}
+
+/// Claims the key with a new row, or an expired or abandoned one. Otherwise the row is left
+/// alone and read back to tell why.
+async fn begin_shared(
+    db: &DBPool,
+    key: &str,
+    request_hash: &[u8],
+    window: Duration,
+) -> Result<Begin, sqlx::Error> {
+    let claimed = sqlx::query(
+        "INSERT INTO idempotency_keys (key, request_hash, created_at)
+        VALUES ($1, $2, NOW())
+        ON CONFLICT (key) DO UPDATE
+        SET
+          request_hash = EXCLUDED.request_hash,
+          status = NULL,
+          content_type = NULL,
+          body = NULL,
+          created_at = NOW()
+        WHERE
+          idempotency_keys.created_at < NOW() - make_interval(secs => $3)
+          OR (
+            idempotency_keys.status IS NULL
+            AND idempotency_keys.created_at < NOW() - make_interval(secs => $4)
+          )",
+    )
+    .bind(key)
+    .bind(request_hash)
+    .bind(window.as_secs_f64())
+    .bind(ABANDONED_AFTER.as_secs_f64())
+    .execute(traced(db))
+    .await?;
+
+    if claimed.rows_affected() > 0 {
+        return Ok(Begin::Answer);
+    }
+
+    let stored = sqlx::query_as::<_, (Vec<u8>, Option<i16>, Option<String>, Option<Vec<u8>>)>(
+        "SELECT request_hash, status, content_type, body FROM idempotency_keys WHERE key = $1",
+    )
+    .bind(key)
+    .fetch_optional(traced(db))
+    .await?;
+
+    let begin = match stored {
+        Some((stored_hash, ..)) if stored_hash != request_hash => Begin::Mismatch,
+        Some((_, Some(status), content_type, body)) => Begin::Replay(StoredResponse {
+            status: status as u16,
+            content_type,
+            body: body.unwrap_or_default(),
+        }),
+        // Answering the first failed and it was forgotten just now, a retry gets through
+        Some(_) | None => Begin::InUse,
+    };
+
+    Ok(begin)
+}
+
+async fn forget_expired_keys(db: DBPool, window: Duration) {
+    let forgotten = sqlx::query(
+        "DELETE FROM idempotency_keys WHERE created_at < NOW() - make_interval(secs => $1)",
+    )
+    .bind(window.as_secs_f64())
+    .execute(traced(&db))
+    .await;
+
+    if let Err(e) = forgotten {
+        tracing::error!(error = %e, "Failed to remove expired idempotency keys");
+    }
+}
+
+/// Replays the response to the first `POST` sent with an `Idempotency-Key` header to its
+/// retries, so a client retrying after losing the response doesn't run into its own success.
+/// Retries of a request still being answered are turned down with `IdempotencyKeyInUseError`,
+/// other requests sent with a key used before with `IdempotencyKeyMismatchError`. Requests
+/// without a key are passed on to `routes` as they are.
+///
+/// Keys are scoped to the client, told apart the way `limiter` does, so clients can't replay
+/// each other's responses. The requests are told apart by their path, query, credentials and
+/// body. Server errors aren't stored, so a retry after one is answered afresh.
+pub fn apply<F, T>(
+    keys: Arc<IdempotencyKeys>,
+    limiter: Arc<RateLimiter>,
+    routes: F,
+) -> impl Filter<Extract = (Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (T,), Error = Infallible> + Clone + Send + Sync + 'static,
+    T: Reply,
+{
+    let service = warp::service(routes.clone());
+
+    let keyed = warp::post()
+        .and(warp::header::<String>(IDEMPOTENCY_KEY_HEADER))
+        .and(warp::path::full())
+        .and(
+            warp::query::raw()
+                .map(Some)
+                .or(warp::any().map(|| None))
+                .unify(),
+        )
+        .and(warp::header::headers_cloned())
+        .and(warp::ext::optional::<ClientAddr>())
+        .and(
+            warp::body::content_length_limit(MAX_BODY_SIZE)
+                .and(warp::body::bytes())
+                .map(Ok)
+                .or_else(|rejection| async move { Ok::<_, Infallible>((Err(rejection),)) }),
+        )
+        .then(
+            move |key: String,
+                  path: FullPath,
+                  query: Option<String>,
+                  headers: HeaderMap,
+                  client_addr: Option<ClientAddr>,
+                  body: Result<Bytes, Rejection>| {
+                let keys = keys.clone();
+                let limiter = limiter.clone();
+                let service = service.clone();
+
+                async move {
+                    let body = match body {
+                        Ok(body) => body,
+                        // Without a Content-Length there's no body, unless it's chunked
+                        Err(rejection)
+                            if rejection.find::<LengthRequired>().is_some()
+                                && !headers.contains_key(header::TRANSFER_ENCODING) =>
+                        {
+                            Bytes::new()
+                        }
+                        Err(rejection) => return rejection_response(rejection).await,
+                    };
+
+                    if key.is_empty()
+                        || key.len() > 255
+                        || !key.bytes().all(|byte| byte.is_ascii_graphic())
+                    {
+                        return error_response(InvalidIdempotencyKeyError).await;
+                    }
+
+                    let header_value = |name| {
+                        headers
+                            .get(name)
+                            .and_then(|value: &HeaderValue| value.to_str().ok())
+                            .map(String::from)
+                    };
+
+                    let authorization = header_value(header::AUTHORIZATION);
+
+                    let client = limiter.client(
+                        authorization.clone(),
+                        header_value(header::HeaderName::from_static("x-forwarded-for")),
+                        client_addr,
+                    );
+                    let key = match client {
+                        Some(client) => format!("{} {}", client, key),
+                        None => key,
+                    };
+
+                    let mut hasher = Sha256::new();
+                    hasher.update(path.as_str());
+                    hasher.update(b"?");
+                    hasher.update(query.as_deref().unwrap_or_default());
+                    hasher.update(b"\n");
+                    hasher.update(authorization.as_deref().unwrap_or_default());
+                    hasher.update(b"\n");
+                    hasher.update(&body);
+                    let request_hash = hasher.finalize();
+
+                    match keys.begin(&key, &request_hash).await {
+                        Ok(Begin::Answer) => (),
+                        Ok(Begin::Replay(response)) => return response.replay(),
+                        Ok(Begin::InUse) => return error_response(IdempotencyKeyInUseError).await,
+                        Ok(Begin::Mismatch) => {
+                            return error_response(IdempotencyKeyMismatchError).await
+                        }
+                        Err(e) => {
+                            tracing::error!(error = %e, "Failed to look up the idempotency key");
+
+                            return error_response(InternalServerError).await;
+                        }
+                    }
+
+                    let uri = match query {
+                        Some(query) => format!("{}?{}", path.as_str(), query),
+                        None => path.as_str().to_string(),
+                    };
+
+                    let mut req = Request::new(Body::from(body));
+                    *req.method_mut() = Method::POST;
+                    *req.uri_mut() = uri.parse::<Uri>().unwrap_or_default();
+                    *req.headers_mut() = headers;
+
+                    // Keeps going when the client hangs up, so its retry finds the response
+                    let answer = tokio::spawn(
+                        answer_and_store(keys.clone(), key.clone(), service, req)
+                            .instrument(tracing::Span::current()),
+                    );
+
+                    match answer.await {
+                        Ok(resp) => resp,
+                        Err(e) => {
+                            tracing::error!(error = %e, "Failed to answer the request");
+
+                            if let Err(e) = keys.forget(&key).await {
+                                tracing::error!(error = %e, "Failed to forget the idempotency key");
+                            }
+
+                            error_response(InternalServerError).await
+                        }
+                    }
+                }
+            },
+        );
+
+    keyed.or(routes.map(Reply::into_response)).unify()
+}
+
+async fn answer_and_store<S>(
+    keys: Arc<IdempotencyKeys>,
+    key: String,
+    mut service: S,
+    req: Request<Body>,
+) -> Response
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible>,
+{
+    let resp = match service.call(req).await {
+        Ok(resp) => resp,
+        Err(never) => match never {},
+    };
+
+    let (parts, body) = resp.into_parts();
+
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) if !parts.status.is_server_error() => body,
+        read => {
+            if let Err(e) = &read {
+                tracing::error!(error = %e, "Failed to read the response");
+            }
+
+            if let Err(e) = keys.forget(&key).await {
+                tracing::error!(error = %e, "Failed to forget the idempotency key");
+            }
+
+            return match read {
+                Ok(body) => HttpResponse::from_parts(parts, Body::from(body)),
+                Err(_) => error_response(InternalServerError).await,
+            };
+        }
+    };
+
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        content_type: parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .map(String::from),
+        body: body.to_vec(),
+    };
+
+    if let Err(e) = keys.finish(&key, &stored).await {
+        tracing::error!(error = %e, "Failed to store the response to the idempotency key");
+    }
+
+    HttpResponse::from_parts(parts, Body::from(body))
+}
+
+async fn error_response(err: impl Reject) -> Response {
+    rejection_response(warp::reject::custom(err)).await
+}
+
+async fn rejection_response(rejection: Rejection) -> Response {
+    match handle_rejection(rejection).await {
+        Ok(reply) => reply.into_response(),
+        Err(never) => match never {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use serde_json::{json, Value};
+    use warp::{http::StatusCode, Filter};
+
+    use super::{apply, Begin, IdempotencyKeys};
+    use crate::{
+        config::{Config, ConfigArgs},
+        errors::handle_rejection,
+        rate_limit::RateLimiter,
+        routes::rooms_routes,
+        server::ClientAddr,
+        store::{MemoryRoomStore, Store},
+    };
+
+    fn config() -> Config {
+        ConfigArgs {
+            db_string: Some(String::from("postgres://db/zoomer")),
+            ..Default::default()
+        }
+        .resolve()
+        .unwrap()
+    }
+
+    fn keys() -> Arc<IdempotencyKeys> {
+        Arc::new(IdempotencyKeys::from_config(&config(), None))
+    }
+
+    fn limiter() -> Arc<RateLimiter> {
+        Arc::new(RateLimiter::from_config(&config(), None))
+    }
+
+    fn new_room(name: &str) -> Value {
+        json!({
+            "name": name,
+            "room_id": name.to_lowercase(),
+            "capacity": 8,
+            "time_limit": 90,
+            "link": "https://zoom.us/j/1",
+            "comments": "",
+            "timezone": "Europe/Berlin",
+        })
+    }
+
+    fn create(key: Option<&str>, body: &Value) -> warp::test::RequestBuilder {
+        let request = warp::test::request()
+            .method("POST")
+            .path("/rooms/new")
+            .json(body);
+
+        match key {
+            Some(key) => request.header("idempotency-key", key),
+            None => request,
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_the_first_response_to_retries() {
+        let store: Store = Arc::new(MemoryRoomStore::default());
+        let api = apply(
+            keys(),
+            limiter(),
+            rooms_routes(store).recover(handle_rejection),
+        );
+
+        let first = create(Some("retry-1"), &new_room("Everest"))
+            .reply(&api)
+            .await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert!(!first.headers().contains_key("idempotent-replayed"));
+
+        let retry = create(Some("retry-1"), &new_room("Everest"))
+            .reply(&api)
+            .await;
+        assert_eq!(retry.status(), StatusCode::OK);
+        assert_eq!(retry.headers()["idempotent-replayed"], "true");
+        assert_eq!(retry.headers()["content-type"], "application/json");
+        assert_eq!(retry.body(), first.body());
+
+        // Without a key the retry runs again and finds its own room
+        let resp = create(None, &new_room("Everest")).reply(&api).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(std::str::from_utf8(resp.body())
+            .unwrap()
+            .contains("Room with same name exists"));
+
+        let resp = create(Some("retry-1"), &new_room("Kilimanjaro"))
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let resp = create(Some("retry 1"), &new_room("Kilimanjaro"))
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn replays_errors_but_not_server_errors() {
+        let api = apply(
+            keys(),
+            limiter(),
+            warp::path("fail")
+                .map(|| StatusCode::SERVICE_UNAVAILABLE)
+                .or(warp::any().map(|| StatusCode::CONFLICT)),
+        );
+
+        for _ in 0..2 {
+            let resp = warp::test::request()
+                .method("POST")
+                .path("/fail")
+                .header("idempotency-key", "flaky")
+                .reply(&api)
+                .await;
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert!(!resp.headers().contains_key("idempotent-replayed"));
+        }
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/taken")
+            .header("idempotency-key", "conflict")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/taken")
+            .header("idempotency-key", "conflict")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.headers()["idempotent-replayed"], "true");
+    }
+
+    #[tokio::test]
+    async fn scopes_keys_to_the_client_and_its_credentials() {
+        let store: Store = Arc::new(MemoryRoomStore::default());
+        let api = apply(
+            keys(),
+            limiter(),
+            rooms_routes(store).recover(handle_rejection),
+        );
+
+        let from = |ip: [u8; 4], token: &str| {
+            create(Some("shared"), &new_room("Everest"))
+                .header("authorization", format!("Bearer {}", token))
+                .extension(ClientAddr(SocketAddr::from((ip, 50000))))
+        };
+
+        let resp = from([10, 0, 0, 1], "alice").reply(&api).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // Another client's key of the same name isn't replayed to it
+        let resp = from([10, 0, 0, 2], "bob").reply(&api).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(!resp.headers().contains_key("idempotent-replayed"));
+
+        let resp = from([10, 0, 0, 1], "mallory").reply(&api).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let resp = from([10, 0, 0, 1], "alice").reply(&api).await;
+        assert_eq!(resp.headers()["idempotent-replayed"], "true");
+    }
+
+    #[tokio::test]
+    async fn limits_the_body_size() {
+        let api = apply(keys(), limiter(), warp::any().map(|| StatusCode::OK));
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/rooms/new")
+            .header("idempotency-key", "huge")
+            .header("content-length", (64 * 1024 * 1024 + 1).to_string())
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/rooms/new")
+            .header("idempotency-key", "chunked")
+            .header("transfer-encoding", "chunked")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), StatusCode::LENGTH_REQUIRED);
+    }
+
+    #[tokio::test]
+    async fn turns_down_retries_while_the_first_is_answered() {
+        let keys = keys();
+
+        assert!(matches!(keys.begin("slow", b"a").await, Ok(Begin::Answer)));
+        assert!(matches!(keys.begin("slow", b"a").await, Ok(Begin::InUse)));
+        assert!(matches!(
+            keys.begin("slow", b"b").await,
+            Ok(Begin::Mismatch)
+        ));
+
+        keys.forget("slow").await.unwrap();
+        assert!(matches!(keys.begin("slow", b"b").await, Ok(Begin::Answer)));
+    }
+}
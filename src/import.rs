@@ -0,0 +1,362 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde_json::json;
+use warp::{
+    hyper::body::Bytes,
+    reply::{Reply, Response},
+};
+
+use crate::{
+    errors::{InternalServerError, InvalidImportFileError},
+    models::{ImportMode, ImportQuery, ImportRowError, NewRoom, Room},
+    schedule::is_valid_timezone,
+    telemetry::traced,
+    DBPool,
+};
+
+/// Imports a batch of rooms sent as CSV (`Content-Type: text/csv`) or as a JSON array of
+/// `NewRoom`. Every row is checked before anything is written. In atomic mode a single invalid
+/// row fails the whole import, in partial mode the valid rows are still created.
+pub async fn import_rooms(
+    query: ImportQuery,
+    content_type: Option<String>,
+    body: Bytes,
+    db: DBPool,
+) -> Result<Response, warp::Rejection> {
+    let mode = query.mode.unwrap_or(ImportMode::Atomic);
+    let dry_run = query.dry_run.unwrap_or(false);
+
+    let is_csv = content_type
+        .map(|content_type| content_type.starts_with("text/csv"))
+        .unwrap_or(false);
+
+    let rows = if is_csv {
+        parse_csv(&body)
+    } else {
+        parse_json(&body)?
+    };
+
+    let mut tx = match db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to start import transaction");
+
+            return Err(warp::reject::custom(InternalServerError));
+        }
+    };
+
+    // Keep other writers out until the import is done, so the conflict checks below still hold
+    // when the rooms are inserted
+    if !dry_run {
+        let lock_query = sqlx::query("LOCK TABLE rooms IN SHARE ROW EXCLUSIVE MODE")
+            .execute(traced(&mut tx))
+            .await;
+
+        if let Err(e) = lock_query {
+            tracing::error!(error = %e, "Failed to lock rooms for import");
+
+            return Err(warp::reject::custom(InternalServerError));
+        }
+    }
+
+    let names: Vec<String> = rows
+        .iter()
+        .filter_map(|row| row.as_ref().ok())
+        .map(|room| room.name.clone())
+        .collect();
+    let room_ids: Vec<String> = rows
+        .iter()
+        .filter_map(|row| row.as_ref().ok())
+        .map(|room| room.room_id.clone())
+        .collect();
+
+    let existing_query = sqlx::query_as::<_, (String, String)>(
+        "SELECT 
+          name, 
+          room_id 
+        FROM 
+          rooms 
+        WHERE 
+          name = ANY($1) 
+          OR room_id = ANY($2)",
+    )
+    .bind(names)
+    .bind(room_ids)
+    .fetch_all(traced(&mut tx))
+    .await;
+
+    let existing = match existing_query {
+        Ok(existing) => existing,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to look up existing rooms for import");
+
+            return Err(warp::reject::custom(InternalServerError));
+        }
+    };
+
+    let (valid_rooms, row_errors) = check_rows(rows, &existing);
+
+    if mode == ImportMode::Atomic && !row_errors.is_empty() && !dry_run {
+        let resp = json!({
+            "success": false,
+            "message": "Import has invalid rows, no rooms were created",
+            "errors": row_errors,
+        });
+
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&resp),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+        .into_response());
+    }
+
+    if dry_run {
+        let resp = json!({
+            "dry_run": true,
+            "mode": mode,
+            "valid_count": valid_rooms.len(),
+            "rooms": [],
+            "errors": row_errors,
+        });
+
+        return Ok(warp::reply::json(&resp).into_response());
+    }
+
+    let mut rooms = vec![];
+
+    for room in valid_rooms {
+        let interval = Duration::from_secs(room.time_limit * 60);
+
+        let insert_query = sqlx::query_as::<_, Room>(
+            "INSERT INTO rooms(
+              name, room_id, capacity, time_limit, 
+              link, comments, timezone, location
+            ) 
+            VALUES 
+              (
+                $1, $2, $3, $4 :: interval, $5, $6, 
+                COALESCE($7, 'UTC'), 
+                $8
+              ) RETURNING id, 
+              name, 
+              room_id, 
+              capacity, 
+              TO_CHAR(time_limit, 'HH24:MI:SS') as time_limit, 
+              link, 
+              comments, 
+              timezone, 
+              location",
+        )
+        .bind(room.name)
+        .bind(room.room_id)
+        .bind(room.capacity)
+        .bind(interval)
+        .bind(room.link)
+        .bind(room.comments)
+        .bind(room.timezone)
+        .bind(room.location)
+        .fetch_one(traced(&mut tx))
+        .await;
+
+        match insert_query {
+            Ok(room) => rooms.push(room),
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to insert imported room");
+
+                return Err(warp::reject::custom(InternalServerError));
+            }
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!(error = %e, "Failed to commit import");
+
+        return Err(warp::reject::custom(InternalServerError));
+    }
+
+    let resp = json!({
+        "dry_run": false,
+        "mode": mode,
+        "valid_count": rooms.len(),
+        "rooms": rooms,
+        "errors": row_errors,
+    });
+
+    Ok(warp::reply::json(&resp).into_response())
+}
+
+/// Splits the rows into the rooms which can be created and a report of the rest. Rows conflict
+/// with the `existing` rooms' names and room ids, and with the rows before them.
+fn check_rows(
+    rows: Vec<Result<NewRoom, String>>,
+    existing: &[(String, String)],
+) -> (Vec<NewRoom>, Vec<ImportRowError>) {
+    let mut valid_rooms = vec![];
+    let mut row_errors = vec![];
+    let mut seen_names = HashMap::new();
+    let mut seen_room_ids = HashMap::new();
+
+    for (index, row) in rows.into_iter().enumerate() {
+        let row_number = index + 1;
+
+        let room = match row {
+            Ok(room) => room,
+            Err(message) => {
+                row_errors.push(ImportRowError {
+                    row: row_number,
+                    name: None,
+                    room_id: None,
+                    errors: vec![message],
+                });
+
+                continue;
+            }
+        };
+
+        let mut errors = vec![];
+
+        if existing.iter().any(|(name, _)| *name == room.name) {
+            errors.push("Room with same name exists".to_string());
+        }
+
+        if existing.iter().any(|(_, room_id)| *room_id == room.room_id) {
+            errors.push("Room with same room id exists".to_string());
+        }
+
+        match seen_names.get(&room.name) {
+            Some(first_row) => errors.push(format!("Name is already used in row {first_row}")),
+            None => {
+                seen_names.insert(room.name.clone(), row_number);
+            }
+        }
+
+        match seen_room_ids.get(&room.room_id) {
+            Some(first_row) => errors.push(format!("Room id is already used in row {first_row}")),
+            None => {
+                seen_room_ids.insert(room.room_id.clone(), row_number);
+            }
+        }
+
+        if !is_valid_timezone(room.timezone.as_deref()) {
+            errors
+                .push("Invalid timezone, use an IANA timezone name like Europe/Berlin".to_string());
+        }
+
+        if errors.is_empty() {
+            valid_rooms.push(room);
+        } else {
+            row_errors.push(ImportRowError {
+                row: row_number,
+                name: Some(room.name),
+                room_id: Some(room.room_id),
+                errors,
+            });
+        }
+    }
+
+    (valid_rooms, row_errors)
+}
+
+/// Rows which can't be read are kept as errors, so they show up in the report with the rest.
+fn parse_csv(body: &[u8]) -> Vec<Result<NewRoom, String>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body);
+
+    reader
+        .deserialize::<NewRoom>()
+        .map(|row| row.map_err(|e| format!("Invalid row: {e}")))
+        .collect()
+}
+
+fn parse_json(body: &[u8]) -> Result<Vec<Result<NewRoom, String>>, warp::Rejection> {
+    let values: Vec<serde_json::Value> = match serde_json::from_slice(body) {
+        Ok(values) => values,
+        Err(_) => return Err(warp::reject::custom(InvalidImportFileError)),
+    };
+
+    let rows = values
+        .into_iter()
+        .map(|value| serde_json::from_value(value).map_err(|e| format!("Invalid row: {e}")))
+        .collect();
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_rows, parse_csv, parse_json};
+
+    fn existing() -> Vec<(String, String)> {
+        vec![(String::from("Everest"), String::from("everest"))]
+    }
+
+    #[test]
+    fn reads_csv_rows_and_keeps_broken_ones() {
+        let body = b"name,room_id,capacity,time_limit,link,comments,timezone,location
+ K2 , k2 ,6,60,https://zoom.us/j/2,,Asia/Karachi,Skardu
+Denali,denali,many,60,https://zoom.us/j/3,,,
+";
+        let rows = parse_csv(body);
+        assert_eq!(rows.len(), 2);
+
+        let room = rows[0].as_ref().unwrap();
+        assert_eq!(room.name, "K2");
+        assert_eq!(room.capacity, 6);
+        assert_eq!(room.timezone.as_deref(), Some("Asia/Karachi"));
+        assert!(matches!(&rows[1], Err(message) if message.starts_with("Invalid row:")));
+    }
+
+    #[test]
+    fn reads_json_rows_and_refuses_other_files() {
+        let body = br#"[
+            {"name": "K2", "room_id": "k2", "capacity": 6, "time_limit": 60,
+             "link": "https://zoom.us/j/2", "comments": ""},
+            {"name": "Denali"}
+        ]"#;
+        let rows = parse_json(body).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].as_ref().unwrap().room_id, "k2");
+        assert!(rows[1].is_err());
+
+        assert!(parse_json(b"{\"name\": \"K2\"}").is_err());
+        assert!(parse_json(b"not json").is_err());
+    }
+
+    #[test]
+    fn reports_conflicts_per_row() {
+        let body = b"name,room_id,capacity,time_limit,link,comments,timezone,location
+Everest,everest-2,8,60,https://zoom.us/j/1,,,
+K2,k2,6,60,https://zoom.us/j/2,,,
+K2,everest,6,60,https://zoom.us/j/3,,,
+Denali,k2,4,60,https://zoom.us/j/4,,Mars/Olympus_Mons,
+Lhotse,lhotse,4,sixty,https://zoom.us/j/5,,,
+Makalu,makalu,4,60,https://zoom.us/j/6,,Asia/Kathmandu,
+";
+        let (valid, errors) = check_rows(parse_csv(body), &existing());
+
+        let valid: Vec<_> = valid.iter().map(|room| room.name.as_str()).collect();
+        assert_eq!(valid, ["K2", "Makalu"]);
+
+        let rows: Vec<_> = errors.iter().map(|error| error.row).collect();
+        assert_eq!(rows, [1, 3, 4, 5]);
+
+        assert_eq!(errors[0].errors, ["Room with same name exists"]);
+        assert_eq!(
+            errors[1].errors,
+            [
+                "Room with same room id exists",
+                "Name is already used in row 2"
+            ]
+        );
+        assert_eq!(
+            errors[2].errors,
+            [
+                "Room id is already used in row 2",
+                "Invalid timezone, use an IANA timezone name like Europe/Berlin"
+            ]
+        );
+        assert_eq!(errors[3].name, None);
+        assert!(errors[3].errors[0].starts_with("Invalid row:"));
+    }
+}
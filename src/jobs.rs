@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::{metrics, store::Store};
+
+/// Periodically releases rooms whose meeting was never checked in within
+/// `checkin_window` of its start, recording them as no-shows. Returns once `shutdown`
+/// changes, never in the middle of a run.
+pub async fn release_no_shows(
+    store: Store,
+    checkin_window: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => (),
+            _ = shutdown.changed() => return,
+        }
+
+        let released = store.release_no_shows(checkin_window).await;
+
+        match released {
+            Ok(released) if released > 0 => {
+                tracing::info!(released, "Released no-show rooms");
+
+                metrics::OCCUPANCIES_FREED
+                    .with_label_values(&["no_show"])
+                    .inc_by(released);
+            }
+            Ok(_) => (),
+            Err(e) => {
+                tracing::error!(error = ?e, "Failed to release no-show rooms");
+            }
+        }
+    }
+}
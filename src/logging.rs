@@ -0,0 +1,44 @@
+use tracing_subscriber::{
+    filter::Targets, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
+
+use crate::telemetry;
+
+/// Logs one JSON object per line to stdout, at the levels picked by the `log_level` filter
+/// directives. Spans are exported as traces too when an OTLP endpoint is configured, whatever
+/// `log_level` says.
+pub fn init(log_level: &str) {
+    let filter = EnvFilter::new(log_level);
+
+    let logs = tracing_subscriber::fmt::layer()
+        .json()
+        .flatten_event(true)
+        .with_current_span(true)
+        .with_span_list(false)
+        .with_filter(filter);
+
+    let tracer_provider = telemetry::otlp_tracer_provider();
+
+    let traces = tracer_provider
+        .as_ref()
+        .ok()
+        .and_then(Option::as_ref)
+        .map(|provider| {
+            telemetry::layer(provider)
+                .with_filter(Targets::new().with_target("zoomer_api", tracing::Level::INFO))
+        });
+
+    tracing_subscriber::registry()
+        .with(logs)
+        .with(traces)
+        .init();
+
+    match tracer_provider {
+        Ok(Some(provider)) => {
+            opentelemetry::global::set_tracer_provider(provider);
+            tracing::info!("Exporting traces over OTLP");
+        }
+        Ok(None) => (),
+        Err(e) => tracing::error!(error = %e, "Failed to set up the OTLP exporter"),
+    }
+}
@@ -1,73 +1,343 @@
+mod analytics;
+mod backup;
+mod config;
+mod cors;
 mod errors;
+mod export;
 mod handlers;
+mod health;
+mod idempotency;
+mod import;
+mod jobs;
+mod logging;
+mod metrics;
+mod migrations;
 mod models;
+mod rate_limit;
 mod routes;
+mod schedule;
+mod server;
+mod store;
+mod sync;
+mod telemetry;
+mod tls;
 
-use std::env;
+use std::{
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
 
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use tokio::sync::watch;
 use warp::Filter;
 
-use errors::handle_rejection;
-use routes::rooms_routes;
+use config::{Config, ConfigArgs};
+use cors::CorsPolicy;
+use errors::{handle_rejection, UnauthorizedError};
+use idempotency::IdempotencyKeys;
+use rate_limit::RateLimiter;
+use routes::{
+    admin_routes, analytics_routes, export_routes, health_routes, holidays_routes, metrics_route,
+    room_schedule_routes, room_settings_routes, rooms_routes,
+};
+use store::{PgRoomStore, Store};
+use tls::CertResolver;
+
+#[derive(Parser)]
+#[command(version, about = "Zoomer API server")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// TOML file with settings, overridden by environment variables and flags
+    #[arg(
+        long = "config",
+        env = "CONFIG_FILE",
+        value_name = "FILE",
+        global = true
+    )]
+    config_file: Option<PathBuf>,
+    /// Print the resolved settings as TOML and exit
+    #[arg(long, global = true)]
+    print_config: bool,
+    #[command(flatten)]
+    settings: ConfigArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Reconcile the rooms table with a rooms definition file
+    Sync {
+        /// TOML file with a [[rooms]] table per room
+        file: PathBuf,
+        /// Only print the changes, without applying them
+        #[arg(long)]
+        plan: bool,
+    },
+}
 
 #[tokio::main]
 async fn main() {
-    // Load env variables
+    // Load env variables, they have to be set before the flags are parsed
     dotenv().ok();
 
-    // Setup logging
-    if env::var_os("RUST_LOG").is_none() {
-        env::set_var("RUST_LOG", "rooms=info");
+    let cli = Cli::parse();
+
+    // Flags and env variables win over the config file, which wins over the defaults
+    let config = match load_config(cli.settings, cli.config_file.as_deref()) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if cli.print_config {
+        print!("{}", config.to_redacted_toml());
+        return;
     }
-    pretty_env_logger::init();
+
+    // Setup logging
+    logging::init(&config.log_level);
+    metrics::init();
+
+    // Serve HTTPS when there's a certificate, failing before anything else when it's unusable
+    let certs = match CertResolver::from_config(&config) {
+        Ok(certs) => certs.map(Arc::new),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to load the TLS certificate");
+            process::exit(1);
+        }
+    };
 
     // CORS setup
-    let cors = warp::cors()
-        .allow_any_origin()
-        .allow_headers(vec![
-            "Content-Type",
-            "Accept",
-            "Access-Control-Request-Method",
-            "Access-Control-Request-Headers",
-        ])
-        .allow_methods(vec!["POST", "GET"]);
+    let cors = Arc::new(CorsPolicy::from_config(&config));
+
+    // SQLite databases only back the room and occupancy endpoints
+    if config.db_string.starts_with("sqlite:") {
+        return serve_sqlite(&config, certs, cli.command, cors).await;
+    }
 
     // Connect db
-    let db_string = env::var("DB_STRING").expect("Missing env var: DB_STRING");
-    let db_pool = connect_to_db(&db_string).await;
+    let db_pool = connect_to_db(&config).await;
+
+    metrics::DB_POOL_MAX_CONNECTIONS.set(config.db_max_connections as i64);
+
+    // Bring the schema up to date, refusing databases migrated by a newer release
+    match migrations::run_migrations(&db_pool).await {
+        Ok(version) => tracing::info!(version, "Database schema is up to date"),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to migrate the database");
+            process::exit(1);
+        }
+    }
+
+    match cli.command {
+        Some(Command::Migrate) => return,
+        // Sync rooms from a definition file instead of starting the server
+        Some(Command::Sync { file, plan }) => {
+            if let Err(e) = sync::sync_rooms(&db_pool, &file, plan).await {
+                tracing::error!(error = %e, "Failed to sync rooms");
+                process::exit(1);
+            }
+
+            return;
+        }
+        None => (),
+    }
+
+    // Admin endpoints are disabled without a token
+    let admin_token = config.admin_token.clone();
+
+    if admin_token.is_none() {
+        tracing::warn!("ADMIN_TOKEN is not set, admin endpoints are disabled");
+    }
+
+    let store: Store = Arc::new(PgRoomStore::new(db_pool.clone()));
+
+    // Release rooms which were never checked in
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let no_shows = tokio::spawn(jobs::release_no_shows(
+        store.clone(),
+        config.checkin_window(),
+        shutdown_rx,
+    ));
+
+    // Health checks and metrics aren't rate limited, the API endpoints are
+    let limiter_db = config
+        .rate_limit_shared
+        .then(|| rate_limit::connect_shared(&config));
+    let limiter = Arc::new(RateLimiter::from_config(&config, limiter_db));
+    let idempotency_keys = Arc::new(IdempotencyKeys::from_config(&config, Some(db_pool.clone())));
 
     // API routes
     let initial_route = warp::get()
         .and(warp::path::end())
         .map(|| "Zoomer API active");
 
+    let api_routes = rooms_routes(store.clone())
+        .or(room_schedule_routes(store.clone()))
+        .or(room_settings_routes(db_pool.clone()))
+        .or(holidays_routes(store.clone()))
+        .or(analytics_routes(db_pool.clone()))
+        .or(export_routes(db_pool.clone()))
+        .or(admin_routes(db_pool.clone(), admin_token))
+        // Keeps the nested filter types, and with them build times, in check
+        .boxed();
+
     let routes = initial_route
-        .or(rooms_routes(db_pool.clone()))
-        .with(warp::log("rooms"))
-        .with(cors)
+        .or(health_routes(db_pool.clone(), config.clone()))
+        .or(metrics_route(store, Some(db_pool.clone())))
+        .or(rate_limit::limit(limiter.clone()).and(idempotency::apply(
+            idempotency_keys,
+            limiter,
+            api_routes.recover(handle_rejection),
+        )))
         .recover(handle_rejection);
 
-    // Get port and start server
-    if env::var_os("PORT").is_none() {
-        env::set_var("PORT", "4000");
+    let routes = cors::apply(cors, routes).recover(handle_rejection);
+
+    // Start server
+    let drained = server::run(routes, &config, certs).await;
+
+    // Let the no-show job finish the run it's in, then close the pool
+    shutdown_tx.send(true).ok();
+
+    if let Err(e) = no_shows.await {
+        tracing::error!(error = %e, "No-show job failed");
+    }
+
+    // Requests cut off by the shutdown timeout still hold their connections
+    if drained {
+        db_pool.close().await;
     }
 
-    let port: u16 = env::var("PORT")
-        .unwrap()
-        .parse()
-        .expect("Invalid env var: PORT");
+    tracing::info!("Shut down");
 
-    warp::serve(routes).run(([0, 0, 0, 0], port)).await;
+    // Export the spans still waiting in the batch
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Serves rooms, occupancies, maintenance windows, opening hours and holidays from a SQLite
+/// database for single-box deployments, releasing no-shows like on Postgres. Analytics,
+/// exports, imports, tags, no-show counts and the admin endpoints need Postgres.
+#[cfg(feature = "sqlite")]
+async fn serve_sqlite(
+    config: &Config,
+    certs: Option<Arc<CertResolver>>,
+    command: Option<Command>,
+    cors: Arc<CorsPolicy>,
+) {
+    let (store, version) = match store::SqliteRoomStore::connect(&config.db_string).await {
+        Ok(connected) => connected,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to open the SQLite database");
+            process::exit(1);
+        }
+    };
+
+    tracing::info!(version, "Database schema is up to date");
+
+    match command {
+        Some(Command::Migrate) => return,
+        Some(Command::Sync { .. }) => {
+            tracing::error!("Syncing rooms needs a Postgres database");
+            process::exit(1);
+        }
+        None => (),
+    }
+
+    tracing::warn!("Running on SQLite, analytics, exports, imports, tags, no-show counts and admin endpoints aren't served");
+
+    let initial_route = warp::get()
+        .and(warp::path::end())
+        .map(|| "Zoomer API active");
+
+    let sqlite = Arc::new(store);
+    let store: Store = sqlite.clone();
+
+    // Release rooms which were never checked in
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let no_shows = tokio::spawn(jobs::release_no_shows(
+        store.clone(),
+        config.checkin_window(),
+        shutdown_rx,
+    ));
+
+    let limiter = Arc::new(RateLimiter::from_config(config, None));
+    let idempotency_keys = Arc::new(IdempotencyKeys::from_config(config, None));
+
+    let api_routes = rooms_routes(store.clone())
+        .or(room_schedule_routes(store.clone()))
+        .or(holidays_routes(store.clone()))
+        .boxed();
+
+    let routes = initial_route
+        .or(routes::healthz_route())
+        .or(metrics_route(store, None))
+        .or(rate_limit::limit(limiter.clone()).and(idempotency::apply(
+            idempotency_keys,
+            limiter,
+            api_routes.recover(handle_rejection),
+        )))
+        .recover(handle_rejection);
+
+    let routes = cors::apply(cors, routes).recover(handle_rejection);
+
+    let drained = server::run(routes, config, certs).await;
+
+    // Let the no-show job finish the run it's in, then close the database
+    shutdown_tx.send(true).ok();
+
+    if let Err(e) = no_shows.await {
+        tracing::error!(error = %e, "No-show job failed");
+    }
+
+    if drained {
+        sqlite.close().await;
+    }
+
+    tracing::info!("Shut down");
+
+    // Export the spans still waiting in the batch
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(not(feature = "sqlite"))]
+async fn serve_sqlite(
+    _config: &Config,
+    _certs: Option<Arc<CertResolver>>,
+    _command: Option<Command>,
+    _cors: Arc<CorsPolicy>,
+) {
+    tracing::error!("DB_STRING points to a SQLite database, build zoomer_api with `--features sqlite` to use it");
+    process::exit(1);
+}
+
+fn load_config(settings: ConfigArgs, file: Option<&Path>) -> Result<Config, String> {
+    let settings = match file {
+        Some(file) => settings.or(ConfigArgs::from_file(file)?),
+        None => settings,
+    };
+
+    settings.resolve()
 }
 
 pub type DBPool = Pool<Postgres>;
 
-async fn connect_to_db(db_string: &str) -> DBPool {
+async fn connect_to_db(config: &Config) -> DBPool {
     let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(db_string)
+        .max_connections(config.db_max_connections)
+        .min_connections(config.db_min_connections)
+        .acquire_timeout(config.db_acquire_timeout())
+        .idle_timeout(config.db_idle_timeout())
+        .connect(&config.db_string)
         .await;
 
     match pool {
@@ -81,3 +351,48 @@ pub fn with_db(
 ) -> impl Filter<Extract = (Pool<Postgres>,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || db.clone())
 }
+
+pub fn with_config(
+    config: Arc<Config>,
+) -> impl Filter<Extract = (Arc<Config>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || config.clone())
+}
+
+pub fn with_store(
+    store: Store,
+) -> impl Filter<Extract = (Store,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || store.clone())
+}
+
+pub fn with_admin(
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let admin_token = admin_token.clone();
+
+            async move {
+                let token = header
+                    .as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "));
+
+                match (admin_token, token) {
+                    (Some(admin_token), Some(token)) if is_same_token(&admin_token, token) => {
+                        Ok(())
+                    }
+                    _ => Err(warp::reject::custom(UnauthorizedError)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Compares every byte, so the time taken doesn't give away how much of the token matched
+fn is_same_token(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
@@ -0,0 +1,185 @@
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, TextEncoder,
+};
+use warp::http::{Method, StatusCode};
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use crate::{
+    errors::{rejection_status, InternalServerError},
+    store::Store,
+    DBPool,
+};
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "zoomer_http_requests_total",
+        "HTTP requests answered, by route, method and status code",
+        &["route", "method", "status"]
+    )
+    .unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "zoomer_http_request_duration_seconds",
+        "Time taken to answer HTTP requests, by route",
+        &["route"]
+    )
+    .unwrap();
+    static ref DB_POOL_CONNECTIONS: IntGaugeVec = register_int_gauge_vec!(
+        "zoomer_db_pool_connections",
+        "Open database connections, by whether they are idle or in use",
+        &["state"]
+    )
+    .unwrap();
+    pub static ref DB_POOL_MAX_CONNECTIONS: IntGauge = register_int_gauge!(
+        "zoomer_db_pool_max_connections",
+        "Most database connections the pool will open"
+    )
+    .unwrap();
+    static ref ROOMS_OCCUPIED: IntGauge = register_int_gauge!(
+        "zoomer_rooms_occupied",
+        "Rooms with a meeting running in them"
+    )
+    .unwrap();
+    static ref ROOMS_AVAILABLE: IntGauge = register_int_gauge!(
+        "zoomer_rooms_available",
+        "Rooms free to be occupied right now"
+    )
+    .unwrap();
+    pub static ref RATE_LIMITED_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "zoomer_rate_limited_requests_total",
+        "Requests turned down by the rate limiter, by budget: read or write",
+        &["budget"]
+    )
+    .unwrap();
+    pub static ref OCCUPANCIES_CREATED: IntCounter = register_int_counter!(
+        "zoomer_occupancies_created_total",
+        "Meetings started in a room, including transfers into a room"
+    )
+    .unwrap();
+    pub static ref OCCUPANCIES_FREED: IntCounterVec = register_int_counter_vec!(
+        "zoomer_occupancies_freed_total",
+        "Meetings that left a room, by reason: freed_up, transferred or no_show",
+        &["reason"]
+    )
+    .unwrap();
+}
+
+/// Registers every metric up front, so counters are scraped as 0 before they first change
+pub fn init() {
+    lazy_static::initialize(&HTTP_REQUESTS);
+    lazy_static::initialize(&HTTP_REQUEST_DURATION);
+    lazy_static::initialize(&DB_POOL_CONNECTIONS);
+    lazy_static::initialize(&DB_POOL_MAX_CONNECTIONS);
+    lazy_static::initialize(&ROOMS_OCCUPIED);
+    lazy_static::initialize(&ROOMS_AVAILABLE);
+    lazy_static::initialize(&OCCUPANCIES_CREATED);
+
+    for reason in ["freed_up", "transferred", "no_show"] {
+        OCCUPANCIES_FREED.with_label_values(&[reason]);
+    }
+
+    for budget in ["read", "write"] {
+        RATE_LIMITED_REQUESTS.with_label_values(&[budget]);
+    }
+}
+
+/// Counts and times every request `filter` answers under the `route` label. Requests the
+/// route doesn't match are left for the route that does.
+pub fn instrument<F, T>(
+    route: &'static str,
+    filter: F,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (T,), Error = Rejection> + Clone + Send + Sync + 'static,
+    T: Reply,
+{
+    warp::any()
+        .map(Instant::now)
+        .and(warp::method())
+        .and(
+            filter
+                .map(|reply: T| Ok::<_, Rejection>(reply.into_response()))
+                .or_else(|err| async move { Ok::<_, Rejection>((Err(err),)) }),
+        )
+        .and_then(
+            move |started: Instant, method: Method, result: Result<Response, Rejection>| async move {
+                record_request(route, &method, started, &result);
+
+                result
+            },
+        )
+}
+
+fn record_request(
+    route: &str,
+    method: &Method,
+    started: Instant,
+    result: &Result<Response, Rejection>,
+) {
+    let status = match result {
+        Ok(resp) => resp.status(),
+        // The path or method didn't match, this isn't a request for this route
+        Err(err)
+            if err.is_not_found() || err.find::<warp::reject::MethodNotAllowed>().is_some() =>
+        {
+            return
+        }
+        Err(err) => {
+            rejection_status(err).map_or(StatusCode::INTERNAL_SERVER_ERROR, |(code, _)| code)
+        }
+    };
+
+    // Names the route in the request's log lines and its trace
+    let span = tracing::Span::current();
+    span.record("route", route);
+    span.record("otel.name", format!("{method} {route}"));
+
+    HTTP_REQUESTS
+        .with_label_values(&[route, method.as_str(), status.as_str()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[route])
+        .observe(started.elapsed().as_secs_f64());
+}
+
+/// Prometheus text format. Room and pool gauges are read at scrape time, pool gauges only
+/// when running on Postgres.
+pub async fn fetch_metrics(
+    store: Store,
+    db: Option<DBPool>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Ok(state) = store.current_state().await {
+        ROOMS_OCCUPIED.set(state.active_rooms.len() as i64);
+        ROOMS_AVAILABLE.set(state.available_rooms.len() as i64);
+    }
+
+    if let Some(db) = db {
+        let size = db.size() as i64;
+        let idle = db.num_idle() as i64;
+
+        DB_POOL_CONNECTIONS.with_label_values(&["idle"]).set(idle);
+        DB_POOL_CONNECTIONS
+            .with_label_values(&["in_use"])
+            .set(size - idle);
+    }
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut body) {
+        tracing::error!(error = %e, "Failed to encode metrics");
+
+        return Err(warp::reject::custom(InternalServerError));
+    }
+
+    Ok(warp::reply::with_header(
+        body,
+        "content-type",
+        encoder.format_type(),
+    ))
+}
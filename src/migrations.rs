@@ -0,0 +1,94 @@
+use sqlx::migrate::Migrator;
+
+use crate::DBPool;
+
+/// Schema migrations from `migrations/`, embedded into the binary
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Applies every pending migration and returns the schema version the database ends up at.
+/// Databases migrated by a newer release are left alone, since this binary doesn't know their
+/// schema.
+pub async fn run_migrations(db: &DBPool) -> Result<i64, String> {
+    let latest_version = latest_version();
+
+    let applied_version = applied_version(db)
+        .await
+        .map_err(|e| format!("Couldn't read the schema version: {}", e))?;
+
+    check_version(applied_version, latest_version)?;
+
+    MIGRATOR
+        .run(db)
+        .await
+        .map_err(|e| format!("Couldn't migrate the database: {}", e))?;
+
+    Ok(latest_version)
+}
+
+/// Fails for databases migrated past `latest_version`, older ones can be brought up to date
+fn check_version(applied_version: i64, latest_version: i64) -> Result<(), String> {
+    if applied_version > latest_version {
+        return Err(format!(
+            "Database schema version {} is newer than this binary's {}, upgrade zoomer_api first",
+            applied_version, latest_version
+        ));
+    }
+
+    Ok(())
+}
+
+/// The version of the newest migration built into this binary
+pub fn latest_version() -> i64 {
+    MIGRATOR
+        .iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The version of the newest migration applied to the database, 0 for a fresh database
+pub async fn applied_version(db: &DBPool) -> Result<i64, sqlx::Error> {
+    // Fresh databases don't have the migrations table yet
+    let has_migrations =
+        sqlx::query_scalar::<_, bool>("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(db)
+            .await?;
+
+    if !has_migrations {
+        return Ok(0);
+    }
+
+    let version = sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT MAX(version) FROM _sqlx_migrations WHERE success",
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(version.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_version, latest_version, MIGRATOR};
+
+    #[test]
+    fn migrates_older_databases_only() {
+        assert_eq!(check_version(0, 8), Ok(()));
+        assert_eq!(check_version(7, 8), Ok(()));
+        assert_eq!(check_version(8, 8), Ok(()));
+        assert_eq!(
+            check_version(9, 8),
+            Err(String::from(
+                "Database schema version 9 is newer than this binary's 8, upgrade zoomer_api first"
+            ))
+        );
+    }
+
+    #[test]
+    fn numbers_migrations_without_gaps() {
+        let versions: Vec<i64> = MIGRATOR.iter().map(|migration| migration.version).collect();
+
+        assert_eq!(versions, (1..=8).collect::<Vec<_>>());
+        assert_eq!(latest_version(), 8);
+    }
+}
@@ -1,8 +1,9 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
 pub struct Room {
     pub id: Uuid,
     pub name: String,
@@ -11,6 +12,8 @@ pub struct Room {
     pub time_limit: String,
     pub link: String,
     pub comments: String,
+    pub timezone: String,
+    pub location: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -21,6 +24,8 @@ pub struct NewRoom {
     pub time_limit: u64,
     pub link: String,
     pub comments: String,
+    pub timezone: Option<String>,
+    pub location: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
@@ -32,19 +37,49 @@ pub struct ActiveRoom {
     pub time_limit: String,
     pub link: String,
     pub comments: String,
+    pub timezone: String,
+    pub location: Option<String>,
     pub is_active: bool,
     pub occupied_until: DateTime<Utc>,
+    pub occupied_until_local: String,
     pub meeting_title: String,
     pub meeting_comments: String,
+    pub checked_in: bool,
 }
 
 #[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct UnavailableRoom {
+    pub id: Uuid,
+    pub name: String,
+    pub room_id: String,
+    pub capacity: i32,
+    pub time_limit: String,
+    pub link: String,
+    pub comments: String,
+    pub timezone: String,
+    pub location: Option<String>,
+    pub unavailable_until: DateTime<Utc>,
+    pub unavailable_reason: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CurrentState {
+    pub available_rooms: Vec<Room>,
+    pub active_rooms: Vec<ActiveRoom>,
+    pub unavailable_rooms: Vec<UnavailableRoom>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
 pub struct Occupancy {
     pub id: i32,
     pub occupied_room_id: Uuid,
     pub occupied_until: DateTime<Utc>,
     pub meeting_title: String,
     pub comments: String,
+    pub started_at: DateTime<Utc>,
+    pub checked_in: bool,
+    pub headcount: Option<i32>,
+    pub booked_by: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
@@ -53,4 +88,344 @@ pub struct NewOccupancy {
     pub occupied_until: DateTime<Utc>,
     pub meeting_title: String,
     pub comments: String,
+    pub headcount: Option<i32>,
+    pub booked_by: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct OccupancyTransfer {
+    pub from_room_id: Uuid,
+    pub to_room_id: Uuid,
+    pub headcount: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct RoomNoShows {
+    pub id: Uuid,
+    pub name: String,
+    pub room_id: String,
+    pub no_show_count: i64,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
+pub struct MaintenanceWindow {
+    pub id: i32,
+    pub room_id: Uuid,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewMaintenanceWindow {
+    pub room_id: Uuid,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
+pub struct OpeningHours {
+    pub weekday: i16,
+    pub opens_at: NaiveTime,
+    pub closes_at: NaiveTime,
+}
+
+#[derive(Deserialize)]
+pub struct RoomSchedule {
+    pub opening_hours: Vec<OpeningHours>,
+}
+
+#[derive(Deserialize)]
+pub struct RoomTags {
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
+pub struct Holiday {
+    pub id: i32,
+    pub date: NaiveDate,
+    pub name: String,
+    pub location: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewHoliday {
+    pub date: NaiveDate,
+    pub name: String,
+    pub location: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyticsGroup {
+    Room,
+    Location,
+}
+
+#[derive(Deserialize)]
+pub struct UtilizationQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub group_by: Option<AnalyticsGroup>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct Utilization {
+    pub room_id: Option<Uuid>,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub booked_hours: f64,
+    pub bookable_hours: f64,
+    pub utilization_percentage: f64,
+    pub meeting_count: i64,
+    pub average_meeting_minutes: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct HeatmapQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub timezone: Option<String>,
+    pub room_id: Option<Uuid>,
+    pub location: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct HeatmapCell {
+    pub weekday: i16,
+    pub hour: i16,
+    pub max_concurrency: i64,
+    pub average_concurrency: f64,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct DailyPeak {
+    pub date: NaiveDate,
+    pub peak_concurrency: i64,
+}
+
+#[derive(Deserialize)]
+pub struct SizingQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct RoomSizing {
+    pub room_id: Uuid,
+    pub name: String,
+    pub capacity: i32,
+    pub meeting_count: i64,
+    pub average_headcount: Option<f64>,
+    pub average_seat_usage: Option<f64>,
+    pub oversized_count: i64,
+    pub consistently_oversized: bool,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct BookerSizing {
+    pub booked_by: String,
+    pub meeting_count: i64,
+    pub average_headcount: f64,
+    pub average_capacity: f64,
+    pub oversized_count: i64,
+    pub consistently_oversized: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SuggestedRoom {
+    pub id: Uuid,
+    pub name: String,
+    pub capacity: i32,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct OversizedMeeting {
+    pub room_id: Uuid,
+    pub room_name: String,
+    pub capacity: i32,
+    pub meeting_title: String,
+    pub headcount: i32,
+    pub booked_by: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub suggested_rooms: Json<Vec<SuggestedRoom>>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+#[derive(Deserialize)]
+pub struct RoomsExportQuery {
+    pub format: Option<ExportFormat>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryExportQuery {
+    pub format: Option<ExportFormat>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub room_id: Option<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct OccupancyRecord {
+    pub id: i32,
+    pub occupied_room_id: Uuid,
+    pub room_name: String,
+    pub location: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub occupied_until: DateTime<Utc>,
+    pub meeting_title: String,
+    pub comments: Option<String>,
+    pub no_show: bool,
+    pub headcount: Option<i32>,
+    pub booked_by: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    Atomic,
+    Partial,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    pub mode: Option<ImportMode>,
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportRowError {
+    pub row: usize,
+    pub name: Option<String>,
+    pub room_id: Option<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoomsFile {
+    #[serde(default)]
+    pub rooms: Vec<RoomDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoomDefinition {
+    pub name: String,
+    pub room_id: String,
+    pub capacity: i32,
+    pub time_limit: u64,
+    pub link: String,
+    #[serde(default)]
+    pub comments: String,
+    pub timezone: Option<String>,
+    pub location: Option<String>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct SyncedRoom {
+    pub id: Uuid,
+    pub name: String,
+    pub room_id: String,
+    pub capacity: Option<i32>,
+    pub time_limit: Option<i64>,
+    pub link: String,
+    pub comments: Option<String>,
+    pub timezone: String,
+    pub location: Option<String>,
+    pub archived: bool,
+    /// Title of the meeting the room is occupied by, if any
+    pub meeting_title: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct BackupRoom {
+    pub id: Uuid,
+    pub name: String,
+    pub room_id: String,
+    pub capacity: Option<i32>,
+    pub time_limit: Option<String>,
+    pub link: String,
+    pub comments: Option<String>,
+    pub timezone: String,
+    pub location: Option<String>,
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct BackupOccupancy {
+    pub id: i32,
+    pub occupied_room_id: Uuid,
+    pub occupied_until: DateTime<Utc>,
+    pub meeting_title: String,
+    pub comments: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub checked_in: bool,
+    pub headcount: Option<i32>,
+    pub booked_by: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct BackupOccupancyHistory {
+    pub id: i32,
+    pub occupied_room_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub occupied_until: DateTime<Utc>,
+    pub meeting_title: String,
+    pub comments: Option<String>,
+    pub no_show: bool,
+    pub headcount: Option<i32>,
+    pub booked_by: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct BackupOpeningHours {
+    pub room_id: Uuid,
+    pub weekday: i16,
+    pub opens_at: NaiveTime,
+    pub closes_at: NaiveTime,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug)]
+pub struct BackupRoomTag {
+    pub room_id: Uuid,
+    pub tag: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Backup {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub rooms: Vec<BackupRoom>,
+    pub occupancies: Vec<BackupOccupancy>,
+    pub occupancy_history: Vec<BackupOccupancyHistory>,
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+    pub opening_hours: Vec<BackupOpeningHours>,
+    pub holidays: Vec<Holiday>,
+    /// Missing from backups taken before rooms had tags
+    #[serde(default)]
+    pub room_tags: Vec<BackupRoomTag>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreStrategy {
+    Replace,
+    Merge,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreQuery {
+    pub strategy: Option<RestoreStrategy>,
 }
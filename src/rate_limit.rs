@@ -0,0 +1,531 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use sqlx::postgres::PgPoolOptions;
+use warp::{http::Method, Filter, Rejection};
+
+use crate::{
+    config::Config, errors::RateLimitedError, is_same_token, metrics::RATE_LIMITED_REQUESTS,
+    server::ClientAddr, telemetry::traced, DBPool,
+};
+
+/// Budgets are per minute and refill evenly over it, so a bucket left alone for this long is
+/// full again and can be forgotten
+const REFILL_PERIOD: Duration = Duration::from_secs(60);
+
+/// Connections kept for the shared budgets, on top of `db_max_connections`. Every limited
+/// request runs a query or two on them, which shouldn't wait behind the handlers' queries.
+const SHARED_POOL_SIZE: u32 = 2;
+
+/// Which budget a request is taken from
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    fn of(method: &Method) -> Access {
+        if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            Access::Read
+        } else {
+            Access::Write
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+        }
+    }
+}
+
+/// Tokens left in a client's budget, one per request
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn full(per_minute: u32, now: Instant) -> Bucket {
+        Bucket {
+            tokens: per_minute as f64,
+            refilled_at: now,
+        }
+    }
+
+    /// Takes a token, or tells how long it takes until there's one
+    fn take(&mut self, per_minute: u32, now: Instant) -> Result<(), Duration> {
+        let refilled = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64()
+            * per_minute as f64
+            / REFILL_PERIOD.as_secs_f64();
+
+        self.tokens = (self.tokens + refilled).min(per_minute as f64);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+
+            Ok(())
+        } else {
+            Err(time_to_refill(1.0 - self.tokens, per_minute))
+        }
+    }
+}
+
+fn time_to_refill(tokens: f64, per_minute: u32) -> Duration {
+    Duration::from_secs_f64(tokens * REFILL_PERIOD.as_secs_f64() / per_minute as f64)
+}
+
+enum Buckets {
+    Memory {
+        buckets: Mutex<HashMap<String, Bucket>>,
+        pruned_at: Mutex<Instant>,
+    },
+    /// In the `rate_limit_buckets` table, shared by every replica
+    Postgres {
+        db: DBPool,
+        pruned_at: Mutex<Instant>,
+    },
+}
+
+/// Token buckets per client, with a budget for reading endpoints and one for writing ones.
+/// Clients are told apart by the admin token when they send it, by their address otherwise.
+pub struct RateLimiter {
+    reads_per_minute: Option<u32>,
+    writes_per_minute: Option<u32>,
+    admin_token: Option<String>,
+    trust_forwarded_for: bool,
+    buckets: Buckets,
+}
+
+impl RateLimiter {
+    /// The limits set by the `rate_limit_*` settings, kept in `db` when they are shared
+    pub fn from_config(config: &Config, db: Option<DBPool>) -> RateLimiter {
+        let pruned_at = Mutex::new(Instant::now());
+
+        let buckets = match db {
+            Some(db) if config.rate_limit_shared => Buckets::Postgres { db, pruned_at },
+            _ => Buckets::Memory {
+                buckets: Mutex::new(HashMap::new()),
+                pruned_at,
+            },
+        };
+
+        RateLimiter {
+            reads_per_minute: config.rate_limit_reads_per_minute,
+            writes_per_minute: config.rate_limit_writes_per_minute,
+            admin_token: config.admin_token.clone(),
+            trust_forwarded_for: config.trust_forwarded_for,
+            buckets,
+        }
+    }
+
+    async fn check(
+        &self,
+        method: Method,
+        authorization: Option<String>,
+        forwarded_for: Option<String>,
+        client_addr: Option<ClientAddr>,
+    ) -> Result<(), Rejection> {
+        let access = Access::of(&method);
+
+        let per_minute = match access {
+            Access::Read => self.reads_per_minute,
+            Access::Write => self.writes_per_minute,
+        };
+
+        let (Some(per_minute), Some(client)) = (
+            per_minute,
+            self.client(authorization, forwarded_for, client_addr),
+        ) else {
+            return Ok(());
+        };
+
+        let key = format!("{}:{}", access.as_str(), client);
+
+        match self.take(&key, per_minute).await {
+            Ok(()) => Ok(()),
+            Err(wait) => {
+                RATE_LIMITED_REQUESTS
+                    .with_label_values(&[access.as_str()])
+                    .inc();
+
+                tracing::warn!(client, budget = access.as_str(), "Rate limited");
+
+                Err(warp::reject::custom(RateLimitedError {
+                    retry_after_secs: wait.as_secs_f64().ceil().max(1.0) as u64,
+                }))
+            }
+        }
+    }
+
+    /// Whose budget the request is taken from
+    pub fn client(
+        &self,
+        authorization: Option<String>,
+        forwarded_for: Option<String>,
+        client_addr: Option<ClientAddr>,
+    ) -> Option<String> {
+        let token = authorization
+            .as_deref()
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        if let (Some(admin_token), Some(token)) = (&self.admin_token, token) {
+            if is_same_token(admin_token, token) {
+                return Some(String::from("admin"));
+            }
+        }
+
+        // The proxy appends the address it got the request from, anything before it is
+        // whatever the client claimed
+        let forwarded_ip = forwarded_for
+            .filter(|_| self.trust_forwarded_for)
+            .and_then(|header| header.rsplit(',').next()?.trim().parse::<IpAddr>().ok());
+
+        forwarded_ip
+            .or(client_addr.map(|ClientAddr(addr)| addr.ip()))
+            .map(|ip| format!("ip:{}", ip))
+    }
+
+    async fn take(&self, key: &str, per_minute: u32) -> Result<(), Duration> {
+        match &self.buckets {
+            Buckets::Memory { buckets, pruned_at } => {
+                let now = Instant::now();
+                let mut buckets = buckets.lock().unwrap();
+
+                if is_due(pruned_at, now) {
+                    buckets.retain(|_, bucket| {
+                        now.saturating_duration_since(bucket.refilled_at) < REFILL_PERIOD
+                    });
+                }
+
+                buckets
+                    .entry(key.to_string())
+                    .or_insert_with(|| Bucket::full(per_minute, now))
+                    .take(per_minute, now)
+            }
+            Buckets::Postgres { db, pruned_at } => {
+                if is_due(pruned_at, Instant::now()) {
+                    tokio::spawn(forget_full_buckets(db.clone()));
+                }
+
+                match take_shared(db, key, per_minute).await {
+                    Ok(taken) => taken,
+                    Err(e) => {
+                        // An unreachable database shouldn't take the API down with it
+                        tracing::error!(error = %e, "Failed to check the rate limit, letting the request through");
+
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A pool of its own for the shared budgets, connecting on first use
+pub fn connect_shared(config: &Config) -> DBPool {
+    let pool = PgPoolOptions::new()
+        .max_connections(SHARED_POOL_SIZE)
+        .acquire_timeout(config.db_acquire_timeout())
+        .idle_timeout(config.db_idle_timeout())
+        .connect_lazy(&config.db_string);
+
+    match pool {
+        Ok(db) => db,
+        Err(e) => panic!("Couldn't connect to the database!: {}", e),
+    }
+}
+
+/// Whether a refill period has passed since the buckets were last pruned, starting the next
+/// one if so
+fn is_due(pruned_at: &Mutex<Instant>, now: Instant) -> bool {
+    let mut pruned_at = pruned_at.lock().unwrap();
+
+    if now.saturating_duration_since(*pruned_at) < REFILL_PERIOD {
+        return false;
+    }
+
+    *pruned_at = now;
+
+    true
+}
+
+/// Takes a token from the bucket in `rate_limit_buckets`, leaving it alone when it's empty.
+/// The row stays locked from the refill to the update, so replicas can't take the same token.
+async fn take_shared(
+    db: &DBPool,
+    key: &str,
+    per_minute: u32,
+) -> Result<Result<(), Duration>, sqlx::Error> {
+    let capacity = per_minute as f64;
+    let per_sec = capacity / REFILL_PERIOD.as_secs_f64();
+
+    let taken = sqlx::query(
+        "INSERT INTO rate_limit_buckets AS buckets (key, tokens, refilled_at)
+        VALUES ($1, $2 - 1, NOW())
+        ON CONFLICT (key) DO UPDATE
+        SET
+          tokens = LEAST($2, buckets.tokens + EXTRACT(EPOCH FROM NOW() - buckets.refilled_at)::double precision * $3) - 1,
+          refilled_at = NOW()
+        WHERE
+          LEAST($2, buckets.tokens + EXTRACT(EPOCH FROM NOW() - buckets.refilled_at)::double precision * $3) >= 1",
+    )
+    .bind(key)
+    .bind(capacity)
+    .bind(per_sec)
+    .execute(traced(db))
+    .await?;
+
+    if taken.rows_affected() > 0 {
+        return Ok(Ok(()));
+    }
+
+    let tokens = sqlx::query_scalar::<_, f64>(
+        "SELECT
+          LEAST($2, tokens + EXTRACT(EPOCH FROM NOW() - refilled_at)::double precision * $3)
+        FROM
+          rate_limit_buckets
+        WHERE
+          key = $1",
+    )
+    .bind(key)
+    .bind(capacity)
+    .bind(per_sec)
+    .fetch_optional(traced(db))
+    .await?
+    .unwrap_or_default();
+
+    Ok(Err(time_to_refill(1.0 - tokens, per_minute)))
+}
+
+async fn forget_full_buckets(db: DBPool) {
+    let forgotten = sqlx::query(
+        "DELETE FROM rate_limit_buckets WHERE refilled_at < NOW() - make_interval(secs => $1)",
+    )
+    .bind(REFILL_PERIOD.as_secs_f64())
+    .execute(traced(&db))
+    .await;
+
+    if let Err(e) = forgotten {
+        tracing::error!(error = %e, "Failed to remove full rate limit buckets");
+    }
+}
+
+/// Turns down requests over the client's budget with `RateLimitedError`
+pub fn limit(limiter: Arc<RateLimiter>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::method()
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .and(warp::ext::optional::<ClientAddr>())
+        .and_then(move |method, authorization, forwarded_for, client_addr| {
+            let limiter = limiter.clone();
+
+            async move {
+                limiter
+                    .check(method, authorization, forwarded_for, client_addr)
+                    .await
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use warp::{http::StatusCode, Filter};
+
+    use super::{connect_shared, limit, Bucket, RateLimiter};
+    use crate::{config::ConfigArgs, errors::handle_rejection, migrations, server::ClientAddr};
+
+    fn limiter(settings: ConfigArgs) -> Arc<RateLimiter> {
+        let db_string = settings
+            .db_string
+            .clone()
+            .unwrap_or_else(|| String::from("postgres://db/zoomer"));
+
+        let config = ConfigArgs {
+            db_string: Some(db_string),
+            rate_limit_reads_per_minute: Some(3),
+            rate_limit_writes_per_minute: Some(1),
+            admin_token: Some(String::from("hunter2")),
+            ..settings
+        }
+        .resolve()
+        .unwrap();
+
+        let db = config.rate_limit_shared.then(|| connect_shared(&config));
+
+        Arc::new(RateLimiter::from_config(&config, db))
+    }
+
+    fn request(method: &str, ip: [u8; 4]) -> warp::test::RequestBuilder {
+        warp::test::request()
+            .method(method)
+            .path("/rooms")
+            .extension(ClientAddr(SocketAddr::from((ip, 50000))))
+    }
+
+    #[test]
+    fn refills_buckets_evenly_over_a_minute() {
+        let start = Instant::now();
+        let mut bucket = Bucket::full(2, start);
+
+        assert!(bucket.take(2, start).is_ok());
+        assert!(bucket.take(2, start).is_ok());
+        let wait = |taken: Result<(), Duration>| taken.unwrap_err().as_secs_f64().round();
+
+        assert_eq!(wait(bucket.take(2, start)), 30.0);
+        assert_eq!(wait(bucket.take(2, start + Duration::from_secs(20))), 10.0);
+        assert!(bucket.take(2, start + Duration::from_secs(30)).is_ok());
+
+        // Never fills past the budget
+        let later = start + Duration::from_secs(600);
+        assert!(bucket.take(2, later).is_ok());
+        assert!(bucket.take(2, later).is_ok());
+        assert!(bucket.take(2, later).is_err());
+    }
+
+    #[tokio::test]
+    async fn limits_reads_and_writes_per_client() {
+        let routes = limit(limiter(ConfigArgs::default()))
+            .map(|| "ok")
+            .recover(handle_rejection);
+
+        for _ in 0..3 {
+            let resp = request("GET", [10, 0, 0, 1]).reply(&routes).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        let resp = request("GET", [10, 0, 0, 1]).reply(&routes).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()["retry-after"], "20");
+
+        // Writes have a budget of their own, and so do other clients
+        let resp = request("POST", [10, 0, 0, 1]).reply(&routes).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = request("POST", [10, 0, 0, 1]).reply(&routes).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()["retry-after"], "60");
+
+        let resp = request("GET", [10, 0, 0, 2]).reply(&routes).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = request("POST", [10, 0, 0, 1])
+            .header("authorization", "Bearer hunter2")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // Only the admin token gets a budget of its own
+        let resp = request("POST", [10, 0, 0, 1])
+            .header("authorization", "Bearer guess")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn keys_clients_by_forwarded_for_behind_a_proxy() {
+        let routes = limit(limiter(ConfigArgs {
+            trust_forwarded_for: Some(true),
+            ..Default::default()
+        }))
+        .map(|| "ok")
+        .recover(handle_rejection);
+
+        let resp = request("POST", [10, 0, 0, 1])
+            .header("x-forwarded-for", "1.1.1.1, 192.0.2.7")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // A spoofed first entry doesn't make it another client
+        let resp = request("POST", [10, 0, 0, 1])
+            .header("x-forwarded-for", "2.2.2.2, 192.0.2.7")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let resp = request("POST", [10, 0, 0, 1])
+            .header("x-forwarded-for", "192.0.2.8")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn lets_requests_through_without_the_database() {
+        let routes = limit(limiter(ConfigArgs {
+            rate_limit_shared: Some(true),
+            db_acquire_timeout_secs: Some(1),
+            ..Default::default()
+        }))
+        .map(|| "ok")
+        .recover(handle_rejection);
+
+        for _ in 0..2 {
+            let resp = request("POST", [10, 0, 0, 1]).reply(&routes).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+    }
+
+    /// Needs a Postgres database, run with `TEST_DB_STRING=postgres://... cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn shares_budgets_through_postgres() {
+        let db_string = std::env::var("TEST_DB_STRING").expect("TEST_DB_STRING is not set");
+
+        let settings = || ConfigArgs {
+            db_string: Some(db_string.clone()),
+            rate_limit_shared: Some(true),
+            ..Default::default()
+        };
+        let replicas = [limiter(settings()), limiter(settings())];
+
+        let db = connect_shared(&settings().resolve().unwrap());
+        migrations::run_migrations(&db).await.unwrap();
+        sqlx::query("DELETE FROM rate_limit_buckets WHERE key LIKE '%:ip:192.0.2.%'")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let routes = |limiter| limit(limiter).map(|| "ok").recover(handle_rejection);
+        let [first, second] = replicas.map(routes);
+
+        // Both replicas take from the same budgets
+        for routes in [&first, &second, &second] {
+            let resp = request("GET", [192, 0, 2, 1]).reply(routes).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        let resp = request("GET", [192, 0, 2, 1]).reply(&first).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()["retry-after"], "20");
+
+        let resp = request("POST", [192, 0, 2, 1]).reply(&first).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = request("POST", [192, 0, 2, 1]).reply(&second).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()["retry-after"], "60");
+
+        let resp = request("POST", [192, 0, 2, 2]).reply(&second).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
@@ -1,51 +1,65 @@
+use std::sync::Arc;
+
 use crate::{
+    analytics::{fetch_heatmap, fetch_right_sizing, fetch_utilization},
+    backup::{fetch_backup, restore_backup},
+    config::Config,
+    export::{export_history, export_rooms},
     handlers::{
-        create_new_room, fetch_active_rooms, fetch_available_rooms, fetch_current_state,
-        fetch_occupancies, fetch_single_room, handle_freeup_room, handle_occupy_room,
-        update_room_details,
+        create_holiday, create_maintenance_window, create_new_room, fetch_active_rooms,
+        fetch_available_rooms, fetch_current_state, fetch_holiday_collisions, fetch_holidays,
+        fetch_maintenance_windows, fetch_no_shows, fetch_occupancies, fetch_opening_hours,
+        fetch_room_tags, fetch_single_room, handle_checkin_room, handle_freeup_room,
+        handle_occupy_room, handle_transfer_room, remove_holiday, remove_maintenance_window,
+        update_opening_hours, update_room_details, update_room_tags,
     },
-    with_db, DBPool,
+    health::{check_health, check_readiness},
+    import::import_rooms,
+    metrics::{fetch_metrics, instrument},
+    store::Store,
+    tls::redirect_to_https,
+    with_admin, with_config, with_db, with_store, DBPool,
 };
 use uuid::Uuid;
 use warp::Filter;
 
 pub fn rooms_routes(
-    db_pool: DBPool,
+    store: Store,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let rooms_base = warp::path("rooms");
 
     let all_rooms = rooms_base
         .and(warp::get())
         .and(warp::path::end())
-        .and(with_db(db_pool.clone()))
+        .and(with_store(store.clone()))
         .and_then(fetch_current_state);
 
     let available_rooms = rooms_base
         .and(warp::get())
         .and(warp::path("available"))
         .and(warp::path::end())
-        .and(with_db(db_pool.clone()))
+        .and(with_store(store.clone()))
         .and_then(fetch_available_rooms);
 
     let active_rooms = rooms_base
         .and(warp::get())
         .and(warp::path("active"))
         .and(warp::path::end())
-        .and(with_db(db_pool.clone()))
+        .and(with_store(store.clone()))
         .and_then(fetch_active_rooms);
 
     let single_room = rooms_base
         .and(warp::get())
         .and(warp::path::param::<Uuid>())
         .and(warp::path::end())
-        .and(with_db(db_pool.clone()))
+        .and(with_store(store.clone()))
         .and_then(fetch_single_room);
 
     let occupancies = rooms_base
         .and(warp::get())
         .and(warp::path("occupancies"))
         .and(warp::path::end())
-        .and(with_db(db_pool.clone()))
+        .and(with_store(store.clone()))
         .and_then(fetch_occupancies);
 
     let new_room = rooms_base
@@ -53,7 +67,7 @@ pub fn rooms_routes(
         .and(warp::path("new"))
         .and(warp::path::end())
         .and(warp::body::json())
-        .and(with_db(db_pool.clone()))
+        .and(with_store(store.clone()))
         .and_then(create_new_room);
 
     let edit_room = rooms_base
@@ -62,7 +76,7 @@ pub fn rooms_routes(
         .and(warp::path::param::<Uuid>())
         .and(warp::path::end())
         .and(warp::body::json())
-        .and(with_db(db_pool.clone()))
+        .and(with_store(store.clone()))
         .and_then(update_room_details);
 
     let occupy_room = rooms_base
@@ -70,7 +84,7 @@ pub fn rooms_routes(
         .and(warp::path("occupy"))
         .and(warp::path::end())
         .and(warp::body::json())
-        .and(with_db(db_pool.clone()))
+        .and(with_store(store.clone()))
         .and_then(handle_occupy_room);
 
     let freeup_room = rooms_base
@@ -78,16 +92,965 @@ pub fn rooms_routes(
         .and(warp::path("freeup"))
         .and(warp::path::param::<Uuid>())
         .and(warp::path::end())
-        .and(with_db(db_pool.clone()))
+        .and(with_store(store.clone()))
         .and_then(handle_freeup_room);
 
-    all_rooms
-        .or(available_rooms)
-        .or(active_rooms)
-        .or(single_room)
-        .or(occupancies)
-        .or(new_room)
-        .or(edit_room)
-        .or(occupy_room)
-        .or(freeup_room)
+    let transfer_room = rooms_base
+        .and(warp::post())
+        .and(warp::path("transfer"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(with_store(store.clone()))
+        .and_then(handle_transfer_room);
+
+    let checkin_room = rooms_base
+        .and(warp::get())
+        .and(warp::path("checkin"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(with_store(store.clone()))
+        .and_then(handle_checkin_room);
+
+    instrument("all_rooms", all_rooms)
+        .or(instrument("available_rooms", available_rooms))
+        .or(instrument("active_rooms", active_rooms))
+        .or(instrument("single_room", single_room))
+        .or(instrument("occupancies", occupancies))
+        .or(instrument("new_room", new_room))
+        .or(instrument("edit_room", edit_room))
+        .or(instrument("occupy_room", occupy_room))
+        .or(instrument("freeup_room", freeup_room))
+        .or(instrument("transfer_room", transfer_room))
+        .or(instrument("checkin_room", checkin_room))
+}
+
+pub fn room_settings_routes(
+    db_pool: DBPool,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let rooms_base = warp::path("rooms");
+
+    let import_rooms = rooms_base
+        .and(warp::post())
+        .and(warp::path("import"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(warp::header::optional::<String>("content-type"))
+        .and(warp::body::content_length_limit(1024 * 1024))
+        .and(warp::body::bytes())
+        .and(with_db(db_pool.clone()))
+        .and_then(import_rooms);
+
+    let no_shows = rooms_base
+        .and(warp::get())
+        .and(warp::path("no-shows"))
+        .and(warp::path::end())
+        .and(with_db(db_pool.clone()))
+        .and_then(fetch_no_shows);
+
+    let room_tags = rooms_base
+        .and(warp::get())
+        .and(warp::path("tags"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(with_db(db_pool.clone()))
+        .and_then(fetch_room_tags);
+
+    let edit_room_tags = rooms_base
+        .and(warp::post())
+        .and(warp::path("tags"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(with_db(db_pool.clone()))
+        .and_then(update_room_tags);
+
+    instrument("import_rooms", import_rooms)
+        .or(instrument("no_shows", no_shows))
+        .or(instrument("room_tags", room_tags))
+        .or(instrument("edit_room_tags", edit_room_tags))
+}
+
+/// Maintenance windows and opening hours, served with every database backend
+pub fn room_schedule_routes(
+    store: Store,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let rooms_base = warp::path("rooms");
+
+    let maintenance_windows = rooms_base
+        .and(warp::get())
+        .and(warp::path("maintenance"))
+        .and(warp::path::end())
+        .and(with_store(store.clone()))
+        .and_then(fetch_maintenance_windows);
+
+    let new_maintenance_window = rooms_base
+        .and(warp::post())
+        .and(warp::path("maintenance"))
+        .and(warp::path("new"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(with_store(store.clone()))
+        .and_then(create_maintenance_window);
+
+    let remove_maintenance = rooms_base
+        .and(warp::get())
+        .and(warp::path("maintenance"))
+        .and(warp::path("remove"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(with_store(store.clone()))
+        .and_then(remove_maintenance_window);
+
+    let opening_hours = rooms_base
+        .and(warp::get())
+        .and(warp::path("hours"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(with_store(store.clone()))
+        .and_then(fetch_opening_hours);
+
+    let edit_opening_hours = rooms_base
+        .and(warp::post())
+        .and(warp::path("hours"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(with_store(store.clone()))
+        .and_then(update_opening_hours);
+
+    instrument("maintenance_windows", maintenance_windows)
+        .or(instrument("new_maintenance_window", new_maintenance_window))
+        .or(instrument("remove_maintenance", remove_maintenance))
+        .or(instrument("opening_hours", opening_hours))
+        .or(instrument("edit_opening_hours", edit_opening_hours))
+}
+
+pub fn holidays_routes(
+    store: Store,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let holidays_base = warp::path("holidays");
+
+    let all_holidays = holidays_base
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(with_store(store.clone()))
+        .and_then(fetch_holidays);
+
+    let new_holiday = holidays_base
+        .and(warp::post())
+        .and(warp::path("new"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(with_store(store.clone()))
+        .and_then(create_holiday);
+
+    let remove = holidays_base
+        .and(warp::get())
+        .and(warp::path("remove"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(with_store(store.clone()))
+        .and_then(remove_holiday);
+
+    let collisions = holidays_base
+        .and(warp::get())
+        .and(warp::path("collisions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(with_store(store.clone()))
+        .and_then(fetch_holiday_collisions);
+
+    instrument("all_holidays", all_holidays)
+        .or(instrument("new_holiday", new_holiday))
+        .or(instrument("remove_holiday", remove))
+        .or(instrument("holiday_collisions", collisions))
+}
+
+pub fn analytics_routes(
+    db_pool: DBPool,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let analytics_base = warp::path("analytics");
+
+    let utilization = analytics_base
+        .and(warp::get())
+        .and(warp::path("utilization"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(with_db(db_pool.clone()))
+        .and_then(fetch_utilization);
+
+    let heatmap = analytics_base
+        .and(warp::get())
+        .and(warp::path("heatmap"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(with_db(db_pool.clone()))
+        .and_then(fetch_heatmap);
+
+    let right_sizing = analytics_base
+        .and(warp::get())
+        .and(warp::path("right-sizing"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(with_db(db_pool.clone()))
+        .and_then(fetch_right_sizing);
+
+    instrument("utilization", utilization)
+        .or(instrument("heatmap", heatmap))
+        .or(instrument("right_sizing", right_sizing))
+}
+
+pub fn export_routes(
+    db_pool: DBPool,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let export_base = warp::path("export");
+
+    let rooms = export_base
+        .and(warp::get())
+        .and(warp::path("rooms"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(with_db(db_pool.clone()))
+        .and_then(export_rooms);
+
+    let history = export_base
+        .and(warp::get())
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(with_db(db_pool.clone()))
+        .and_then(export_history);
+
+    instrument("export_rooms", rooms).or(instrument("export_history", history))
+}
+
+/// Liveness only, served with every database backend
+pub fn healthz_route() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+{
+    let healthz = warp::get()
+        .and(warp::path("healthz"))
+        .and(warp::path::end())
+        .and_then(check_health);
+
+    instrument("healthz", healthz)
+}
+
+pub fn health_routes(
+    db_pool: DBPool,
+    config: Arc<Config>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let readyz = warp::get()
+        .and(warp::path("readyz"))
+        .and(warp::path::end())
+        .and(with_db(db_pool.clone()))
+        .and(with_config(config))
+        .and_then(check_readiness);
+
+    healthz_route().or(instrument("readyz", readyz))
+}
+
+/// Everything on the plain HTTP port is redirected to HTTPS on `https_port`
+pub fn https_redirect_route(
+    https_port: u16,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let redirect = warp::header::optional::<String>("host")
+        .and(warp::path::full())
+        .and(
+            warp::query::raw()
+                .map(Some)
+                .or(warp::any().map(|| None))
+                .unify(),
+        )
+        .map(move |host, path, query| redirect_to_https(https_port, host, path, query));
+
+    instrument("https_redirect", redirect)
+}
+
+/// Prometheus scrape endpoint, pool gauges are only reported with a Postgres pool
+pub fn metrics_route(
+    store: Store,
+    db_pool: Option<DBPool>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let metrics = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(with_store(store))
+        .and(warp::any().map(move || db_pool.clone()))
+        .and_then(fetch_metrics);
+
+    instrument("metrics", metrics)
+}
+
+pub fn admin_routes(
+    db_pool: DBPool,
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let admin_base = warp::path("admin").and(with_admin(admin_token));
+
+    let backup = admin_base
+        .clone()
+        .and(warp::get())
+        .and(warp::path("backup"))
+        .and(warp::path::end())
+        .and(with_db(db_pool.clone()))
+        .and_then(fetch_backup);
+
+    let restore = admin_base
+        .and(warp::post())
+        .and(warp::path("restore"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(warp::body::content_length_limit(64 * 1024 * 1024))
+        .and(warp::body::json())
+        .and(with_db(db_pool.clone()))
+        .and_then(restore_backup);
+
+    instrument("backup", backup).or(instrument("restore", restore))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+    use serde_json::{json, Value};
+    use warp::{http::StatusCode, Filter, Reply};
+
+    use super::{
+        analytics_routes, holidays_routes, https_redirect_route, metrics_route,
+        room_schedule_routes, room_settings_routes, rooms_routes,
+    };
+    use crate::{
+        errors::handle_rejection,
+        store::{MemoryRoomStore, Store},
+        DBPool,
+    };
+
+    fn api() -> impl Filter<Extract = (impl Reply,), Error = std::convert::Infallible> + Clone {
+        let store: Store = Arc::new(MemoryRoomStore::default());
+
+        rooms_routes(store.clone())
+            .or(room_schedule_routes(store.clone()))
+            .or(holidays_routes(store))
+            .recover(handle_rejection)
+    }
+
+    async fn send<F>(api: &F, method: &str, path: &str, body: Option<Value>) -> (StatusCode, Value)
+    where
+        F: Filter<Error = std::convert::Infallible> + 'static,
+        F::Extract: Reply + Send,
+    {
+        let mut request = warp::test::request().method(method).path(path);
+
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let resp = request.reply(api).await;
+        let body = serde_json::from_slice(resp.body()).unwrap_or(Value::Null);
+
+        (resp.status(), body)
+    }
+
+    fn new_room(name: &str, room_id: &str, capacity: i32) -> Value {
+        json!({
+            "name": name,
+            "room_id": room_id,
+            "capacity": capacity,
+            "time_limit": 90,
+            "link": format!("https://zoom.us/j/{room_id}"),
+            "comments": "",
+            "timezone": "Europe/Berlin",
+        })
+    }
+
+    async fn create_room<F>(api: &F, name: &str, room_id: &str, capacity: i32) -> String
+    where
+        F: Filter<Error = std::convert::Infallible> + 'static,
+        F::Extract: Reply + Send,
+    {
+        let (status, body) = send(
+            api,
+            "POST",
+            "/rooms/new",
+            Some(new_room(name, room_id, capacity)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        body["room_details"]["id"].as_str().unwrap().to_string()
+    }
+
+    fn occupancy(room: &str, headcount: Option<i32>) -> Value {
+        json!({
+            "occupied_room_id": room,
+            "occupied_until": Utc::now() + Duration::hours(1),
+            "meeting_title": "Standup",
+            "comments": "",
+            "headcount": headcount,
+        })
+    }
+
+    #[tokio::test]
+    async fn creates_and_lists_rooms() {
+        let api = api();
+        let id = create_room(&api, "Everest", "everest", 8).await;
+
+        let (status, body) = send(&api, "GET", &format!("/rooms/{id}"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["room_details"]["name"], "Everest");
+        assert_eq!(body["room_details"]["time_limit"], "01:30:00");
+
+        let (status, body) = send(&api, "GET", "/rooms", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["available_rooms"].as_array().unwrap().len(), 1);
+        assert_eq!(body["active_rooms"].as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicate_and_invalid_rooms() {
+        let api = api();
+        create_room(&api, "Everest", "everest", 8).await;
+
+        let (status, body) = send(
+            &api,
+            "POST",
+            "/rooms/new",
+            Some(new_room("Everest", "k2", 8)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "Room with same name exists");
+
+        let (status, body) = send(
+            &api,
+            "POST",
+            "/rooms/new",
+            Some(new_room("K2", "everest", 8)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "Room with same room id exists");
+
+        let mut room = new_room("K2", "k2", 8);
+        room["timezone"] = json!("Mars/Olympus");
+        let (status, _) = send(&api, "POST", "/rooms/new", Some(room)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn edits_rooms() {
+        let api = api();
+        let id = create_room(&api, "Everest", "everest", 8).await;
+        create_room(&api, "K2", "k2", 4).await;
+
+        let (status, body) = send(
+            &api,
+            "POST",
+            &format!("/rooms/edit/{id}"),
+            Some(new_room("Everest", "everest", 12)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["updated_room"]["capacity"], 12);
+
+        let (status, _) = send(
+            &api,
+            "POST",
+            &format!("/rooms/edit/{id}"),
+            Some(new_room("K2", "everest", 12)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn occupies_checks_in_and_frees_up_rooms() {
+        let api = api();
+        let id = create_room(&api, "Everest", "everest", 8).await;
+
+        let (status, body) =
+            send(&api, "POST", "/rooms/occupy", Some(occupancy(&id, Some(4)))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["room_details"]["meeting_title"], "Standup");
+        assert_eq!(body["room_details"]["checked_in"], false);
+
+        let (status, body) = send(&api, "POST", "/rooms/occupy", Some(occupancy(&id, None))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Room is already occupied, check selected room"
+        );
+
+        let (_, body) = send(&api, "GET", "/rooms/active", None).await;
+        assert_eq!(body["rooms"].as_array().unwrap().len(), 1);
+
+        let (status, _) = send(&api, "GET", &format!("/rooms/checkin/{id}"), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(&api, "GET", &format!("/rooms/checkin/{id}"), None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Meeting in this room is already checked in"
+        );
+
+        let (status, _) = send(&api, "GET", &format!("/rooms/freeup/{id}"), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(&api, "GET", &format!("/rooms/freeup/{id}"), None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "Room is not occupied, check selected room");
+
+        let (_, body) = send(&api, "GET", "/rooms/occupancies", None).await;
+        assert_eq!(body["occupancies"].as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_meetings_too_big_for_the_room() {
+        let api = api();
+        let id = create_room(&api, "Everest", "everest", 4).await;
+
+        let (status, body) =
+            send(&api, "POST", "/rooms/occupy", Some(occupancy(&id, Some(6)))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Room is too small for this meeting, check selected room"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_meetings_without_attendees() {
+        let api = api();
+        let everest = create_room(&api, "Everest", "everest", 8).await;
+        let k2 = create_room(&api, "K2", "k2", 4).await;
+
+        for headcount in [0, -3] {
+            let (status, body) = send(
+                &api,
+                "POST",
+                "/rooms/occupy",
+                Some(occupancy(&everest, Some(headcount))),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(
+                body["message"],
+                "Invalid headcount, a meeting needs at least 1 attendee"
+            );
+        }
+
+        send(
+            &api,
+            "POST",
+            "/rooms/occupy",
+            Some(occupancy(&everest, Some(3))),
+        )
+        .await;
+
+        let transfer = json!({ "from_room_id": everest, "to_room_id": k2, "headcount": 0 });
+        let (status, body) = send(&api, "POST", "/rooms/transfer", Some(transfer)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Invalid headcount, a meeting needs at least 1 attendee"
+        );
+    }
+
+    /// Only connects once a query runs, for requests turned down before that
+    fn unreachable_db() -> DBPool {
+        sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://db/zoomer")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rejects_heatmaps_over_a_year() {
+        let api = analytics_routes(unreachable_db()).recover(handle_rejection);
+
+        let (status, body) = send(
+            &api,
+            "GET",
+            "/analytics/heatmap?from=2024-01-01T00:00:00Z&to=2025-01-02T00:00:01Z&tag=video",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Date range too long, send at most 366 days"
+        );
+    }
+
+    #[tokio::test]
+    async fn blocks_rooms_during_maintenance() {
+        let api = api();
+        let id = create_room(&api, "Everest", "everest", 8).await;
+        let now = Utc::now();
+
+        let window = |room: &str, starts_at, ends_at| {
+            json!({
+                "room_id": room,
+                "starts_at": starts_at,
+                "ends_at": ends_at,
+                "reason": "New screen",
+            })
+        };
+
+        let (status, body) = send(
+            &api,
+            "POST",
+            "/rooms/maintenance/new",
+            Some(window(&id, now, now)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Maintenance window must end after it starts"
+        );
+
+        let (status, _) = send(
+            &api,
+            "POST",
+            "/rooms/maintenance/new",
+            Some(window(
+                "00000000-0000-0000-0000-000000000000",
+                now,
+                now + Duration::hours(2),
+            )),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = send(
+            &api,
+            "POST",
+            "/rooms/maintenance/new",
+            Some(window(
+                &id,
+                now - Duration::minutes(1),
+                now + Duration::hours(2),
+            )),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let window_id = body["maintenance_window"]["id"].as_i64().unwrap();
+
+        let (_, body) = send(&api, "GET", "/rooms/maintenance", None).await;
+        assert_eq!(body["maintenance_windows"].as_array().unwrap().len(), 1);
+
+        let (_, body) = send(&api, "GET", "/rooms", None).await;
+        assert_eq!(body["available_rooms"].as_array().unwrap().len(), 0);
+        assert_eq!(body["unavailable_rooms"][0]["id"], id.as_str());
+        assert_eq!(
+            body["unavailable_rooms"][0]["unavailable_reason"],
+            "New screen"
+        );
+
+        let (status, body) = send(&api, "POST", "/rooms/occupy", Some(occupancy(&id, None))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Room is under maintenance during this time, check selected room"
+        );
+
+        let remove = format!("/rooms/maintenance/remove/{window_id}");
+        let (status, _) = send(&api, "GET", &remove, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send(&api, "GET", &remove, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body["message"],
+            "Requested maintenance window does not exist"
+        );
+
+        let (status, _) = send(&api, "POST", "/rooms/occupy", Some(occupancy(&id, None))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn sets_opening_hours_by_weekday() {
+        let api = api();
+        let id = create_room(&api, "Everest", "everest", 8).await;
+        let path = format!("/rooms/hours/{id}");
+
+        let invalid = [
+            json!([{ "weekday": 7, "opens_at": "09:00:00", "closes_at": "17:00:00" }]),
+            json!([{ "weekday": 0, "opens_at": "17:00:00", "closes_at": "09:00:00" }]),
+        ];
+
+        for opening_hours in invalid {
+            let (status, body) = send(
+                &api,
+                "POST",
+                &path,
+                Some(json!({ "opening_hours": opening_hours })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(
+                body["message"],
+                "Invalid opening hours, weekday must be 0-6 and rooms must close after they open"
+            );
+        }
+
+        let opening_hours = json!([
+            { "weekday": 4, "opens_at": "09:00:00", "closes_at": "17:00:00" },
+            { "weekday": 0, "opens_at": "13:00:00", "closes_at": "17:00:00" },
+            { "weekday": 0, "opens_at": "08:00:00", "closes_at": "12:00:00" },
+        ]);
+        let (status, _) = send(
+            &api,
+            "POST",
+            &path,
+            Some(json!({ "opening_hours": opening_hours })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(&api, "GET", &path, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["timezone"], "Europe/Berlin");
+        let opens: Vec<_> = body["opening_hours"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hours| {
+                (
+                    hours["weekday"].as_i64().unwrap(),
+                    hours["opens_at"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            opens,
+            [
+                (0, json!("08:00:00")),
+                (0, json!("13:00:00")),
+                (4, json!("09:00:00"))
+            ]
+        );
+
+        let (status, _) = send(
+            &api,
+            "GET",
+            "/rooms/hours/00000000-0000-0000-0000-000000000000",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn blocks_rooms_on_holidays_at_their_location() {
+        let api = api();
+        let mut ids = vec![];
+
+        for (name, location) in [("Everest", "Berlin"), ("K2", "Pune")] {
+            let mut room = new_room(name, &name.to_lowercase(), 8);
+            room["location"] = json!(location);
+
+            let (status, body) = send(&api, "POST", "/rooms/new", Some(room)).await;
+            assert_eq!(status, StatusCode::OK);
+            ids.push(body["room_details"]["id"].as_str().unwrap().to_string());
+        }
+
+        let (berlin, pune) = (&ids[0], &ids[1]);
+        let today = Utc::now()
+            .with_timezone(&chrono_tz::Europe::Berlin)
+            .date_naive();
+
+        let (status, _) = send(&api, "POST", "/rooms/occupy", Some(occupancy(pune, None))).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let mut holiday_ids = vec![];
+
+        for (date, location) in [
+            (today.to_string(), json!("Berlin")),
+            (String::from("2000-01-01"), json!(null)),
+        ] {
+            let holiday = json!({ "date": date, "name": "Holiday", "location": location });
+
+            let (status, body) = send(&api, "POST", "/holidays/new", Some(holiday)).await;
+            assert_eq!(status, StatusCode::OK);
+            holiday_ids.push(body["holiday"]["id"].as_i64().unwrap());
+        }
+
+        // Past holidays aren't listed
+        let (_, body) = send(&api, "GET", "/holidays", None).await;
+        assert_eq!(body["holidays"].as_array().unwrap().len(), 1);
+        assert_eq!(body["holidays"][0]["location"], "Berlin");
+
+        let (status, body) =
+            send(&api, "POST", "/rooms/occupy", Some(occupancy(berlin, None))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Rooms can't be booked on a holiday, check the holiday calendar"
+        );
+
+        // The meeting in Pune only collides with holidays observed there
+        let collisions = format!("/holidays/collisions/{}", holiday_ids[0]);
+        let (_, body) = send(&api, "GET", &collisions, None).await;
+        assert_eq!(body["occupancies"].as_array().unwrap().len(), 0);
+
+        let holiday = json!({ "date": today, "name": "Holiday", "location": null });
+        let (_, body) = send(&api, "POST", "/holidays/new", Some(holiday)).await;
+        let global = body["holiday"]["id"].as_i64().unwrap();
+
+        let (_, body) = send(&api, "GET", &format!("/holidays/collisions/{global}"), None).await;
+        assert_eq!(body["occupancies"].as_array().unwrap().len(), 1);
+        assert_eq!(body["occupancies"][0]["occupied_room_id"], pune.as_str());
+
+        for id in [holiday_ids[0], global] {
+            let (status, _) = send(&api, "GET", &format!("/holidays/remove/{id}"), None).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let (status, body) = send(&api, "GET", &collisions, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["message"], "Requested holiday does not exist");
+
+        let (status, _) = send(&api, "POST", "/rooms/occupy", Some(occupancy(berlin, None))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_tags() {
+        let api = room_settings_routes(unreachable_db()).recover(handle_rejection);
+
+        for tags in [json!([""]), json!([" video"]), json!(["x".repeat(65)])] {
+            let (status, body) = send(
+                &api,
+                "POST",
+                "/rooms/tags/00000000-0000-0000-0000-000000000000",
+                Some(json!({ "tags": tags })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(
+                body["message"],
+                "Invalid tags, send tags of 1 to 64 characters without surrounding spaces"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn transfers_meetings() {
+        let api = api();
+        let everest = create_room(&api, "Everest", "everest", 8).await;
+        let k2 = create_room(&api, "K2", "k2", 4).await;
+        let denali = create_room(&api, "Denali", "denali", 10).await;
+
+        send(
+            &api,
+            "POST",
+            "/rooms/occupy",
+            Some(occupancy(&everest, None)),
+        )
+        .await;
+
+        // Without a headcount the whole source room has to fit
+        let transfer = json!({ "from_room_id": everest, "to_room_id": k2 });
+        let (status, _) = send(&api, "POST", "/rooms/transfer", Some(transfer)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let transfer = json!({ "from_room_id": everest, "to_room_id": denali });
+        let (status, body) = send(&api, "POST", "/rooms/transfer", Some(transfer)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["room_details"]["id"], denali.as_str());
+        assert_eq!(body["room_details"]["meeting_title"], "Standup");
+
+        let (_, body) = send(&api, "GET", "/rooms/available", None).await;
+        let available: Vec<&str> = body["rooms"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|room| room["id"].as_str().unwrap())
+            .collect();
+        assert!(available.contains(&everest.as_str()));
+        assert!(!available.contains(&denali.as_str()));
+    }
+
+    #[tokio::test]
+    async fn reports_missing_rooms() {
+        let api = api();
+        let missing = "7d2c5e58-3b3a-4d8e-9f55-4c1f1b8f0a11";
+
+        let (status, body) = send(&api, "GET", &format!("/rooms/{missing}"), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["message"], "Requested room does not exist");
+
+        let (status, _) = send(
+            &api,
+            "POST",
+            "/rooms/occupy",
+            Some(occupancy(missing, None)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = send(&api, "GET", &format!("/rooms/checkin/{missing}"), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn labels_request_metrics_by_route() {
+        let store: Store = Arc::new(MemoryRoomStore::default());
+        let api = metrics_route(store.clone(), None)
+            .or(rooms_routes(store))
+            .recover(handle_rejection);
+        create_room(&api, "Everest", "everest", 8).await;
+        let missing = "7d2c5e58-3b3a-4d8e-9f55-4c1f1b8f0a11";
+
+        let (status, _) = send(&api, "GET", &format!("/rooms/freeup/{missing}"), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let resp = warp::test::request().path("/metrics").reply(&api).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let metrics = String::from_utf8(resp.body().to_vec()).unwrap();
+
+        assert!(metrics.contains(
+            r#"zoomer_http_requests_total{method="GET",route="freeup_room",status="404"}"#
+        ));
+        assert!(metrics.contains(
+            r#"zoomer_http_requests_total{method="POST",route="new_room",status="200"}"#
+        ));
+        // Routes the request didn't match aren't counted
+        assert!(!metrics.contains(r#"route="all_rooms",status="404""#));
+        assert!(!metrics.contains(r#"status="405""#));
+        assert!(metrics.contains("zoomer_rooms_available 1"));
+    }
+
+    #[tokio::test]
+    async fn redirects_plain_http_to_https() {
+        let redirect = https_redirect_route(8443).recover(handle_rejection);
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/rooms/occupy?room=1")
+            .header("host", "zoomer.internal:8080")
+            .reply(&redirect)
+            .await;
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            resp.headers()["location"],
+            "https://zoomer.internal:8443/rooms/occupy?room=1"
+        );
+
+        let resp = warp::test::request()
+            .path("/rooms")
+            .reply(&https_redirect_route(443))
+            .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = warp::test::request()
+            .path("/rooms")
+            .header("host", "zoomer.internal")
+            .reply(&https_redirect_route(443))
+            .await;
+        assert_eq!(resp.headers()["location"], "https://zoomer.internal/rooms");
+    }
 }
@@ -0,0 +1,209 @@
+use chrono::{DateTime, Datelike, Utc};
+use chrono_tz::Tz;
+
+use crate::models::OpeningHours;
+
+/// Checks that `timezone`, if sent, is a valid IANA timezone name.
+pub fn is_valid_timezone(timezone: Option<&str>) -> bool {
+    match timezone {
+        Some(timezone) => timezone.parse::<Tz>().is_ok(),
+        None => true,
+    }
+}
+
+/// Checks if a meeting from `starts_at` until `ends_at` fits in one of the room's opening
+/// periods, compared in the room's local time. Rooms without opening hours are always open.
+pub fn is_within_opening_hours(
+    timezone: &str,
+    opening_hours: &[OpeningHours],
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+) -> bool {
+    if opening_hours.is_empty() {
+        return true;
+    }
+
+    let tz: Tz = match timezone.parse() {
+        Ok(tz) => tz,
+        Err(_) => return false,
+    };
+
+    let local_start = starts_at.with_timezone(&tz);
+    let local_end = ends_at.with_timezone(&tz);
+
+    if local_start.date_naive() != local_end.date_naive() {
+        return false;
+    }
+
+    let weekday = local_start.weekday().num_days_from_monday() as i16;
+
+    opening_hours.iter().any(|hours| {
+        hours.weekday == weekday
+            && hours.opens_at <= local_start.time()
+            && local_end.time() <= hours.closes_at
+    })
+}
+
+/// The date at `time` in the room's local timezone, falling back to UTC for unknown timezones.
+/// Postgres works this out in SQL, so only the other stores need it.
+#[cfg(any(test, feature = "sqlite"))]
+pub fn local_date(timezone: &str, time: DateTime<Utc>) -> chrono::NaiveDate {
+    let tz: Tz = timezone.parse().unwrap_or(Tz::UTC);
+
+    time.with_timezone(&tz).date_naive()
+}
+
+/// Formats `time` in the room's local timezone, falling back to UTC for unknown timezones.
+pub fn format_local_time(timezone: &str, time: DateTime<Utc>) -> String {
+    let tz: Tz = timezone.parse().unwrap_or(Tz::UTC);
+
+    time.with_timezone(&tz)
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveTime, TimeZone, Utc};
+
+    use super::{format_local_time, is_valid_timezone, is_within_opening_hours};
+    use crate::models::OpeningHours;
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    /// 2024-01-01 is a Monday
+    fn january(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    fn open(weekday: i16, opens_at: NaiveTime, closes_at: NaiveTime) -> OpeningHours {
+        OpeningHours {
+            weekday,
+            opens_at,
+            closes_at,
+        }
+    }
+
+    #[test]
+    fn checks_timezone_names() {
+        assert!(is_valid_timezone(None));
+        assert!(is_valid_timezone(Some("Europe/Berlin")));
+        assert!(is_valid_timezone(Some("UTC")));
+        assert!(!is_valid_timezone(Some("Mars/Olympus_Mons")));
+        assert!(!is_valid_timezone(Some("")));
+    }
+
+    #[test]
+    fn rooms_without_opening_hours_are_always_open() {
+        assert!(is_within_opening_hours(
+            "Europe/Berlin",
+            &[],
+            january(1, 23, 0),
+            january(2, 2, 0)
+        ));
+    }
+
+    #[test]
+    fn counts_weekdays_from_monday_in_local_time() {
+        let monday = [open(0, at(9, 0), at(17, 0))];
+        let tuesday = [open(1, at(9, 0), at(17, 0))];
+
+        // 09:00-10:00 in Berlin on Monday
+        let (starts_at, ends_at) = (january(1, 8, 0), january(1, 9, 0));
+        assert!(is_within_opening_hours(
+            "Europe/Berlin",
+            &monday,
+            starts_at,
+            ends_at
+        ));
+        assert!(!is_within_opening_hours(
+            "Europe/Berlin",
+            &tuesday,
+            starts_at,
+            ends_at
+        ));
+
+        // The same time in UTC is before opening
+        assert!(!is_within_opening_hours("UTC", &monday, starts_at, ends_at));
+
+        // Sunday is the last day of the week
+        let sunday = [open(6, at(9, 0), at(17, 0))];
+        assert!(is_within_opening_hours(
+            "UTC",
+            &sunday,
+            january(7, 9, 0),
+            january(7, 17, 0)
+        ));
+    }
+
+    #[test]
+    fn meetings_have_to_fit_in_one_opening_period() {
+        let hours = [open(0, at(9, 0), at(12, 0)), open(0, at(13, 0), at(17, 0))];
+
+        assert!(is_within_opening_hours(
+            "UTC",
+            &hours,
+            january(1, 13, 0),
+            january(1, 17, 0)
+        ));
+        assert!(!is_within_opening_hours(
+            "UTC",
+            &hours,
+            january(1, 11, 0),
+            january(1, 14, 0)
+        ));
+        assert!(!is_within_opening_hours(
+            "UTC",
+            &hours,
+            january(1, 16, 0),
+            january(1, 17, 30)
+        ));
+    }
+
+    #[test]
+    fn meetings_running_past_midnight_are_closed() {
+        let every_day: Vec<_> = (0..7)
+            .map(|weekday| {
+                open(
+                    weekday,
+                    at(0, 0),
+                    NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
+                )
+            })
+            .collect();
+
+        // 23:30 on Monday until 00:30 on Tuesday in Berlin, still Monday in UTC
+        let (starts_at, ends_at) = (january(1, 22, 30), january(1, 23, 30));
+        assert!(!is_within_opening_hours(
+            "Europe/Berlin",
+            &every_day,
+            starts_at,
+            ends_at
+        ));
+        assert!(is_within_opening_hours(
+            "UTC", &every_day, starts_at, ends_at
+        ));
+    }
+
+    #[test]
+    fn unknown_timezones_are_closed_but_formatted_in_utc() {
+        let hours = [open(0, at(0, 0), at(23, 0))];
+
+        assert!(!is_within_opening_hours(
+            "Mars/Olympus_Mons",
+            &hours,
+            january(1, 9, 0),
+            january(1, 10, 0)
+        ));
+        assert_eq!(
+            format_local_time("Mars/Olympus_Mons", january(1, 9, 0)),
+            "2024-01-01T09:00:00"
+        );
+        assert_eq!(
+            format_local_time("Europe/Berlin", january(1, 9, 0)),
+            "2024-01-01T10:00:00"
+        );
+    }
+}
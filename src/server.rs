@@ -0,0 +1,483 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::FutureExt;
+use hyper::{
+    header::HeaderValue,
+    server::{
+        accept::Accept,
+        conn::{AddrIncoming, AddrStream},
+    },
+    service::{make_service_fn, service_fn, Service},
+    Body, Request, Response, Server,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::oneshot,
+};
+use tokio_rustls::rustls::ServerConfig;
+use tracing::{field, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+use warp::{Filter, Reply};
+
+use crate::{
+    config::Config,
+    errors::handle_rejection,
+    routes::https_redirect_route,
+    telemetry,
+    tls::{self, CertResolver},
+};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Address of the client on the other end of the connection, in every request's extensions
+#[derive(Clone, Copy, Debug)]
+pub struct ClientAddr(pub SocketAddr);
+
+/// Connections that know the address of the client they are with
+pub trait RemoteAddr {
+    fn remote_addr(&self) -> SocketAddr;
+}
+
+impl RemoteAddr for AddrStream {
+    fn remote_addr(&self) -> SocketAddr {
+        AddrStream::remote_addr(self)
+    }
+}
+
+/// Serves `routes` as configured until SIGTERM or SIGINT, over TLS when there's a certificate,
+/// along with the redirect from plain HTTP when it's turned on. Returns whether every
+/// in-flight request finished, see `serve`.
+pub async fn run<F>(routes: F, config: &Config, certs: Option<Arc<CertResolver>>) -> bool
+where
+    F: Filter<Error = Infallible> + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let shutdown = shutdown_signal().shared();
+
+    let tls = certs.map(|certs| {
+        tokio::spawn(tls::reload_on_change(certs.clone(), tls::RELOAD_INTERVAL));
+
+        tls::server_config(certs)
+    });
+
+    if let Some(addr) = config.http_redirect_addr() {
+        let redirect = https_redirect_route(config.port).recover(handle_rejection);
+
+        tokio::spawn(serve(
+            redirect,
+            addr,
+            None,
+            shutdown.clone(),
+            config.shutdown_timeout(),
+        ));
+    }
+
+    serve(
+        routes,
+        config.addr(),
+        tls,
+        shutdown,
+        config.shutdown_timeout(),
+    )
+    .await
+}
+
+/// Serves `routes` on `addr` until `shutdown` completes, over TLS with `tls`. Every request
+/// runs in a span carrying its request id, so everything logged while answering it can be
+/// traced back to it.
+///
+/// On shutdown no new connections are accepted, and requests already being answered get up to
+/// `drain_timeout` to finish. Returns `false` when some were still running by then, they are
+/// dropped with the runtime, rolling back their transactions.
+pub async fn serve<F>(
+    routes: F,
+    addr: SocketAddr,
+    tls: Option<Arc<ServerConfig>>,
+    shutdown: impl Future<Output = ()>,
+    drain_timeout: Duration,
+) -> bool
+where
+    F: Filter<Error = Infallible> + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let incoming = match AddrIncoming::bind(&addr) {
+        Ok(incoming) => incoming,
+        Err(e) => {
+            tracing::error!(%addr, error = %e, "Failed to listen");
+            process::exit(1);
+        }
+    };
+
+    tracing::info!(%addr, tls = tls.is_some(), "Listening");
+
+    match tls {
+        Some(config) => {
+            serve_connections(
+                tls::accept(incoming, config),
+                routes,
+                shutdown,
+                drain_timeout,
+            )
+            .await
+        }
+        None => serve_connections(incoming, routes, shutdown, drain_timeout).await,
+    }
+}
+
+async fn serve_connections<I, F>(
+    incoming: I,
+    routes: F,
+    shutdown: impl Future<Output = ()>,
+    drain_timeout: Duration,
+) -> bool
+where
+    I: Accept,
+    I::Conn: AsyncRead + AsyncWrite + RemoteAddr + Unpin + Send + 'static,
+    I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    F: Filter<Error = Infallible> + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let service = warp::service(routes);
+
+    let make_service = make_service_fn(move |conn: &I::Conn| {
+        let service = service.clone();
+        let client_addr = ClientAddr(conn.remote_addr());
+
+        let service = service_fn(move |mut req: Request<Body>| {
+            req.extensions_mut().insert(client_addr);
+
+            handle_request(service.clone(), req)
+        });
+
+        async move { Ok::<_, Infallible>(service) }
+    });
+
+    let (drain_tx, drain_rx) = oneshot::channel::<()>();
+
+    let server = Server::builder(incoming)
+        .serve(make_service)
+        .with_graceful_shutdown(async {
+            drain_rx.await.ok();
+        });
+    tokio::pin!(server);
+
+    tokio::select! {
+        result = &mut server => {
+            if let Err(e) = result {
+                tracing::error!(error = %e, "Server failed");
+            }
+
+            return true;
+        }
+        _ = shutdown => (),
+    }
+
+    tracing::info!("Shutting down, waiting for in-flight requests");
+
+    drain_tx.send(()).ok();
+
+    match tokio::time::timeout(drain_timeout, server).await {
+        Ok(Ok(())) => {
+            tracing::info!("In-flight requests finished");
+
+            true
+        }
+        Ok(Err(e)) => {
+            tracing::error!(error = %e, "Server failed");
+
+            true
+        }
+        Err(_) => {
+            tracing::warn!(
+                timeout_secs = drain_timeout.as_secs(),
+                "Gave up waiting for in-flight requests"
+            );
+
+            false
+        }
+    }
+}
+
+/// Completes on the first SIGTERM or SIGINT
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "Failed to listen for SIGINT");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => tracing::info!(signal = "SIGINT", "Received shutdown signal"),
+        _ = terminate => tracing::info!(signal = "SIGTERM", "Received shutdown signal"),
+    }
+}
+
+/// Answers a request, echoing its request id back in the response and logging it once done
+pub async fn handle_request<S>(
+    mut service: S,
+    mut req: Request<Body>,
+) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let started = Instant::now();
+    let request_id = request_id(&req);
+
+    // Handlers see the same id the response carries
+    req.headers_mut()
+        .insert(REQUEST_ID_HEADER, request_id.clone());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = request_id.to_str().unwrap_or_default(),
+        method = %req.method(),
+        path = req.uri().path(),
+        route = field::Empty,
+        otel.name = %req.method(),
+        otel.kind = "server",
+        otel.status_code = field::Empty,
+        http.status_code = field::Empty,
+    );
+
+    // Continue the caller's trace when it sent a traceparent
+    span.set_parent(telemetry::remote_context(req.headers()));
+
+    let mut resp = service.call(req).instrument(span.clone()).await?;
+
+    resp.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+
+    span.record("http.status_code", resp.status().as_u16());
+
+    if resp.status().is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+
+    span.in_scope(|| {
+        tracing::info!(
+            status = resp.status().as_u16(),
+            latency_ms = (started.elapsed().as_secs_f64() * 1e6).round() / 1e3,
+            "Request finished"
+        );
+    });
+
+    Ok(resp)
+}
+
+/// The caller's request id when it sent a usable one, a new one otherwise
+fn request_id(req: &Request<Body>) -> HeaderValue {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .filter(|id| {
+            let id = id.as_bytes();
+
+            !id.is_empty() && id.len() <= 128 && id.iter().all(u8::is_ascii_graphic)
+        })
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_str(&Uuid::new_v4().to_string()).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use hyper::{Body, Request, StatusCode};
+    use opentelemetry::trace::SpanKind;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        sync::oneshot,
+        task::JoinHandle,
+    };
+    use warp::Filter;
+
+    use super::{handle_request, serve, REQUEST_ID_HEADER};
+    use crate::telemetry::record_spans;
+
+    fn service() -> impl hyper::service::Service<
+        Request<Body>,
+        Response = hyper::Response<Body>,
+        Error = std::convert::Infallible,
+    > + Clone {
+        let routes = warp::header::<String>(REQUEST_ID_HEADER).map(|id: String| id);
+
+        warp::service(routes.recover(crate::errors::handle_rejection))
+    }
+
+    #[tokio::test]
+    async fn propagates_request_ids() {
+        let req = Request::builder()
+            .header(REQUEST_ID_HEADER, "edge-1234")
+            .body(Body::empty())
+            .unwrap();
+
+        let resp = handle_request(service(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[REQUEST_ID_HEADER], "edge-1234");
+
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body, "edge-1234");
+    }
+
+    #[tokio::test]
+    async fn generates_missing_or_invalid_request_ids() {
+        for id in [None, Some(""), Some("has spaces")] {
+            let mut req = Request::builder();
+
+            if let Some(id) = id {
+                req = req.header(REQUEST_ID_HEADER, id);
+            }
+
+            let resp = handle_request(service(), req.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let generated = resp.headers()[REQUEST_ID_HEADER]
+                .to_str()
+                .unwrap()
+                .to_string();
+            assert!(uuid::Uuid::parse_str(&generated).is_ok());
+
+            // Handlers see the generated id too
+            let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(body, generated);
+        }
+    }
+
+    #[tokio::test]
+    async fn continues_the_callers_trace() {
+        let (exporter, provider, _guard) = record_spans();
+
+        let req = Request::builder()
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(Body::empty())
+            .unwrap();
+        handle_request(service(), req).await.unwrap();
+
+        provider.force_flush();
+        let spans = exporter.get_finished_spans().unwrap();
+        let request = spans
+            .iter()
+            .find(|span| span.span_kind == SpanKind::Server)
+            .unwrap();
+
+        assert_eq!(
+            request.span_context.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(request.parent_span_id.to_string(), "00f067aa0ba902b7");
+    }
+
+    /// Serves a `/slow` route taking `delay` to answer on a free port, until the returned
+    /// sender fires
+    async fn serve_slow_route(
+        delay: Duration,
+        drain_timeout: Duration,
+    ) -> (SocketAddr, oneshot::Sender<()>, JoinHandle<bool>) {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let routes = warp::path("slow")
+            .and_then(move || async move {
+                tokio::time::sleep(delay).await;
+
+                Ok::<_, warp::Rejection>("done")
+            })
+            .recover(crate::errors::handle_rejection);
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            routes,
+            addr,
+            None,
+            async {
+                shutdown_rx.await.ok();
+            },
+            drain_timeout,
+        ));
+
+        (addr, shutdown_tx, server)
+    }
+
+    /// Sends a request for `/slow` once the server is listening
+    async fn request_slow_route(addr: SocketAddr) -> TcpStream {
+        for _ in 0..50 {
+            if let Ok(mut stream) = TcpStream::connect(addr).await {
+                stream
+                    .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                    .await
+                    .unwrap();
+
+                return stream;
+            }
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        panic!("Server didn't start listening on {}", addr);
+    }
+
+    #[tokio::test]
+    async fn drains_in_flight_requests_on_shutdown() {
+        let (addr, shutdown, server) =
+            serve_slow_route(Duration::from_millis(300), Duration::from_secs(5)).await;
+
+        let mut stream = request_slow_route(addr).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.send(()).unwrap();
+
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).await.unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert!(resp.ends_with("done"));
+
+        assert!(server.await.unwrap());
+
+        // New connections are refused once it stopped
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stops_waiting_for_requests_after_the_drain_timeout() {
+        let (addr, shutdown, server) =
+            serve_slow_route(Duration::from_secs(60), Duration::from_millis(200)).await;
+
+        let _stream = request_slow_route(addr).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.send(()).unwrap();
+
+        let drained = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("Server kept waiting past the drain timeout")
+            .unwrap();
+        assert!(!drained);
+    }
+}
@@ -0,0 +1,481 @@
+#[cfg(test)]
+mod memory;
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::models::{
+    ActiveRoom, CurrentState, Holiday, MaintenanceWindow, NewHoliday, NewMaintenanceWindow,
+    NewOccupancy, NewRoom, Occupancy, OccupancyTransfer, OpeningHours, Room,
+};
+
+#[cfg(test)]
+pub use memory::MemoryRoomStore;
+pub use postgres::PgRoomStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteRoomStore;
+
+pub type Store = Arc<dyn RoomStore>;
+
+/// Why a store operation failed, turned into the matching rejection by `handle_rejection`
+#[derive(Debug)]
+pub enum StoreError {
+    RoomNotFound,
+    RoomWithNameExists,
+    RoomWithIdExists,
+    RoomOccupied,
+    RoomNotOccupied,
+    RoomAlreadyCheckedIn,
+    RoomTooSmall,
+    InvalidHeadcount,
+    RoomUnderMaintenance,
+    RoomClosed,
+    Holiday,
+    MaintenanceWindowNotFound,
+    HolidayNotFound,
+    Internal,
+}
+
+/// Rooms, their occupancies and the current state of every room, along with the maintenance
+/// windows, opening hours and holidays deciding when a room can be booked. Handlers for these
+/// only talk to the store through this trait, so they run against any backend. Reports and
+/// admin tools (analytics, exports, imports, backups, tags and no-show counts) query Postgres
+/// directly.
+#[async_trait]
+pub trait RoomStore: Send + Sync {
+    async fn current_state(&self) -> Result<CurrentState, StoreError>;
+
+    async fn available_rooms(&self) -> Result<Vec<Room>, StoreError>;
+
+    async fn active_rooms(&self) -> Result<Vec<ActiveRoom>, StoreError>;
+
+    async fn room(&self, id: Uuid) -> Result<Room, StoreError>;
+
+    async fn occupancies(&self) -> Result<Vec<Occupancy>, StoreError>;
+
+    async fn create_room(&self, room: NewRoom) -> Result<Room, StoreError>;
+
+    async fn update_room(&self, id: Uuid, room: NewRoom) -> Result<Room, StoreError>;
+
+    /// Starts a meeting in an available room, checking its capacity, maintenance windows,
+    /// opening hours and holidays first
+    async fn occupy_room(&self, occupancy: NewOccupancy) -> Result<ActiveRoom, StoreError>;
+
+    /// Ends the meeting in a room and moves it to the occupancy history
+    async fn free_room(&self, id: Uuid) -> Result<(), StoreError>;
+
+    async fn check_in_room(&self, id: Uuid) -> Result<(), StoreError>;
+
+    /// Moves a meeting to another room, with the same checks as `occupy_room` for the target
+    async fn transfer_room(&self, transfer: OccupancyTransfer) -> Result<ActiveRoom, StoreError>;
+
+    /// Ends the meetings which weren't checked in within `checkin_window` of their start and
+    /// records them as no-shows, returning how many were released
+    async fn release_no_shows(&self, checkin_window: Duration) -> Result<u64, StoreError>;
+
+    /// Maintenance windows which haven't ended yet, the soonest first
+    async fn maintenance_windows(&self) -> Result<Vec<MaintenanceWindow>, StoreError>;
+
+    async fn create_maintenance_window(
+        &self,
+        window: NewMaintenanceWindow,
+    ) -> Result<MaintenanceWindow, StoreError>;
+
+    async fn remove_maintenance_window(&self, id: i32) -> Result<(), StoreError>;
+
+    /// The room's timezone and its opening hours, sorted by weekday and opening time
+    async fn opening_hours(&self, room_id: Uuid)
+        -> Result<(String, Vec<OpeningHours>), StoreError>;
+
+    /// Replaces the opening hours of a room, no opening hours at all keeps it always open
+    async fn set_opening_hours(
+        &self,
+        room_id: Uuid,
+        opening_hours: &[OpeningHours],
+    ) -> Result<(), StoreError>;
+
+    /// Holidays from today on, by date
+    async fn holidays(&self) -> Result<Vec<Holiday>, StoreError>;
+
+    async fn create_holiday(&self, holiday: NewHoliday) -> Result<Holiday, StoreError>;
+
+    async fn remove_holiday(&self, id: i32) -> Result<(), StoreError>;
+
+    /// Meetings which haven't ended yet and run into the holiday, in their room's local time
+    async fn holiday_collisions(&self, id: i32) -> Result<Vec<Occupancy>, StoreError>;
+}
+
+/// A meeting's expected headcount, when sent, has to count at least one attendee
+pub fn is_valid_headcount(headcount: Option<i32>) -> bool {
+    !matches!(headcount, Some(headcount) if headcount < 1)
+}
+
+/// Builds the state of a room with a meeting running in it
+pub fn active_room(room: Room, occupancy: &Occupancy) -> ActiveRoom {
+    ActiveRoom {
+        occupied_until_local: crate::schedule::format_local_time(
+            &room.timezone,
+            occupancy.occupied_until,
+        ),
+        id: room.id,
+        name: room.name,
+        room_id: room.room_id,
+        capacity: room.capacity,
+        time_limit: room.time_limit,
+        link: room.link,
+        comments: room.comments,
+        timezone: room.timezone,
+        location: room.location,
+        is_active: true,
+        occupied_until: occupancy.occupied_until,
+        meeting_title: occupancy.meeting_title.clone(),
+        meeting_comments: occupancy.comments.clone(),
+        checked_in: occupancy.checked_in,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{Datelike, NaiveTime, Utc};
+    use uuid::Uuid;
+
+    use super::{MemoryRoomStore, RoomStore, StoreError};
+    use crate::models::{
+        NewHoliday, NewMaintenanceWindow, NewOccupancy, NewRoom, OccupancyTransfer, OpeningHours,
+    };
+
+    /// Every store the tests run against, SQLite only with the `sqlite` feature
+    async fn stores() -> Vec<Box<dyn RoomStore>> {
+        #[allow(unused_mut)]
+        let mut stores: Vec<Box<dyn RoomStore>> = vec![Box::new(MemoryRoomStore::default())];
+
+        #[cfg(feature = "sqlite")]
+        {
+            let (sqlite, _) = super::SqliteRoomStore::connect("sqlite::memory:")
+                .await
+                .unwrap();
+            stores.push(Box::new(sqlite));
+        }
+
+        stores
+    }
+
+    async fn create_room(store: &dyn RoomStore, name: &str, capacity: i32) -> Uuid {
+        let room = NewRoom {
+            name: name.to_string(),
+            room_id: name.to_lowercase(),
+            capacity,
+            time_limit: 90,
+            link: String::from("https://zoom.us/j/1"),
+            comments: String::new(),
+            timezone: None,
+            location: None,
+        };
+
+        store.create_room(room).await.unwrap().id
+    }
+
+    fn occupancy(room: Uuid, headcount: Option<i32>) -> NewOccupancy {
+        NewOccupancy {
+            occupied_room_id: room,
+            occupied_until: Utc::now() + chrono::Duration::hours(1),
+            meeting_title: String::from("Standup"),
+            comments: String::new(),
+            headcount,
+            booked_by: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn releases_meetings_not_checked_in_in_time() {
+        for store in stores().await {
+            let store = store.as_ref();
+            let everest = create_room(store, "Everest", 8).await;
+            let k2 = create_room(store, "K2", 8).await;
+
+            store.occupy_room(occupancy(everest, None)).await.unwrap();
+            store.occupy_room(occupancy(k2, None)).await.unwrap();
+            store.check_in_room(k2).await.unwrap();
+
+            // Both meetings are still within the window
+            let released = store.release_no_shows(Duration::from_secs(600)).await;
+            assert_eq!(released.unwrap(), 0);
+
+            tokio::time::sleep(Duration::from_millis(5)).await;
+
+            let released = store.release_no_shows(Duration::ZERO).await;
+            assert_eq!(released.unwrap(), 1);
+
+            let available = store.available_rooms().await.unwrap();
+            assert_eq!(available.len(), 1);
+            assert_eq!(available[0].id, everest);
+
+            // A released meeting can't be checked in anymore, the checked in one stays
+            assert!(matches!(
+                store.check_in_room(everest).await,
+                Err(StoreError::RoomNotOccupied)
+            ));
+            assert!(matches!(
+                store.check_in_room(k2).await,
+                Err(StoreError::RoomAlreadyCheckedIn)
+            ));
+
+            let released = store.release_no_shows(Duration::ZERO).await;
+            assert_eq!(released.unwrap(), 0);
+        }
+    }
+
+    fn transfer(from: Uuid, to: Uuid, headcount: Option<i32>) -> OccupancyTransfer {
+        OccupancyTransfer {
+            from_room_id: from,
+            to_room_id: to,
+            headcount,
+        }
+    }
+
+    #[tokio::test]
+    async fn transfers_fit_the_headcount_or_the_source_room() {
+        for store in stores().await {
+            let store = store.as_ref();
+            let everest = create_room(store, "Everest", 10).await;
+            let k2 = create_room(store, "K2", 6).await;
+            let denali = create_room(store, "Denali", 4).await;
+
+            // Without any headcount the whole source room has to fit
+            store.occupy_room(occupancy(everest, None)).await.unwrap();
+            assert!(matches!(
+                store.transfer_room(transfer(everest, k2, None)).await,
+                Err(StoreError::RoomTooSmall)
+            ));
+
+            // A headcount sent with the transfer is used, and kept for the next one
+            let moved = store
+                .transfer_room(transfer(everest, k2, Some(5)))
+                .await
+                .unwrap();
+            assert_eq!(moved.id, k2);
+            assert!(matches!(
+                store.transfer_room(transfer(k2, denali, None)).await,
+                Err(StoreError::RoomTooSmall)
+            ));
+
+            // A smaller headcount overrides the recorded one
+            let moved = store
+                .transfer_room(transfer(k2, denali, Some(4)))
+                .await
+                .unwrap();
+            assert_eq!(moved.id, denali);
+            assert_eq!(store.occupancies().await.unwrap()[0].headcount, Some(4));
+
+            // The recorded headcount is used when none is sent
+            let moved = store
+                .transfer_room(transfer(denali, everest, None))
+                .await
+                .unwrap();
+            assert_eq!(moved.id, everest);
+
+            let available: Vec<Uuid> = store
+                .available_rooms()
+                .await
+                .unwrap()
+                .iter()
+                .map(|room| room.id)
+                .collect();
+            assert_eq!(available.len(), 2);
+            assert!(!available.contains(&everest));
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_transfers_to_busy_or_missing_rooms() {
+        for store in stores().await {
+            let store = store.as_ref();
+            let everest = create_room(store, "Everest", 8).await;
+            let k2 = create_room(store, "K2", 8).await;
+
+            assert!(matches!(
+                store.transfer_room(transfer(everest, k2, Some(2))).await,
+                Err(StoreError::RoomNotOccupied)
+            ));
+
+            store
+                .occupy_room(occupancy(everest, Some(2)))
+                .await
+                .unwrap();
+            store.occupy_room(occupancy(k2, Some(2))).await.unwrap();
+
+            for to in [k2, everest] {
+                assert!(matches!(
+                    store.transfer_room(transfer(everest, to, None)).await,
+                    Err(StoreError::RoomOccupied)
+                ));
+            }
+
+            assert!(matches!(
+                store
+                    .transfer_room(transfer(everest, Uuid::new_v4(), None))
+                    .await,
+                Err(StoreError::RoomNotFound)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn only_books_rooms_free_of_maintenance_closing_and_holidays() {
+        for store in stores().await {
+            let store = store.as_ref();
+            let everest = create_room(store, "Everest", 8).await;
+            let now = Utc::now();
+
+            let window = store
+                .create_maintenance_window(NewMaintenanceWindow {
+                    room_id: everest,
+                    starts_at: now + chrono::Duration::minutes(30),
+                    ends_at: now + chrono::Duration::hours(2),
+                    reason: String::from("New screen"),
+                })
+                .await
+                .unwrap();
+            assert!(matches!(
+                store.occupy_room(occupancy(everest, None)).await,
+                Err(StoreError::RoomUnderMaintenance)
+            ));
+            store.remove_maintenance_window(window.id).await.unwrap();
+
+            // Only open tomorrow
+            let tomorrow = (now.weekday().num_days_from_monday() as i16 + 1) % 7;
+            let opening_hours = [OpeningHours {
+                weekday: tomorrow,
+                opens_at: NaiveTime::MIN,
+                closes_at: NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
+            }];
+            store
+                .set_opening_hours(everest, &opening_hours)
+                .await
+                .unwrap();
+            assert!(matches!(
+                store.occupy_room(occupancy(everest, None)).await,
+                Err(StoreError::RoomClosed)
+            ));
+            store.set_opening_hours(everest, &[]).await.unwrap();
+
+            let holiday = store
+                .create_holiday(NewHoliday {
+                    date: now.date_naive(),
+                    name: String::from("Founders day"),
+                    location: None,
+                })
+                .await
+                .unwrap();
+            assert!(matches!(
+                store.occupy_room(occupancy(everest, None)).await,
+                Err(StoreError::Holiday)
+            ));
+            store.remove_holiday(holiday.id).await.unwrap();
+
+            store.occupy_room(occupancy(everest, None)).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_maintenance_windows_opening_hours_and_holidays() {
+        for store in stores().await {
+            let store = store.as_ref();
+            let everest = create_room(store, "Everest", 8).await;
+            let k2 = create_room(store, "K2", 8).await;
+            let now = Utc::now();
+
+            assert_eq!(store.room(everest).await.unwrap().time_limit, "01:30:00");
+
+            for (starts_at, ends_at) in [(-2, -1), (-1, 2)] {
+                let window = NewMaintenanceWindow {
+                    room_id: everest,
+                    starts_at: now + chrono::Duration::hours(starts_at),
+                    ends_at: now + chrono::Duration::hours(ends_at),
+                    reason: String::from("New screen"),
+                };
+                store.create_maintenance_window(window).await.unwrap();
+            }
+
+            // Only the window which hasn't ended yet is listed, and it takes the room out
+            let windows = store.maintenance_windows().await.unwrap();
+            assert_eq!(windows.len(), 1);
+            assert_eq!(windows[0].id, 2);
+
+            let state = store.current_state().await.unwrap();
+            assert_eq!(state.available_rooms.len(), 1);
+            assert_eq!(state.unavailable_rooms.len(), 1);
+            assert_eq!(state.unavailable_rooms[0].id, everest);
+            assert_eq!(state.unavailable_rooms[0].unavailable_reason, "New screen");
+
+            assert!(matches!(
+                store.remove_maintenance_window(3).await,
+                Err(StoreError::MaintenanceWindowNotFound)
+            ));
+
+            let hours = |weekday, opens_at| OpeningHours {
+                weekday,
+                opens_at: NaiveTime::from_hms_opt(opens_at, 0, 0).unwrap(),
+                closes_at: NaiveTime::from_hms_opt(opens_at + 3, 0, 0).unwrap(),
+            };
+            store
+                .set_opening_hours(k2, &[hours(4, 9), hours(0, 13), hours(0, 8)])
+                .await
+                .unwrap();
+
+            let (timezone, opening_hours) = store.opening_hours(k2).await.unwrap();
+            assert_eq!(timezone, "UTC");
+            let opening_hours: Vec<_> = opening_hours
+                .iter()
+                .map(|hours| (hours.weekday, hours.opens_at))
+                .collect();
+            assert_eq!(
+                opening_hours,
+                [hours(0, 8), hours(0, 13), hours(4, 9)]
+                    .map(|hours| (hours.weekday, hours.opens_at))
+            );
+
+            store.set_opening_hours(k2, &[]).await.unwrap();
+            store.occupy_room(occupancy(k2, None)).await.unwrap();
+
+            // Rooms without a location only observe holidays without one
+            let mut holidays = vec![];
+
+            for location in [Some("Pune"), None] {
+                let holiday = NewHoliday {
+                    date: now.date_naive(),
+                    name: String::from("Founders day"),
+                    location: location.map(String::from),
+                };
+                holidays.push(store.create_holiday(holiday).await.unwrap().id);
+            }
+
+            assert_eq!(store.holidays().await.unwrap().len(), 2);
+            assert!(store
+                .holiday_collisions(holidays[0])
+                .await
+                .unwrap()
+                .is_empty());
+
+            let collisions = store.holiday_collisions(holidays[1]).await.unwrap();
+            assert_eq!(collisions.len(), 1);
+            assert_eq!(collisions[0].occupied_room_id, k2);
+
+            assert!(matches!(
+                store.holiday_collisions(3).await,
+                Err(StoreError::HolidayNotFound)
+            ));
+            assert!(matches!(
+                store.remove_holiday(3).await,
+                Err(StoreError::HolidayNotFound)
+            ));
+        }
+    }
+}
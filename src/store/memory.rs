@@ -0,0 +1,582 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{active_room, is_valid_headcount, RoomStore, StoreError};
+use crate::{
+    models::{
+        ActiveRoom, CurrentState, Holiday, MaintenanceWindow, NewHoliday, NewMaintenanceWindow,
+        NewOccupancy, NewRoom, Occupancy, OccupancyTransfer, OpeningHours, Room, UnavailableRoom,
+    },
+    schedule::{is_within_opening_hours, local_date},
+};
+
+/// A room store which keeps everything in memory, used by the tests to run the routes without
+/// a database. Maintenance windows, opening hours and holidays are checked like in Postgres.
+/// Rooms are only archived by `sync`, which needs Postgres, so none are archived here, and
+/// ended meetings are dropped instead of being kept in an occupancy history.
+#[derive(Default)]
+pub struct MemoryRoomStore {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    rooms: Vec<Room>,
+    occupancies: Vec<Occupancy>,
+    maintenance_windows: Vec<MaintenanceWindow>,
+    opening_hours: HashMap<Uuid, Vec<OpeningHours>>,
+    holidays: Vec<Holiday>,
+    next_occupancy_id: i32,
+    next_maintenance_window_id: i32,
+    next_holiday_id: i32,
+}
+
+impl MemoryState {
+    fn room(&self, id: Uuid) -> Option<&Room> {
+        self.rooms.iter().find(|room| room.id == id)
+    }
+
+    fn occupancy(&self, room_id: Uuid) -> Option<&Occupancy> {
+        self.occupancies
+            .iter()
+            .find(|occupancy| occupancy.occupied_room_id == room_id)
+    }
+
+    fn end_occupancy(&mut self, room_id: Uuid) -> Option<Occupancy> {
+        let index = self
+            .occupancies
+            .iter()
+            .position(|occupancy| occupancy.occupied_room_id == room_id)?;
+
+        Some(self.occupancies.remove(index))
+    }
+
+    fn check_room_conflicts(
+        &self,
+        room: &NewRoom,
+        room_id: Option<Uuid>,
+    ) -> Result<(), StoreError> {
+        let others = self.rooms.iter().filter(|other| Some(other.id) != room_id);
+
+        for other in others {
+            if other.name == room.name {
+                return Err(StoreError::RoomWithNameExists);
+            } else if other.room_id == room.room_id {
+                return Err(StoreError::RoomWithIdExists);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The maintenance window the room is in at `time`, the one ending last if they overlap
+    fn maintenance_window(&self, room_id: Uuid, time: DateTime<Utc>) -> Option<&MaintenanceWindow> {
+        self.maintenance_windows
+            .iter()
+            .filter(|window| {
+                window.room_id == room_id && window.starts_at <= time && time < window.ends_at
+            })
+            .max_by_key(|window| window.ends_at)
+    }
+
+    /// Fails if the room can't be booked until `until` because of maintenance, its opening
+    /// hours or a holiday
+    fn check_room_bookable(&self, room: &Room, until: DateTime<Utc>) -> Result<(), StoreError> {
+        let now = Utc::now();
+
+        let under_maintenance = self.maintenance_windows.iter().any(|window| {
+            window.room_id == room.id && window.starts_at < until && window.ends_at > now
+        });
+
+        if under_maintenance {
+            return Err(StoreError::RoomUnderMaintenance);
+        }
+
+        let opening_hours = self
+            .opening_hours
+            .get(&room.id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        if !is_within_opening_hours(&room.timezone, opening_hours, now, until) {
+            return Err(StoreError::RoomClosed);
+        }
+
+        let starts_on = local_date(&room.timezone, now);
+        let ends_on = local_date(&room.timezone, until);
+
+        let is_holiday = self.holidays.iter().any(|holiday| {
+            is_holiday_at(holiday, room) && starts_on <= holiday.date && holiday.date <= ends_on
+        });
+
+        if is_holiday {
+            return Err(StoreError::Holiday);
+        }
+
+        Ok(())
+    }
+
+    fn add_occupancy(&mut self, occupancy: Occupancy) -> Occupancy {
+        self.next_occupancy_id += 1;
+
+        let occupancy = Occupancy {
+            id: self.next_occupancy_id,
+            ..occupancy
+        };
+        self.occupancies.push(occupancy.clone());
+
+        occupancy
+    }
+}
+
+#[async_trait]
+impl RoomStore for MemoryRoomStore {
+    async fn current_state(&self) -> Result<CurrentState, StoreError> {
+        let available_rooms = self.available_rooms().await?;
+        let active_rooms = self.active_rooms().await?;
+
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+
+        let unavailable_rooms = state
+            .rooms
+            .iter()
+            .filter(|room| state.occupancy(room.id).is_none())
+            .filter_map(|room| {
+                let window = state.maintenance_window(room.id, now)?;
+
+                Some(UnavailableRoom {
+                    id: room.id,
+                    name: room.name.clone(),
+                    room_id: room.room_id.clone(),
+                    capacity: room.capacity,
+                    time_limit: room.time_limit.clone(),
+                    link: room.link.clone(),
+                    comments: room.comments.clone(),
+                    timezone: room.timezone.clone(),
+                    location: room.location.clone(),
+                    unavailable_until: window.ends_at,
+                    unavailable_reason: window.reason.clone(),
+                })
+            })
+            .collect();
+
+        Ok(CurrentState {
+            available_rooms,
+            active_rooms,
+            unavailable_rooms,
+        })
+    }
+
+    async fn available_rooms(&self) -> Result<Vec<Room>, StoreError> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+
+        let rooms = state
+            .rooms
+            .iter()
+            .filter(|room| {
+                state.occupancy(room.id).is_none()
+                    && state.maintenance_window(room.id, now).is_none()
+            })
+            .cloned()
+            .collect();
+
+        Ok(rooms)
+    }
+
+    async fn active_rooms(&self) -> Result<Vec<ActiveRoom>, StoreError> {
+        let state = self.state.lock().unwrap();
+
+        let rooms = state
+            .occupancies
+            .iter()
+            .filter_map(|occupancy| {
+                state
+                    .room(occupancy.occupied_room_id)
+                    .map(|room| active_room(room.clone(), occupancy))
+            })
+            .collect();
+
+        Ok(rooms)
+    }
+
+    async fn room(&self, id: Uuid) -> Result<Room, StoreError> {
+        let state = self.state.lock().unwrap();
+
+        state.room(id).cloned().ok_or(StoreError::RoomNotFound)
+    }
+
+    async fn occupancies(&self) -> Result<Vec<Occupancy>, StoreError> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state.occupancies.to_vec())
+    }
+
+    async fn create_room(&self, room: NewRoom) -> Result<Room, StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        state.check_room_conflicts(&room, None)?;
+
+        let room = Room {
+            id: Uuid::new_v4(),
+            name: room.name,
+            room_id: room.room_id,
+            capacity: room.capacity,
+            time_limit: format_time_limit(room.time_limit),
+            link: room.link,
+            comments: room.comments,
+            timezone: room.timezone.unwrap_or_else(|| "UTC".to_string()),
+            location: room.location,
+        };
+
+        state.rooms.push(room.clone());
+
+        Ok(room)
+    }
+
+    async fn update_room(&self, id: Uuid, room: NewRoom) -> Result<Room, StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.room(id).is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        state.check_room_conflicts(&room, Some(id))?;
+
+        let stored = state
+            .rooms
+            .iter_mut()
+            .find(|room| room.id == id)
+            .ok_or(StoreError::RoomNotFound)?;
+
+        *stored = Room {
+            id,
+            name: room.name,
+            room_id: room.room_id,
+            capacity: room.capacity,
+            time_limit: format_time_limit(room.time_limit),
+            link: room.link,
+            comments: room.comments,
+            timezone: room.timezone.unwrap_or_else(|| stored.timezone.clone()),
+            location: room.location,
+        };
+
+        Ok(stored.clone())
+    }
+
+    async fn occupy_room(&self, occupancy: NewOccupancy) -> Result<ActiveRoom, StoreError> {
+        if !is_valid_headcount(occupancy.headcount) {
+            return Err(StoreError::InvalidHeadcount);
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.occupancy(occupancy.occupied_room_id).is_some() {
+            return Err(StoreError::RoomOccupied);
+        }
+
+        let room = match state.room(occupancy.occupied_room_id) {
+            Some(room) => room.clone(),
+            None => return Err(StoreError::RoomNotFound),
+        };
+
+        match occupancy.headcount {
+            Some(headcount) if headcount > room.capacity => return Err(StoreError::RoomTooSmall),
+            _ => (),
+        }
+
+        state.check_room_bookable(&room, occupancy.occupied_until)?;
+
+        let occupancy = state.add_occupancy(Occupancy {
+            id: 0,
+            occupied_room_id: occupancy.occupied_room_id,
+            occupied_until: occupancy.occupied_until,
+            meeting_title: occupancy.meeting_title,
+            comments: occupancy.comments,
+            started_at: Utc::now(),
+            checked_in: false,
+            headcount: occupancy.headcount,
+            booked_by: occupancy.booked_by,
+        });
+
+        Ok(active_room(room, &occupancy))
+    }
+
+    async fn free_room(&self, id: Uuid) -> Result<(), StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.room(id).is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        match state.end_occupancy(id) {
+            Some(_) => Ok(()),
+            None => Err(StoreError::RoomNotOccupied),
+        }
+    }
+
+    async fn check_in_room(&self, id: Uuid) -> Result<(), StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.room(id).is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        let occupancy = state
+            .occupancies
+            .iter_mut()
+            .find(|occupancy| occupancy.occupied_room_id == id)
+            .ok_or(StoreError::RoomNotOccupied)?;
+
+        if occupancy.checked_in {
+            return Err(StoreError::RoomAlreadyCheckedIn);
+        }
+
+        occupancy.checked_in = true;
+
+        Ok(())
+    }
+
+    async fn transfer_room(&self, transfer: OccupancyTransfer) -> Result<ActiveRoom, StoreError> {
+        if !is_valid_headcount(transfer.headcount) {
+            return Err(StoreError::InvalidHeadcount);
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        let source_room = match state.room(transfer.from_room_id) {
+            Some(room) => room.clone(),
+            None => return Err(StoreError::RoomNotFound),
+        };
+
+        if transfer.to_room_id == source_room.id {
+            return Err(StoreError::RoomOccupied);
+        }
+
+        let target_room = match state.room(transfer.to_room_id) {
+            Some(room) => room.clone(),
+            None => return Err(StoreError::RoomNotFound),
+        };
+
+        if state.occupancy(target_room.id).is_some() {
+            return Err(StoreError::RoomOccupied);
+        }
+
+        let ended_occupancy = match state.occupancy(source_room.id) {
+            Some(occupancy) => occupancy.clone(),
+            None => return Err(StoreError::RoomNotOccupied),
+        };
+
+        let headcount = transfer
+            .headcount
+            .or(ended_occupancy.headcount)
+            .unwrap_or(source_room.capacity);
+
+        if target_room.capacity < headcount {
+            return Err(StoreError::RoomTooSmall);
+        }
+
+        state.check_room_bookable(&target_room, ended_occupancy.occupied_until)?;
+
+        state.end_occupancy(source_room.id);
+
+        let occupancy = state.add_occupancy(Occupancy {
+            id: 0,
+            occupied_room_id: target_room.id,
+            started_at: Utc::now(),
+            headcount: transfer.headcount.or(ended_occupancy.headcount),
+            ..ended_occupancy
+        });
+
+        Ok(active_room(target_room, &occupancy))
+    }
+
+    async fn release_no_shows(&self, checkin_window: Duration) -> Result<u64, StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        let checkin_window =
+            chrono::Duration::from_std(checkin_window).map_err(|_| StoreError::Internal)?;
+        let now = Utc::now();
+        let before = state.occupancies.len();
+
+        state.occupancies.retain(|occupancy| {
+            occupancy.checked_in || occupancy.started_at + checkin_window >= now
+        });
+
+        Ok((before - state.occupancies.len()) as u64)
+    }
+
+    async fn maintenance_windows(&self) -> Result<Vec<MaintenanceWindow>, StoreError> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+
+        let mut windows: Vec<_> = state
+            .maintenance_windows
+            .iter()
+            .filter(|window| window.ends_at > now)
+            .cloned()
+            .collect();
+        windows.sort_by_key(|window| window.starts_at);
+
+        Ok(windows)
+    }
+
+    async fn create_maintenance_window(
+        &self,
+        window: NewMaintenanceWindow,
+    ) -> Result<MaintenanceWindow, StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.room(window.room_id).is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        state.next_maintenance_window_id += 1;
+
+        let window = MaintenanceWindow {
+            id: state.next_maintenance_window_id,
+            room_id: window.room_id,
+            starts_at: window.starts_at,
+            ends_at: window.ends_at,
+            reason: window.reason,
+        };
+        state.maintenance_windows.push(window.clone());
+
+        Ok(window)
+    }
+
+    async fn remove_maintenance_window(&self, id: i32) -> Result<(), StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        let index = state
+            .maintenance_windows
+            .iter()
+            .position(|window| window.id == id)
+            .ok_or(StoreError::MaintenanceWindowNotFound)?;
+        state.maintenance_windows.remove(index);
+
+        Ok(())
+    }
+
+    async fn opening_hours(
+        &self,
+        room_id: Uuid,
+    ) -> Result<(String, Vec<OpeningHours>), StoreError> {
+        let state = self.state.lock().unwrap();
+
+        let room = state.room(room_id).ok_or(StoreError::RoomNotFound)?;
+
+        let mut opening_hours = state
+            .opening_hours
+            .get(&room_id)
+            .cloned()
+            .unwrap_or_default();
+        opening_hours.sort_by_key(|hours| (hours.weekday, hours.opens_at));
+
+        Ok((room.timezone.clone(), opening_hours))
+    }
+
+    async fn set_opening_hours(
+        &self,
+        room_id: Uuid,
+        opening_hours: &[OpeningHours],
+    ) -> Result<(), StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.room(room_id).is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        state.opening_hours.insert(room_id, opening_hours.to_vec());
+
+        Ok(())
+    }
+
+    async fn holidays(&self) -> Result<Vec<Holiday>, StoreError> {
+        let state = self.state.lock().unwrap();
+        let today = Utc::now().date_naive();
+
+        let mut holidays: Vec<_> = state
+            .holidays
+            .iter()
+            .filter(|holiday| holiday.date >= today)
+            .cloned()
+            .collect();
+        holidays.sort_by_key(|holiday| holiday.date);
+
+        Ok(holidays)
+    }
+
+    async fn create_holiday(&self, holiday: NewHoliday) -> Result<Holiday, StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        state.next_holiday_id += 1;
+
+        let holiday = Holiday {
+            id: state.next_holiday_id,
+            date: holiday.date,
+            name: holiday.name,
+            location: holiday.location,
+        };
+        state.holidays.push(holiday.clone());
+
+        Ok(holiday)
+    }
+
+    async fn remove_holiday(&self, id: i32) -> Result<(), StoreError> {
+        let mut state = self.state.lock().unwrap();
+
+        let index = state
+            .holidays
+            .iter()
+            .position(|holiday| holiday.id == id)
+            .ok_or(StoreError::HolidayNotFound)?;
+        state.holidays.remove(index);
+
+        Ok(())
+    }
+
+    async fn holiday_collisions(&self, id: i32) -> Result<Vec<Occupancy>, StoreError> {
+        let state = self.state.lock().unwrap();
+
+        let holiday = state
+            .holidays
+            .iter()
+            .find(|holiday| holiday.id == id)
+            .ok_or(StoreError::HolidayNotFound)?;
+        let now = Utc::now();
+
+        let occupancies = state
+            .occupancies
+            .iter()
+            .filter(|occupancy| {
+                let room = match state.room(occupancy.occupied_room_id) {
+                    Some(room) => room,
+                    None => return false,
+                };
+
+                is_holiday_at(holiday, room)
+                    && occupancy.occupied_until > now
+                    && local_date(&room.timezone, occupancy.started_at) <= holiday.date
+                    && holiday.date <= local_date(&room.timezone, occupancy.occupied_until)
+            })
+            .cloned()
+            .collect();
+
+        Ok(occupancies)
+    }
+}
+
+/// Holidays without a location are observed everywhere, the others only at their location
+fn is_holiday_at(holiday: &Holiday, room: &Room) -> bool {
+    holiday.location.is_none() || holiday.location == room.location
+}
+
+/// Formats a time limit given in minutes the way Postgres formats the `time_limit` interval
+fn format_time_limit(minutes: u64) -> String {
+    format!("{:02}:{:02}:00", minutes / 60, minutes % 60)
+}
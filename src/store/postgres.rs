@@ -0,0 +1,930 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use super::{active_room, is_valid_headcount, RoomStore, StoreError};
+use crate::{
+    models::{
+        ActiveRoom, CurrentState, Holiday, MaintenanceWindow, NewHoliday, NewMaintenanceWindow,
+        NewOccupancy, NewRoom, Occupancy, OccupancyTransfer, OpeningHours, Room, UnavailableRoom,
+    },
+    schedule::is_within_opening_hours,
+    telemetry::traced,
+    DBPool,
+};
+
+/// The room store backed by the `rooms` and `occupancies` tables. Occupying a room also checks
+/// the maintenance windows, opening hours and holidays stored next to them.
+pub struct PgRoomStore {
+    db: DBPool,
+}
+
+impl PgRoomStore {
+    pub fn new(db: DBPool) -> Self {
+        PgRoomStore { db }
+    }
+}
+
+#[async_trait]
+impl RoomStore for PgRoomStore {
+    async fn current_state(&self) -> Result<CurrentState, StoreError> {
+        let available_rooms = self.available_rooms().await?;
+        let active_rooms = self.active_rooms().await?;
+
+        let unavailable_rooms = sqlx::query_as::<_, UnavailableRoom>(
+            "SELECT 
+              DISTINCT ON (rooms.id) rooms.id, 
+              rooms.name, 
+              rooms.room_id, 
+              rooms.capacity, 
+              rooms.link, 
+              TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
+              rooms.comments, 
+              rooms.timezone, 
+              rooms.location, 
+              maintenance_windows.ends_at as unavailable_until, 
+              maintenance_windows.reason as unavailable_reason 
+            FROM 
+              rooms 
+              JOIN maintenance_windows ON rooms.id = maintenance_windows.room_id 
+              LEFT OUTER JOIN occupancies ON rooms.id = occupancies.occupied_room_id 
+            WHERE 
+              occupancies.id IS NULL 
+              AND rooms.archived_at IS NULL 
+              AND maintenance_windows.starts_at <= NOW() 
+              AND maintenance_windows.ends_at > NOW() 
+            ORDER BY 
+              rooms.id, 
+              maintenance_windows.ends_at DESC",
+        )
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)?;
+
+        Ok(CurrentState {
+            available_rooms,
+            active_rooms,
+            unavailable_rooms,
+        })
+    }
+
+    async fn available_rooms(&self) -> Result<Vec<Room>, StoreError> {
+        sqlx::query_as::<_, Room>(
+            "SELECT 
+              rooms.id, 
+              rooms.name, 
+              rooms.room_id, 
+              rooms.capacity, 
+              rooms.link, 
+              TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
+              rooms.comments, 
+              rooms.timezone, 
+              rooms.location 
+            FROM 
+              rooms 
+              LEFT OUTER JOIN occupancies ON rooms.id = occupancies.occupied_room_id 
+            WHERE 
+              occupancies.id IS NULL 
+              AND rooms.archived_at IS NULL 
+              AND NOT EXISTS (
+                SELECT 
+                  1 
+                FROM 
+                  maintenance_windows 
+                WHERE 
+                  maintenance_windows.room_id = rooms.id 
+                  AND maintenance_windows.starts_at <= NOW() 
+                  AND maintenance_windows.ends_at > NOW()
+              )",
+        )
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn active_rooms(&self) -> Result<Vec<ActiveRoom>, StoreError> {
+        sqlx::query_as::<_, ActiveRoom>(
+            "SELECT
+              rooms.id,
+              rooms.name,
+              rooms.room_id,
+              rooms.capacity,
+              rooms.link,
+              TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit,
+              rooms.comments,
+              rooms.timezone,
+              rooms.location,
+              TRUE as is_active,
+              occupancies.occupied_until,
+              occupancies.meeting_title,
+              occupancies.comments as meeting_comments,
+              occupancies.checked_in,
+              TO_CHAR(
+                occupancies.occupied_until AT TIME ZONE rooms.timezone,
+                'YYYY-MM-DD\"T\"HH24:MI:SS'
+              ) as occupied_until_local
+            FROM
+              rooms
+              JOIN occupancies ON rooms.id = occupancies.occupied_room_id",
+        )
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn room(&self, id: Uuid) -> Result<Room, StoreError> {
+        sqlx::query_as::<_, Room>(
+            "SELECT 
+              rooms.id, 
+              rooms.name, 
+              rooms.room_id, 
+              rooms.capacity, 
+              rooms.link, 
+              TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
+              rooms.comments, 
+              rooms.timezone, 
+              rooms.location 
+            FROM 
+              rooms 
+            WHERE 
+              id = $1",
+        )
+        .bind(id)
+        .fetch_one(traced(&self.db))
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StoreError::RoomNotFound,
+            e => internal(e),
+        })
+    }
+
+    async fn occupancies(&self) -> Result<Vec<Occupancy>, StoreError> {
+        sqlx::query_as::<_, Occupancy>("SELECT * FROM occupancies")
+            .fetch_all(traced(&self.db))
+            .await
+            .map_err(internal)
+    }
+
+    async fn create_room(&self, room: NewRoom) -> Result<Room, StoreError> {
+        check_room_conflicts(&self.db, &room, None).await?;
+
+        let interval = Duration::from_secs(room.time_limit * 60);
+
+        sqlx::query_as::<_, Room>(
+            "INSERT INTO rooms(
+              name, room_id, capacity, time_limit, 
+              link, comments, timezone, location
+            ) 
+            VALUES 
+              (
+                $1, $2, $3, $4 :: interval, $5, $6, 
+                COALESCE($7, 'UTC'), 
+                $8
+              ) RETURNING id, 
+              name, 
+              room_id, 
+              capacity, 
+              TO_CHAR(time_limit, 'HH24:MI:SS') as time_limit, 
+              link, 
+              comments, 
+              timezone, 
+              location",
+        )
+        .bind(room.name)
+        .bind(room.room_id)
+        .bind(room.capacity)
+        .bind(interval)
+        .bind(room.link)
+        .bind(room.comments)
+        .bind(room.timezone)
+        .bind(room.location)
+        .fetch_one(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn update_room(&self, id: Uuid, room: NewRoom) -> Result<Room, StoreError> {
+        self.room(id).await?;
+
+        check_room_conflicts(&self.db, &room, Some(id)).await?;
+
+        let interval = Duration::from_secs(room.time_limit * 60);
+
+        sqlx::query_as::<_, Room>(
+            "UPDATE 
+              rooms 
+            SET 
+              name = $1, 
+              room_id = $2, 
+              capacity = $3, 
+              link = $4, 
+              time_limit = $5, 
+              comments = $6, 
+              timezone = COALESCE($8, timezone), 
+              location = $9 
+            WHERE 
+              id = $7 RETURNING id, 
+              name, 
+              room_id, 
+              capacity, 
+              TO_CHAR(time_limit, 'HH24:MI:SS') as time_limit, 
+              link, 
+              comments, 
+              timezone, 
+              location",
+        )
+        .bind(room.name)
+        .bind(room.room_id)
+        .bind(room.capacity)
+        .bind(room.link)
+        .bind(interval)
+        .bind(room.comments)
+        .bind(id)
+        .bind(room.timezone)
+        .bind(room.location)
+        .fetch_one(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn occupy_room(&self, occupancy: NewOccupancy) -> Result<ActiveRoom, StoreError> {
+        if !is_valid_headcount(occupancy.headcount) {
+            return Err(StoreError::InvalidHeadcount);
+        }
+
+        let existing = sqlx::query("SELECT id FROM occupancies WHERE occupied_room_id = $1")
+            .bind(occupancy.occupied_room_id)
+            .fetch_optional(traced(&self.db))
+            .await
+            .map_err(internal)?;
+
+        if existing.is_some() {
+            return Err(StoreError::RoomOccupied);
+        }
+
+        let room = sqlx::query_as::<_, Room>(
+            "SELECT 
+              rooms.id, 
+              rooms.name, 
+              rooms.room_id, 
+              rooms.capacity, 
+              rooms.link, 
+              TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
+              rooms.comments, 
+              rooms.timezone, 
+              rooms.location 
+            FROM 
+              rooms 
+            WHERE 
+              id = $1 
+              AND archived_at IS NULL",
+        )
+        .bind(occupancy.occupied_room_id)
+        .fetch_optional(traced(&self.db))
+        .await
+        .map_err(internal)?
+        .ok_or(StoreError::RoomNotFound)?;
+
+        match occupancy.headcount {
+            Some(headcount) if headcount > room.capacity => return Err(StoreError::RoomTooSmall),
+            _ => (),
+        }
+
+        // Time spent waiting on the pool shows up apart from the queries
+        let mut conn = self
+            .db
+            .acquire()
+            .instrument(tracing::info_span!("db.acquire"))
+            .await
+            .map_err(internal)?;
+
+        check_room_bookable(&mut conn, &room, occupancy.occupied_until).await?;
+
+        let insert_query = sqlx::query_as::<_, Occupancy>(
+            "INSERT INTO occupancies(
+              occupied_room_id, occupied_until, 
+              meeting_title, comments, headcount, 
+              booked_by
+            ) 
+            VALUES 
+              ($1, $2, $3, $4, $5, $6) RETURNING id, 
+              occupied_room_id, 
+              occupied_until, 
+              meeting_title, 
+              comments, 
+              started_at, 
+              checked_in, 
+              headcount, 
+              booked_by",
+        )
+        .bind(occupancy.occupied_room_id)
+        .bind(occupancy.occupied_until)
+        .bind(occupancy.meeting_title)
+        .bind(occupancy.comments)
+        .bind(occupancy.headcount)
+        .bind(occupancy.booked_by)
+        .fetch_one(traced(&mut conn))
+        .await;
+
+        match insert_query {
+            Ok(occupancy) => Ok(active_room(room, &occupancy)),
+            Err(e) if is_unique_violation(&e) => Err(StoreError::RoomOccupied),
+            Err(e) => Err(internal(e)),
+        }
+    }
+
+    async fn free_room(&self, id: Uuid) -> Result<(), StoreError> {
+        self.room(id).await?;
+
+        let ended = sqlx::query(
+            "WITH ended AS (
+              DELETE FROM 
+                occupancies 
+              WHERE 
+                occupied_room_id = $1 RETURNING *
+            ) 
+            INSERT INTO occupancy_history(
+              occupied_room_id, started_at, ended_at, 
+              occupied_until, meeting_title, comments, 
+              headcount, booked_by, no_show
+            ) 
+            SELECT 
+              occupied_room_id, 
+              started_at, 
+              NOW(), 
+              occupied_until, 
+              meeting_title, 
+              comments, 
+              headcount, 
+              booked_by, 
+              FALSE 
+            FROM 
+              ended",
+        )
+        .bind(id)
+        .execute(traced(&self.db))
+        .await
+        .map_err(internal)?;
+
+        if ended.rows_affected() == 0 {
+            return Err(StoreError::RoomNotOccupied);
+        }
+
+        Ok(())
+    }
+
+    async fn check_in_room(&self, id: Uuid) -> Result<(), StoreError> {
+        let room = sqlx::query("SELECT id FROM rooms WHERE id = $1")
+            .bind(id)
+            .fetch_optional(traced(&self.db))
+            .await
+            .map_err(internal)?;
+
+        if room.is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        let occupancy = sqlx::query_as::<_, Occupancy>(
+            "SELECT 
+              * 
+            FROM 
+              occupancies 
+            WHERE 
+              occupied_room_id = $1",
+        )
+        .bind(id)
+        .fetch_optional(traced(&self.db))
+        .await
+        .map_err(internal)?;
+
+        match occupancy {
+            Some(occupancy) if occupancy.checked_in => {
+                return Err(StoreError::RoomAlreadyCheckedIn)
+            }
+            Some(_) => (),
+            None => return Err(StoreError::RoomNotOccupied),
+        }
+
+        sqlx::query("UPDATE occupancies SET checked_in = TRUE WHERE occupied_room_id = $1")
+            .bind(id)
+            .execute(traced(&self.db))
+            .await
+            .map_err(internal)?;
+
+        Ok(())
+    }
+
+    async fn transfer_room(&self, transfer: OccupancyTransfer) -> Result<ActiveRoom, StoreError> {
+        if !is_valid_headcount(transfer.headcount) {
+            return Err(StoreError::InvalidHeadcount);
+        }
+
+        let mut tx = self
+            .db
+            .begin()
+            .instrument(tracing::info_span!("db.acquire"))
+            .await
+            .map_err(internal)?;
+
+        // Lock both rooms in a stable order so concurrent transfers can't deadlock
+        let mut locked_rooms = sqlx::query_as::<_, Room>(
+            "SELECT 
+              rooms.id, 
+              rooms.name, 
+              rooms.room_id, 
+              rooms.capacity, 
+              rooms.link, 
+              TO_CHAR(rooms.time_limit, 'HH24:MI:SS') as time_limit, 
+              rooms.comments, 
+              rooms.timezone, 
+              rooms.location 
+            FROM 
+              rooms 
+            WHERE 
+              id = $1 
+              OR (
+                id = $2 
+                AND archived_at IS NULL
+              ) 
+            ORDER BY 
+              id FOR UPDATE",
+        )
+        .bind(transfer.from_room_id)
+        .bind(transfer.to_room_id)
+        .fetch_all(traced(&mut tx))
+        .await
+        .map_err(internal)?;
+
+        let source_room = match locked_rooms
+            .iter()
+            .position(|room| room.id == transfer.from_room_id)
+        {
+            Some(index) => locked_rooms.remove(index),
+            None => return Err(StoreError::RoomNotFound),
+        };
+
+        let target_room = match locked_rooms
+            .into_iter()
+            .find(|room| room.id == transfer.to_room_id)
+        {
+            Some(room) => room,
+            None if transfer.to_room_id == source_room.id => return Err(StoreError::RoomOccupied),
+            None => return Err(StoreError::RoomNotFound),
+        };
+
+        let target_occupancy =
+            sqlx::query("SELECT id FROM occupancies WHERE occupied_room_id = $1")
+                .bind(target_room.id)
+                .fetch_optional(traced(&mut tx))
+                .await
+                .map_err(internal)?;
+
+        if target_occupancy.is_some() {
+            return Err(StoreError::RoomOccupied);
+        }
+
+        let ended_occupancy = sqlx::query_as::<_, Occupancy>(
+            "WITH ended AS (
+              DELETE FROM 
+                occupancies 
+              WHERE 
+                occupied_room_id = $1 RETURNING *
+            ), 
+            recorded AS (
+              INSERT INTO occupancy_history(
+                occupied_room_id, started_at, ended_at, 
+                occupied_until, meeting_title, comments, 
+                headcount, booked_by, no_show
+              ) 
+              SELECT 
+                occupied_room_id, 
+                started_at, 
+                NOW(), 
+                occupied_until, 
+                meeting_title, 
+                comments, 
+                headcount, 
+                booked_by, 
+                FALSE 
+              FROM 
+                ended
+            ) 
+            SELECT 
+              * 
+            FROM 
+              ended",
+        )
+        .bind(source_room.id)
+        .fetch_optional(traced(&mut tx))
+        .await
+        .map_err(internal)?
+        .ok_or(StoreError::RoomNotOccupied)?;
+
+        let headcount = transfer
+            .headcount
+            .or(ended_occupancy.headcount)
+            .unwrap_or(source_room.capacity);
+
+        if target_room.capacity < headcount {
+            return Err(StoreError::RoomTooSmall);
+        }
+
+        check_room_bookable(&mut tx, &target_room, ended_occupancy.occupied_until).await?;
+
+        let insert_query = sqlx::query_as::<_, Occupancy>(
+            "INSERT INTO occupancies(
+              occupied_room_id, occupied_until, 
+              meeting_title, comments, checked_in, 
+              headcount, booked_by
+            ) 
+            VALUES 
+              ($1, $2, $3, $4, $5, $6, $7) RETURNING id, 
+              occupied_room_id, 
+              occupied_until, 
+              meeting_title, 
+              comments, 
+              started_at, 
+              checked_in, 
+              headcount, 
+              booked_by",
+        )
+        .bind(target_room.id)
+        .bind(ended_occupancy.occupied_until)
+        .bind(&ended_occupancy.meeting_title)
+        .bind(&ended_occupancy.comments)
+        .bind(ended_occupancy.checked_in)
+        .bind(transfer.headcount.or(ended_occupancy.headcount))
+        .bind(&ended_occupancy.booked_by)
+        .fetch_one(traced(&mut tx))
+        .await;
+
+        let occupancy = match insert_query {
+            Ok(occupancy) => occupancy,
+            Err(e) if is_unique_violation(&e) => return Err(StoreError::RoomOccupied),
+            Err(e) => return Err(internal(e)),
+        };
+
+        tx.commit().await.map_err(internal)?;
+
+        Ok(active_room(target_room, &occupancy))
+    }
+
+    async fn release_no_shows(&self, checkin_window: Duration) -> Result<u64, StoreError> {
+        let released = sqlx::query(
+            "WITH released AS (
+              DELETE FROM 
+                occupancies 
+              WHERE 
+                NOT checked_in 
+                AND started_at + $1 :: interval < NOW() RETURNING *
+            ) 
+            INSERT INTO occupancy_history(
+              occupied_room_id, started_at, ended_at, 
+              occupied_until, meeting_title, comments, 
+              headcount, booked_by, no_show
+            ) 
+            SELECT 
+              occupied_room_id, 
+              started_at, 
+              NOW(), 
+              occupied_until, 
+              meeting_title, 
+              comments, 
+              headcount, 
+              booked_by, 
+              TRUE 
+            FROM 
+              released",
+        )
+        .bind(checkin_window)
+        .execute(traced(&self.db))
+        .await
+        .map_err(internal)?;
+
+        Ok(released.rows_affected())
+    }
+
+    async fn maintenance_windows(&self) -> Result<Vec<MaintenanceWindow>, StoreError> {
+        sqlx::query_as::<_, MaintenanceWindow>(
+            "SELECT 
+              * 
+            FROM 
+              maintenance_windows 
+            WHERE 
+              ends_at > NOW() 
+            ORDER BY 
+              starts_at",
+        )
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn create_maintenance_window(
+        &self,
+        window: NewMaintenanceWindow,
+    ) -> Result<MaintenanceWindow, StoreError> {
+        self.room(window.room_id).await?;
+
+        sqlx::query_as::<_, MaintenanceWindow>(
+            "INSERT INTO maintenance_windows(room_id, starts_at, ends_at, reason) 
+            VALUES 
+              ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(window.room_id)
+        .bind(window.starts_at)
+        .bind(window.ends_at)
+        .bind(window.reason)
+        .fetch_one(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn remove_maintenance_window(&self, id: i32) -> Result<(), StoreError> {
+        let removed = sqlx::query("DELETE FROM maintenance_windows WHERE id = $1")
+            .bind(id)
+            .execute(traced(&self.db))
+            .await
+            .map_err(internal)?;
+
+        if removed.rows_affected() == 0 {
+            return Err(StoreError::MaintenanceWindowNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn opening_hours(
+        &self,
+        room_id: Uuid,
+    ) -> Result<(String, Vec<OpeningHours>), StoreError> {
+        let room = self.room(room_id).await?;
+
+        let opening_hours = sqlx::query_as::<_, OpeningHours>(
+            "SELECT 
+              weekday, 
+              opens_at, 
+              closes_at 
+            FROM 
+              room_opening_hours 
+            WHERE 
+              room_id = $1 
+            ORDER BY 
+              weekday, 
+              opens_at",
+        )
+        .bind(room_id)
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)?;
+
+        Ok((room.timezone, opening_hours))
+    }
+
+    async fn set_opening_hours(
+        &self,
+        room_id: Uuid,
+        opening_hours: &[OpeningHours],
+    ) -> Result<(), StoreError> {
+        let mut tx = self.db.begin().await.map_err(internal)?;
+
+        let room = sqlx::query("SELECT id FROM rooms WHERE id = $1 FOR UPDATE")
+            .bind(room_id)
+            .fetch_optional(traced(&mut tx))
+            .await
+            .map_err(internal)?;
+
+        if room.is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        sqlx::query("DELETE FROM room_opening_hours WHERE room_id = $1")
+            .bind(room_id)
+            .execute(traced(&mut tx))
+            .await
+            .map_err(internal)?;
+
+        for hours in opening_hours {
+            sqlx::query(
+                "INSERT INTO room_opening_hours(room_id, weekday, opens_at, closes_at) 
+                VALUES 
+                  ($1, $2, $3, $4)",
+            )
+            .bind(room_id)
+            .bind(hours.weekday)
+            .bind(hours.opens_at)
+            .bind(hours.closes_at)
+            .execute(traced(&mut tx))
+            .await
+            .map_err(internal)?;
+        }
+
+        tx.commit().await.map_err(internal)
+    }
+
+    async fn holidays(&self) -> Result<Vec<Holiday>, StoreError> {
+        sqlx::query_as::<_, Holiday>(
+            "SELECT 
+              * 
+            FROM 
+              holidays 
+            WHERE 
+              date >= CURRENT_DATE 
+            ORDER BY 
+              date",
+        )
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn create_holiday(&self, holiday: NewHoliday) -> Result<Holiday, StoreError> {
+        sqlx::query_as::<_, Holiday>(
+            "INSERT INTO holidays(date, name, location) 
+            VALUES 
+              ($1, $2, $3) RETURNING *",
+        )
+        .bind(holiday.date)
+        .bind(holiday.name)
+        .bind(holiday.location)
+        .fetch_one(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn remove_holiday(&self, id: i32) -> Result<(), StoreError> {
+        let removed = sqlx::query("DELETE FROM holidays WHERE id = $1")
+            .bind(id)
+            .execute(traced(&self.db))
+            .await
+            .map_err(internal)?;
+
+        if removed.rows_affected() == 0 {
+            return Err(StoreError::HolidayNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn holiday_collisions(&self, id: i32) -> Result<Vec<Occupancy>, StoreError> {
+        let holiday = sqlx::query("SELECT id FROM holidays WHERE id = $1")
+            .bind(id)
+            .fetch_optional(traced(&self.db))
+            .await
+            .map_err(internal)?;
+
+        if holiday.is_none() {
+            return Err(StoreError::HolidayNotFound);
+        }
+
+        sqlx::query_as::<_, Occupancy>(
+            "SELECT 
+              occupancies.* 
+            FROM 
+              occupancies 
+              JOIN rooms ON rooms.id = occupancies.occupied_room_id 
+              JOIN holidays ON holidays.id = $1 
+            WHERE 
+              (
+                holidays.location IS NULL 
+                OR holidays.location = rooms.location
+              ) 
+              AND occupancies.occupied_until > NOW() 
+              AND holidays.date BETWEEN (
+                occupancies.started_at AT TIME ZONE rooms.timezone
+              ):: date 
+              AND (
+                occupancies.occupied_until AT TIME ZONE rooms.timezone
+              ):: date",
+        )
+        .bind(id)
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+}
+
+/// Fails if another room already uses the name or room id, `room_id` is left out of the check
+async fn check_room_conflicts(
+    db: &DBPool,
+    room: &NewRoom,
+    room_id: Option<Uuid>,
+) -> Result<(), StoreError> {
+    let found_rooms = sqlx::query_as::<_, (String, String)>(
+        "SELECT 
+          name, 
+          room_id 
+        FROM 
+          rooms 
+        WHERE 
+          (
+            name = $1 
+            OR room_id = $2
+          ) 
+          AND id IS DISTINCT FROM $3",
+    )
+    .bind(&room.name)
+    .bind(&room.room_id)
+    .bind(room_id)
+    .fetch_all(traced(db))
+    .await
+    .map_err(internal)?;
+
+    if found_rooms.iter().any(|(name, _)| *name == room.name) {
+        Err(StoreError::RoomWithNameExists)
+    } else if found_rooms.iter().any(|(_, id)| *id == room.room_id) {
+        Err(StoreError::RoomWithIdExists)
+    } else {
+        Ok(())
+    }
+}
+
+/// Fails if the room can't be booked until `until` because of maintenance, its opening hours
+/// or a holiday
+async fn check_room_bookable(
+    conn: &mut PgConnection,
+    room: &Room,
+    until: DateTime<Utc>,
+) -> Result<(), StoreError> {
+    let maintenance_window = sqlx::query(
+        "SELECT 
+          id 
+        FROM 
+          maintenance_windows 
+        WHERE 
+          room_id = $1 
+          AND starts_at < $2 
+          AND ends_at > NOW()",
+    )
+    .bind(room.id)
+    .bind(until)
+    .fetch_optional(traced(&mut *conn))
+    .await
+    .map_err(internal)?;
+
+    if maintenance_window.is_some() {
+        return Err(StoreError::RoomUnderMaintenance);
+    }
+
+    let opening_hours = sqlx::query_as::<_, OpeningHours>(
+        "SELECT 
+          weekday, 
+          opens_at, 
+          closes_at 
+        FROM 
+          room_opening_hours 
+        WHERE 
+          room_id = $1",
+    )
+    .bind(room.id)
+    .fetch_all(traced(&mut *conn))
+    .await
+    .map_err(internal)?;
+
+    if !is_within_opening_hours(&room.timezone, &opening_hours, Utc::now(), until) {
+        return Err(StoreError::RoomClosed);
+    }
+
+    let holiday = sqlx::query(
+        "SELECT 
+          id 
+        FROM 
+          holidays 
+        WHERE 
+          (
+            location IS NULL 
+            OR location = $1
+          ) 
+          AND date BETWEEN (NOW() AT TIME ZONE $2):: date 
+          AND ($3 AT TIME ZONE $2):: date",
+    )
+    .bind(&room.location)
+    .bind(&room.timezone)
+    .bind(until)
+    .fetch_optional(traced(&mut *conn))
+    .await
+    .map_err(internal)?;
+
+    if holiday.is_some() {
+        return Err(StoreError::Holiday);
+    }
+
+    Ok(())
+}
+
+fn internal(err: sqlx::Error) -> StoreError {
+    tracing::error!(error = %err, "Room store query failed");
+
+    StoreError::Internal
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db_err) => db_err.code().as_deref() == Some("23505"),
+        _ => false,
+    }
+}
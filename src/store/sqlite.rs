@@ -0,0 +1,957 @@
+use std::{str::FromStr, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{
+    migrate::Migrator,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    SqliteConnection, SqlitePool,
+};
+use uuid::Uuid;
+
+use super::{active_room, is_valid_headcount, RoomStore, StoreError};
+use crate::{
+    models::{
+        ActiveRoom, CurrentState, Holiday, MaintenanceWindow, NewHoliday, NewMaintenanceWindow,
+        NewOccupancy, NewRoom, Occupancy, OccupancyTransfer, OpeningHours, Room,
+    },
+    schedule::{is_within_opening_hours, local_date},
+    telemetry::traced,
+};
+
+/// The SQLite schema from `migrations-sqlite/`, embedded into the binary
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations-sqlite");
+
+/// The room store for single-box deployments, backed by a SQLite database file. It mirrors
+/// `PgRoomStore`, with time limits kept as minutes and local times worked out in Rust since
+/// SQLite has neither intervals nor timezones.
+pub struct SqliteRoomStore {
+    db: SqlitePool,
+}
+
+impl SqliteRoomStore {
+    /// Opens the database at `db_string` (e.g. `sqlite://zoomer.db`), creating the file if it's
+    /// missing, and brings its schema up to date. Returns the store and the schema version.
+    pub async fn connect(db_string: &str) -> Result<(Self, i64), String> {
+        let options = SqliteConnectOptions::from_str(db_string)
+            .map_err(|e| format!("Invalid db_string: {}", e))?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+
+        // SQLite takes one writer at a time anyway
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .map_err(|e| format!("Couldn't connect to the database!: {}", e))?;
+
+        MIGRATOR
+            .run(&db)
+            .await
+            .map_err(|e| format!("Couldn't migrate the database: {}", e))?;
+
+        let version = MIGRATOR
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or(0);
+
+        Ok((SqliteRoomStore { db }, version))
+    }
+
+    /// Waits for the queries in progress and closes the database
+    pub async fn close(&self) {
+        self.db.close().await;
+    }
+}
+
+#[async_trait]
+impl RoomStore for SqliteRoomStore {
+    async fn current_state(&self) -> Result<CurrentState, StoreError> {
+        let available_rooms = self.available_rooms().await?;
+        let active_rooms = self.active_rooms().await?;
+
+        let rooms = sqlx::query_as::<_, UnavailableRow>(
+            "SELECT 
+              rooms.id, 
+              rooms.name, 
+              rooms.room_id, 
+              rooms.capacity, 
+              rooms.link, 
+              printf(
+                '%02d:%02d:00', rooms.time_limit / 60, 
+                rooms.time_limit % 60
+              ) as time_limit, 
+              rooms.comments, 
+              rooms.timezone, 
+              rooms.location, 
+              MAX(maintenance_windows.ends_at) as unavailable_until, 
+              maintenance_windows.reason as unavailable_reason 
+            FROM 
+              rooms 
+              JOIN maintenance_windows ON rooms.id = maintenance_windows.room_id 
+              LEFT OUTER JOIN occupancies ON rooms.id = occupancies.occupied_room_id 
+            WHERE 
+              occupancies.id IS NULL 
+              AND rooms.archived_at IS NULL 
+              AND maintenance_windows.starts_at <= $1 
+              AND maintenance_windows.ends_at > $1 
+            GROUP BY 
+              rooms.id",
+        )
+        .bind(Utc::now())
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)?;
+
+        Ok(CurrentState {
+            available_rooms,
+            active_rooms,
+            unavailable_rooms: rooms.into_iter().map(UnavailableRow::into_room).collect(),
+        })
+    }
+
+    async fn available_rooms(&self) -> Result<Vec<Room>, StoreError> {
+        sqlx::query_as::<_, Room>(
+            "SELECT 
+              rooms.id, 
+              rooms.name, 
+              rooms.room_id, 
+              rooms.capacity, 
+              rooms.link, 
+              printf(
+                '%02d:%02d:00', rooms.time_limit / 60, 
+                rooms.time_limit % 60
+              ) as time_limit, 
+              rooms.comments, 
+              rooms.timezone, 
+              rooms.location 
+            FROM 
+              rooms 
+              LEFT OUTER JOIN occupancies ON rooms.id = occupancies.occupied_room_id 
+            WHERE 
+              occupancies.id IS NULL 
+              AND rooms.archived_at IS NULL 
+              AND NOT EXISTS (
+                SELECT 
+                  1 
+                FROM 
+                  maintenance_windows 
+                WHERE 
+                  maintenance_windows.room_id = rooms.id 
+                  AND maintenance_windows.starts_at <= $1 
+                  AND maintenance_windows.ends_at > $1
+              )",
+        )
+        .bind(Utc::now())
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn active_rooms(&self) -> Result<Vec<ActiveRoom>, StoreError> {
+        let rows = sqlx::query_as::<_, ActiveRow>(
+            "SELECT 
+              rooms.id, 
+              rooms.name, 
+              rooms.room_id, 
+              rooms.capacity, 
+              rooms.link, 
+              printf(
+                '%02d:%02d:00', rooms.time_limit / 60, 
+                rooms.time_limit % 60
+              ) as time_limit, 
+              rooms.comments, 
+              rooms.timezone, 
+              rooms.location, 
+              occupancies.id as occupancy_id, 
+              occupancies.occupied_until, 
+              occupancies.meeting_title, 
+              occupancies.comments as meeting_comments, 
+              occupancies.started_at, 
+              occupancies.checked_in, 
+              occupancies.headcount, 
+              occupancies.booked_by 
+            FROM 
+              rooms 
+              JOIN occupancies ON rooms.id = occupancies.occupied_room_id",
+        )
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)?;
+
+        Ok(rows.into_iter().map(ActiveRow::into_room).collect())
+    }
+
+    async fn room(&self, id: Uuid) -> Result<Room, StoreError> {
+        let mut conn = self.db.acquire().await.map_err(internal)?;
+
+        find_room(&mut conn, id, false)
+            .await?
+            .ok_or(StoreError::RoomNotFound)
+    }
+
+    async fn occupancies(&self) -> Result<Vec<Occupancy>, StoreError> {
+        sqlx::query_as::<_, Occupancy>("SELECT * FROM occupancies")
+            .fetch_all(traced(&self.db))
+            .await
+            .map_err(internal)
+    }
+
+    async fn create_room(&self, room: NewRoom) -> Result<Room, StoreError> {
+        let mut conn = self.db.acquire().await.map_err(internal)?;
+        let id = Uuid::new_v4();
+
+        check_room_conflicts(&mut conn, &room, None).await?;
+
+        sqlx::query(
+            "INSERT INTO rooms(
+              id, name, room_id, capacity, time_limit, 
+              link, comments, timezone, location
+            ) 
+            VALUES 
+              (
+                $1, $2, $3, $4, $5, $6, $7, 
+                COALESCE($8, 'UTC'), 
+                $9
+              )",
+        )
+        .bind(id)
+        .bind(room.name)
+        .bind(room.room_id)
+        .bind(room.capacity)
+        .bind(room.time_limit as i64)
+        .bind(room.link)
+        .bind(room.comments)
+        .bind(room.timezone)
+        .bind(room.location)
+        .execute(traced(&mut conn))
+        .await
+        .map_err(internal)?;
+
+        find_room(&mut conn, id, false)
+            .await?
+            .ok_or(StoreError::Internal)
+    }
+
+    async fn update_room(&self, id: Uuid, room: NewRoom) -> Result<Room, StoreError> {
+        let mut conn = self.db.acquire().await.map_err(internal)?;
+
+        if find_room(&mut conn, id, false).await?.is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        check_room_conflicts(&mut conn, &room, Some(id)).await?;
+
+        sqlx::query(
+            "UPDATE 
+              rooms 
+            SET 
+              name = $1, 
+              room_id = $2, 
+              capacity = $3, 
+              link = $4, 
+              time_limit = $5, 
+              comments = $6, 
+              timezone = COALESCE($8, timezone), 
+              location = $9 
+            WHERE 
+              id = $7",
+        )
+        .bind(room.name)
+        .bind(room.room_id)
+        .bind(room.capacity)
+        .bind(room.link)
+        .bind(room.time_limit as i64)
+        .bind(room.comments)
+        .bind(id)
+        .bind(room.timezone)
+        .bind(room.location)
+        .execute(traced(&mut conn))
+        .await
+        .map_err(internal)?;
+
+        find_room(&mut conn, id, false)
+            .await?
+            .ok_or(StoreError::Internal)
+    }
+
+    async fn occupy_room(&self, occupancy: NewOccupancy) -> Result<ActiveRoom, StoreError> {
+        if !is_valid_headcount(occupancy.headcount) {
+            return Err(StoreError::InvalidHeadcount);
+        }
+
+        let mut conn = self.db.acquire().await.map_err(internal)?;
+
+        if find_occupancy(&mut conn, occupancy.occupied_room_id)
+            .await?
+            .is_some()
+        {
+            return Err(StoreError::RoomOccupied);
+        }
+
+        let room = find_room(&mut conn, occupancy.occupied_room_id, true)
+            .await?
+            .ok_or(StoreError::RoomNotFound)?;
+
+        match occupancy.headcount {
+            Some(headcount) if headcount > room.capacity => return Err(StoreError::RoomTooSmall),
+            _ => (),
+        }
+
+        check_room_bookable(&mut conn, &room, occupancy.occupied_until).await?;
+
+        let insert_query = sqlx::query_as::<_, Occupancy>(
+            "INSERT INTO occupancies(
+              occupied_room_id, occupied_until, 
+              meeting_title, comments, started_at, 
+              headcount, booked_by
+            ) 
+            VALUES 
+              ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+        )
+        .bind(occupancy.occupied_room_id)
+        .bind(occupancy.occupied_until)
+        .bind(occupancy.meeting_title)
+        .bind(occupancy.comments)
+        .bind(Utc::now())
+        .bind(occupancy.headcount)
+        .bind(occupancy.booked_by)
+        .fetch_one(traced(&mut conn))
+        .await;
+
+        match insert_query {
+            Ok(occupancy) => Ok(active_room(room, &occupancy)),
+            Err(e) if is_unique_violation(&e) => Err(StoreError::RoomOccupied),
+            Err(e) => Err(internal(e)),
+        }
+    }
+
+    async fn free_room(&self, id: Uuid) -> Result<(), StoreError> {
+        let mut tx = self.db.begin().await.map_err(internal)?;
+
+        if find_room(&mut tx, id, false).await?.is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        end_occupancy(&mut tx, id, false)
+            .await?
+            .ok_or(StoreError::RoomNotOccupied)?;
+
+        tx.commit().await.map_err(internal)
+    }
+
+    async fn check_in_room(&self, id: Uuid) -> Result<(), StoreError> {
+        let mut conn = self.db.acquire().await.map_err(internal)?;
+
+        if find_room(&mut conn, id, false).await?.is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        match find_occupancy(&mut conn, id).await? {
+            Some(occupancy) if occupancy.checked_in => {
+                return Err(StoreError::RoomAlreadyCheckedIn)
+            }
+            Some(_) => (),
+            None => return Err(StoreError::RoomNotOccupied),
+        }
+
+        sqlx::query("UPDATE occupancies SET checked_in = TRUE WHERE occupied_room_id = $1")
+            .bind(id)
+            .execute(traced(&mut conn))
+            .await
+            .map_err(internal)?;
+
+        Ok(())
+    }
+
+    async fn transfer_room(&self, transfer: OccupancyTransfer) -> Result<ActiveRoom, StoreError> {
+        if !is_valid_headcount(transfer.headcount) {
+            return Err(StoreError::InvalidHeadcount);
+        }
+
+        // The pool has a single connection, so nothing else runs until the transfer is done
+        let mut tx = self.db.begin().await.map_err(internal)?;
+
+        let source_room = find_room(&mut tx, transfer.from_room_id, false)
+            .await?
+            .ok_or(StoreError::RoomNotFound)?;
+
+        if transfer.to_room_id == source_room.id {
+            return Err(StoreError::RoomOccupied);
+        }
+
+        let target_room = find_room(&mut tx, transfer.to_room_id, true)
+            .await?
+            .ok_or(StoreError::RoomNotFound)?;
+
+        if find_occupancy(&mut tx, target_room.id).await?.is_some() {
+            return Err(StoreError::RoomOccupied);
+        }
+
+        let ended_occupancy = end_occupancy(&mut tx, source_room.id, false)
+            .await?
+            .ok_or(StoreError::RoomNotOccupied)?;
+
+        let headcount = transfer
+            .headcount
+            .or(ended_occupancy.headcount)
+            .unwrap_or(source_room.capacity);
+
+        if target_room.capacity < headcount {
+            return Err(StoreError::RoomTooSmall);
+        }
+
+        check_room_bookable(&mut tx, &target_room, ended_occupancy.occupied_until).await?;
+
+        let occupancy = sqlx::query_as::<_, Occupancy>(
+            "INSERT INTO occupancies(
+              occupied_room_id, occupied_until, 
+              meeting_title, comments, started_at, 
+              checked_in, headcount, booked_by
+            ) 
+            VALUES 
+              ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+        )
+        .bind(target_room.id)
+        .bind(ended_occupancy.occupied_until)
+        .bind(&ended_occupancy.meeting_title)
+        .bind(&ended_occupancy.comments)
+        .bind(Utc::now())
+        .bind(ended_occupancy.checked_in)
+        .bind(transfer.headcount.or(ended_occupancy.headcount))
+        .bind(&ended_occupancy.booked_by)
+        .fetch_one(traced(&mut tx))
+        .await
+        .map_err(internal)?;
+
+        tx.commit().await.map_err(internal)?;
+
+        Ok(active_room(target_room, &occupancy))
+    }
+
+    async fn release_no_shows(&self, checkin_window: Duration) -> Result<u64, StoreError> {
+        let checkin_window =
+            chrono::Duration::from_std(checkin_window).map_err(|_| StoreError::Internal)?;
+
+        let mut tx = self.db.begin().await.map_err(internal)?;
+
+        // Timestamps are text, so the start times are compared here rather than in SQL
+        let unchecked = sqlx::query_as::<_, (Uuid, DateTime<Utc>)>(
+            "SELECT occupied_room_id, started_at FROM occupancies WHERE NOT checked_in",
+        )
+        .fetch_all(traced(&mut tx))
+        .await
+        .map_err(internal)?;
+
+        let now = Utc::now();
+        let mut released = 0;
+
+        for (room_id, started_at) in unchecked {
+            if started_at + checkin_window < now
+                && end_occupancy(&mut tx, room_id, true).await?.is_some()
+            {
+                released += 1;
+            }
+        }
+
+        tx.commit().await.map_err(internal)?;
+
+        Ok(released)
+    }
+
+    async fn maintenance_windows(&self) -> Result<Vec<MaintenanceWindow>, StoreError> {
+        sqlx::query_as::<_, MaintenanceWindow>(
+            "SELECT 
+              * 
+            FROM 
+              maintenance_windows 
+            WHERE 
+              ends_at > $1 
+            ORDER BY 
+              starts_at",
+        )
+        .bind(Utc::now())
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn create_maintenance_window(
+        &self,
+        window: NewMaintenanceWindow,
+    ) -> Result<MaintenanceWindow, StoreError> {
+        let mut conn = self.db.acquire().await.map_err(internal)?;
+
+        if find_room(&mut conn, window.room_id, false).await?.is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        sqlx::query_as::<_, MaintenanceWindow>(
+            "INSERT INTO maintenance_windows(room_id, starts_at, ends_at, reason) 
+            VALUES 
+              ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(window.room_id)
+        .bind(window.starts_at)
+        .bind(window.ends_at)
+        .bind(window.reason)
+        .fetch_one(traced(&mut conn))
+        .await
+        .map_err(internal)
+    }
+
+    async fn remove_maintenance_window(&self, id: i32) -> Result<(), StoreError> {
+        let removed = sqlx::query("DELETE FROM maintenance_windows WHERE id = $1")
+            .bind(id)
+            .execute(traced(&self.db))
+            .await
+            .map_err(internal)?;
+
+        if removed.rows_affected() == 0 {
+            return Err(StoreError::MaintenanceWindowNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn opening_hours(
+        &self,
+        room_id: Uuid,
+    ) -> Result<(String, Vec<OpeningHours>), StoreError> {
+        let mut conn = self.db.acquire().await.map_err(internal)?;
+
+        let room = find_room(&mut conn, room_id, false)
+            .await?
+            .ok_or(StoreError::RoomNotFound)?;
+
+        let opening_hours = sqlx::query_as::<_, OpeningHours>(
+            "SELECT 
+              weekday, 
+              opens_at, 
+              closes_at 
+            FROM 
+              room_opening_hours 
+            WHERE 
+              room_id = $1 
+            ORDER BY 
+              weekday, 
+              opens_at",
+        )
+        .bind(room_id)
+        .fetch_all(traced(&mut conn))
+        .await
+        .map_err(internal)?;
+
+        Ok((room.timezone, opening_hours))
+    }
+
+    async fn set_opening_hours(
+        &self,
+        room_id: Uuid,
+        opening_hours: &[OpeningHours],
+    ) -> Result<(), StoreError> {
+        let mut tx = self.db.begin().await.map_err(internal)?;
+
+        if find_room(&mut tx, room_id, false).await?.is_none() {
+            return Err(StoreError::RoomNotFound);
+        }
+
+        sqlx::query("DELETE FROM room_opening_hours WHERE room_id = $1")
+            .bind(room_id)
+            .execute(traced(&mut tx))
+            .await
+            .map_err(internal)?;
+
+        for hours in opening_hours {
+            sqlx::query(
+                "INSERT INTO room_opening_hours(room_id, weekday, opens_at, closes_at) 
+                VALUES 
+                  ($1, $2, $3, $4)",
+            )
+            .bind(room_id)
+            .bind(hours.weekday)
+            .bind(hours.opens_at)
+            .bind(hours.closes_at)
+            .execute(traced(&mut tx))
+            .await
+            .map_err(internal)?;
+        }
+
+        tx.commit().await.map_err(internal)
+    }
+
+    async fn holidays(&self) -> Result<Vec<Holiday>, StoreError> {
+        sqlx::query_as::<_, Holiday>(
+            "SELECT 
+              * 
+            FROM 
+              holidays 
+            WHERE 
+              date >= $1 
+            ORDER BY 
+              date",
+        )
+        .bind(Utc::now().date_naive())
+        .fetch_all(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn create_holiday(&self, holiday: NewHoliday) -> Result<Holiday, StoreError> {
+        sqlx::query_as::<_, Holiday>(
+            "INSERT INTO holidays(date, name, location) 
+            VALUES 
+              ($1, $2, $3) RETURNING *",
+        )
+        .bind(holiday.date)
+        .bind(holiday.name)
+        .bind(holiday.location)
+        .fetch_one(traced(&self.db))
+        .await
+        .map_err(internal)
+    }
+
+    async fn remove_holiday(&self, id: i32) -> Result<(), StoreError> {
+        let removed = sqlx::query("DELETE FROM holidays WHERE id = $1")
+            .bind(id)
+            .execute(traced(&self.db))
+            .await
+            .map_err(internal)?;
+
+        if removed.rows_affected() == 0 {
+            return Err(StoreError::HolidayNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn holiday_collisions(&self, id: i32) -> Result<Vec<Occupancy>, StoreError> {
+        let mut conn = self.db.acquire().await.map_err(internal)?;
+
+        let holiday = sqlx::query_as::<_, Holiday>("SELECT * FROM holidays WHERE id = $1")
+            .bind(id)
+            .fetch_optional(traced(&mut conn))
+            .await
+            .map_err(internal)?
+            .ok_or(StoreError::HolidayNotFound)?;
+
+        let running = sqlx::query_as::<_, CollisionRow>(
+            "SELECT 
+              occupancies.*, 
+              rooms.timezone 
+            FROM 
+              occupancies 
+              JOIN rooms ON rooms.id = occupancies.occupied_room_id 
+            WHERE 
+              (
+                $1 IS NULL 
+                OR rooms.location = $1
+              ) 
+              AND occupancies.occupied_until > $2",
+        )
+        .bind(&holiday.location)
+        .bind(Utc::now())
+        .fetch_all(traced(&mut conn))
+        .await
+        .map_err(internal)?;
+
+        // The meetings' local dates are worked out here, like for `check_room_bookable`
+        let occupancies = running
+            .into_iter()
+            .filter(|row| {
+                local_date(&row.timezone, row.occupancy.started_at) <= holiday.date
+                    && holiday.date <= local_date(&row.timezone, row.occupancy.occupied_until)
+            })
+            .map(|row| row.occupancy)
+            .collect();
+
+        Ok(occupancies)
+    }
+}
+
+/// A room with the meeting running in it, `occupied_until_local` is filled in afterwards
+#[derive(sqlx::FromRow)]
+struct ActiveRow {
+    #[sqlx(flatten)]
+    room: Room,
+    occupancy_id: i32,
+    occupied_until: DateTime<Utc>,
+    meeting_title: String,
+    meeting_comments: String,
+    started_at: DateTime<Utc>,
+    checked_in: bool,
+    headcount: Option<i32>,
+    booked_by: Option<String>,
+}
+
+impl ActiveRow {
+    fn into_room(self) -> ActiveRoom {
+        let occupancy = Occupancy {
+            id: self.occupancy_id,
+            occupied_room_id: self.room.id,
+            occupied_until: self.occupied_until,
+            meeting_title: self.meeting_title,
+            comments: self.meeting_comments,
+            started_at: self.started_at,
+            checked_in: self.checked_in,
+            headcount: self.headcount,
+            booked_by: self.booked_by,
+        };
+
+        active_room(self.room, &occupancy)
+    }
+}
+
+/// A running meeting with the timezone of its room
+#[derive(sqlx::FromRow)]
+struct CollisionRow {
+    #[sqlx(flatten)]
+    occupancy: Occupancy,
+    timezone: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct UnavailableRow {
+    #[sqlx(flatten)]
+    room: Room,
+    unavailable_until: DateTime<Utc>,
+    unavailable_reason: String,
+}
+
+impl UnavailableRow {
+    fn into_room(self) -> crate::models::UnavailableRoom {
+        crate::models::UnavailableRoom {
+            id: self.room.id,
+            name: self.room.name,
+            room_id: self.room.room_id,
+            capacity: self.room.capacity,
+            time_limit: self.room.time_limit,
+            link: self.room.link,
+            comments: self.room.comments,
+            timezone: self.room.timezone,
+            location: self.room.location,
+            unavailable_until: self.unavailable_until,
+            unavailable_reason: self.unavailable_reason,
+        }
+    }
+}
+
+async fn find_room(
+    conn: &mut SqliteConnection,
+    id: Uuid,
+    active_only: bool,
+) -> Result<Option<Room>, StoreError> {
+    sqlx::query_as::<_, Room>(
+        "SELECT 
+          rooms.id, 
+          rooms.name, 
+          rooms.room_id, 
+          rooms.capacity, 
+          rooms.link, 
+          printf(
+            '%02d:%02d:00', rooms.time_limit / 60, 
+            rooms.time_limit % 60
+          ) as time_limit, 
+          rooms.comments, 
+          rooms.timezone, 
+          rooms.location 
+        FROM 
+          rooms 
+        WHERE 
+          id = $1 
+          AND (
+            NOT $2 
+            OR archived_at IS NULL
+          )",
+    )
+    .bind(id)
+    .bind(active_only)
+    .fetch_optional(traced(&mut *conn))
+    .await
+    .map_err(internal)
+}
+
+async fn find_occupancy(
+    conn: &mut SqliteConnection,
+    room_id: Uuid,
+) -> Result<Option<Occupancy>, StoreError> {
+    sqlx::query_as::<_, Occupancy>("SELECT * FROM occupancies WHERE occupied_room_id = $1")
+        .bind(room_id)
+        .fetch_optional(traced(&mut *conn))
+        .await
+        .map_err(internal)
+}
+
+/// Moves the meeting in the room to the occupancy history, returning it if there was one
+async fn end_occupancy(
+    conn: &mut SqliteConnection,
+    room_id: Uuid,
+    no_show: bool,
+) -> Result<Option<Occupancy>, StoreError> {
+    let occupancy = match find_occupancy(&mut *conn, room_id).await? {
+        Some(occupancy) => occupancy,
+        None => return Ok(None),
+    };
+
+    sqlx::query(
+        "INSERT INTO occupancy_history(
+          occupied_room_id, started_at, ended_at, 
+          occupied_until, meeting_title, comments, 
+          headcount, booked_by, no_show
+        ) 
+        SELECT 
+          occupied_room_id, 
+          started_at, 
+          $2, 
+          occupied_until, 
+          meeting_title, 
+          comments, 
+          headcount, 
+          booked_by, 
+          $3 
+        FROM 
+          occupancies 
+        WHERE 
+          occupied_room_id = $1",
+    )
+    .bind(room_id)
+    .bind(Utc::now())
+    .bind(no_show)
+    .execute(traced(&mut *conn))
+    .await
+    .map_err(internal)?;
+
+    sqlx::query("DELETE FROM occupancies WHERE occupied_room_id = $1")
+        .bind(room_id)
+        .execute(traced(&mut *conn))
+        .await
+        .map_err(internal)?;
+
+    Ok(Some(occupancy))
+}
+
+/// Fails if another room already uses the name or room id, `room_id` is left out of the check
+async fn check_room_conflicts(
+    conn: &mut SqliteConnection,
+    room: &NewRoom,
+    room_id: Option<Uuid>,
+) -> Result<(), StoreError> {
+    let found_rooms = sqlx::query_as::<_, (String, String)>(
+        "SELECT 
+          name, 
+          room_id 
+        FROM 
+          rooms 
+        WHERE 
+          (
+            name = $1 
+            OR room_id = $2
+          ) 
+          AND id IS NOT $3",
+    )
+    .bind(&room.name)
+    .bind(&room.room_id)
+    .bind(room_id)
+    .fetch_all(traced(&mut *conn))
+    .await
+    .map_err(internal)?;
+
+    if found_rooms.iter().any(|(name, _)| *name == room.name) {
+        Err(StoreError::RoomWithNameExists)
+    } else if found_rooms.iter().any(|(_, id)| *id == room.room_id) {
+        Err(StoreError::RoomWithIdExists)
+    } else {
+        Ok(())
+    }
+}
+
+/// Fails if the room can't be booked until `until` because of maintenance, its opening hours
+/// or a holiday
+async fn check_room_bookable(
+    conn: &mut SqliteConnection,
+    room: &Room,
+    until: DateTime<Utc>,
+) -> Result<(), StoreError> {
+    let now = Utc::now();
+
+    let maintenance_window = sqlx::query(
+        "SELECT 
+          id 
+        FROM 
+          maintenance_windows 
+        WHERE 
+          room_id = $1 
+          AND starts_at < $2 
+          AND ends_at > $3",
+    )
+    .bind(room.id)
+    .bind(until)
+    .bind(now)
+    .fetch_optional(traced(&mut *conn))
+    .await
+    .map_err(internal)?;
+
+    if maintenance_window.is_some() {
+        return Err(StoreError::RoomUnderMaintenance);
+    }
+
+    let opening_hours = sqlx::query_as::<_, OpeningHours>(
+        "SELECT 
+          weekday, 
+          opens_at, 
+          closes_at 
+        FROM 
+          room_opening_hours 
+        WHERE 
+          room_id = $1",
+    )
+    .bind(room.id)
+    .fetch_all(traced(&mut *conn))
+    .await
+    .map_err(internal)?;
+
+    if !is_within_opening_hours(&room.timezone, &opening_hours, now, until) {
+        return Err(StoreError::RoomClosed);
+    }
+
+    let holiday = sqlx::query(
+        "SELECT 
+          id 
+        FROM 
+          holidays 
+        WHERE 
+          (
+            location IS NULL 
+            OR location = $1
+          ) 
+          AND date BETWEEN $2 
+          AND $3",
+    )
+    .bind(&room.location)
+    .bind(local_date(&room.timezone, now))
+    .bind(local_date(&room.timezone, until))
+    .fetch_optional(traced(&mut *conn))
+    .await
+    .map_err(internal)?;
+
+    if holiday.is_some() {
+        return Err(StoreError::Holiday);
+    }
+
+    Ok(())
+}
+
+fn internal(err: sqlx::Error) -> StoreError {
+    tracing::error!(error = %err, "Room store query failed");
+
+    StoreError::Internal
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    match err {
+        // SQLITE_CONSTRAINT_UNIQUE
+        sqlx::Error::Database(db_err) => db_err.code().as_deref() == Some("2067"),
+        _ => false,
+    }
+}
@@ -0,0 +1,553 @@
+use std::{collections::HashSet, fs, path::Path, time::Duration};
+
+use crate::{
+    models::{RoomDefinition, RoomsFile, SyncedRoom},
+    schedule::is_valid_timezone,
+    telemetry::traced,
+    DBPool,
+};
+
+enum Change<'a> {
+    Create(&'a RoomDefinition),
+    Update {
+        room: &'a SyncedRoom,
+        definition: &'a RoomDefinition,
+        fields: Vec<FieldChange>,
+    },
+    Archive(&'a SyncedRoom),
+}
+
+struct FieldChange {
+    field: &'static str,
+    from: String,
+    to: String,
+}
+
+/// Reconciles the `rooms` table with a rooms definition file. Rooms are matched on `room_id`:
+/// rooms missing from the table are created, changed ones are updated and rooms missing from
+/// the file are archived, ending the meeting they're occupied by. Archived rooms which show up
+/// in the file again are restored. With `plan_only` the changes are printed without applying
+/// them.
+pub async fn sync_rooms(db: &DBPool, path: &Path, plan_only: bool) -> Result<(), String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+    let rooms_file: RoomsFile = toml::from_str(&contents)
+        .map_err(|e| format!("Couldn't parse {}: {}", path.display(), e))?;
+
+    validate_definitions(&rooms_file.rooms)?;
+
+    let existing = sqlx::query_as::<_, SyncedRoom>(
+        "SELECT 
+          rooms.id, 
+          rooms.name, 
+          rooms.room_id, 
+          rooms.capacity, 
+          (
+            EXTRACT(
+              EPOCH 
+              FROM 
+                rooms.time_limit
+            ) / 60
+          ):: int8 AS time_limit, 
+          rooms.link, 
+          rooms.comments, 
+          rooms.timezone, 
+          rooms.location, 
+          rooms.archived_at IS NOT NULL AS archived, 
+          occupancies.meeting_title 
+        FROM 
+          rooms 
+          LEFT OUTER JOIN occupancies ON occupancies.occupied_room_id = rooms.id 
+        ORDER BY 
+          rooms.name",
+    )
+    .fetch_all(traced(db))
+    .await
+    .map_err(|e| format!("Couldn't fetch rooms: {}", e))?;
+
+    let changes = plan_changes(&rooms_file.rooms, &existing)?;
+
+    print_plan(&changes);
+
+    if plan_only || changes.is_empty() {
+        return Ok(());
+    }
+
+    apply_changes(db, &changes)
+        .await
+        .map_err(|e| format!("Couldn't apply changes, nothing was changed: {}", e))?;
+
+    println!("Applied {} change(s)", changes.len());
+
+    Ok(())
+}
+
+fn validate_definitions(definitions: &[RoomDefinition]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    let mut room_ids = HashSet::new();
+
+    for definition in definitions {
+        if !names.insert(&definition.name) {
+            return Err(format!("Room name {:?} is defined twice", definition.name));
+        }
+
+        if !room_ids.insert(&definition.room_id) {
+            return Err(format!("Room id {:?} is defined twice", definition.room_id));
+        }
+
+        if !is_valid_timezone(definition.timezone.as_deref()) {
+            return Err(format!(
+                "Room {:?} has an invalid timezone, use an IANA timezone name like Europe/Berlin",
+                definition.room_id
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn plan_changes<'a>(
+    definitions: &'a [RoomDefinition],
+    existing: &'a [SyncedRoom],
+) -> Result<Vec<Change<'a>>, String> {
+    let mut changes = vec![];
+
+    for definition in definitions {
+        // Archived rooms keep their name, so it can't be taken by another room
+        let name_holder = existing.iter().find(|room| {
+            room.name == definition.name
+                && room.room_id != definition.room_id
+                && !definitions
+                    .iter()
+                    .any(|other| other.room_id == room.room_id)
+        });
+
+        if let Some(holder) = name_holder {
+            return Err(format!(
+                "Room name {:?} is already used by room {:?}, which is not in the file",
+                definition.name, holder.room_id
+            ));
+        }
+
+        match existing
+            .iter()
+            .find(|room| room.room_id == definition.room_id)
+        {
+            Some(room) => {
+                let fields = diff_room(room, definition);
+
+                if !fields.is_empty() || room.archived {
+                    changes.push(Change::Update {
+                        room,
+                        definition,
+                        fields,
+                    });
+                }
+            }
+            None => changes.push(Change::Create(definition)),
+        }
+    }
+
+    for room in existing {
+        let is_defined = definitions
+            .iter()
+            .any(|definition| definition.room_id == room.room_id);
+
+        if !is_defined && !room.archived {
+            changes.push(Change::Archive(room));
+        }
+    }
+
+    Ok(changes)
+}
+
+fn diff_room(room: &SyncedRoom, definition: &RoomDefinition) -> Vec<FieldChange> {
+    let mut fields = vec![];
+    let mut compare = |field, from: String, to: String| {
+        if from != to {
+            fields.push(FieldChange { field, from, to });
+        }
+    };
+
+    compare(
+        "name",
+        format!("{:?}", room.name),
+        format!("{:?}", definition.name),
+    );
+    compare(
+        "capacity",
+        format_optional(room.capacity),
+        definition.capacity.to_string(),
+    );
+    compare(
+        "time_limit",
+        format_optional(room.time_limit),
+        definition.time_limit.to_string(),
+    );
+    compare(
+        "link",
+        format!("{:?}", room.link),
+        format!("{:?}", definition.link),
+    );
+    compare(
+        "comments",
+        format!("{:?}", room.comments.as_deref().unwrap_or_default()),
+        format!("{:?}", definition.comments),
+    );
+    compare(
+        "timezone",
+        format!("{:?}", room.timezone),
+        format!("{:?}", definition.timezone.as_deref().unwrap_or("UTC")),
+    );
+    compare(
+        "location",
+        format_optional(
+            room.location
+                .as_ref()
+                .map(|location| format!("{:?}", location)),
+        ),
+        format_optional(
+            definition
+                .location
+                .as_ref()
+                .map(|location| format!("{:?}", location)),
+        ),
+    );
+    fields
+}
+
+fn format_optional<T: ToString>(value: Option<T>) -> String {
+    value.map_or("none".to_string(), |value| value.to_string())
+}
+
+fn print_plan(changes: &[Change]) {
+    let (mut created, mut updated, mut archived) = (0, 0, 0);
+
+    for change in changes {
+        match change {
+            Change::Create(definition) => {
+                created += 1;
+                println!("+ create {} {:?}", definition.room_id, definition.name);
+            }
+            Change::Update { room, fields, .. } => {
+                updated += 1;
+
+                if room.archived {
+                    println!("~ restore {} {:?}", room.room_id, room.name);
+                } else {
+                    println!("~ update {} {:?}", room.room_id, room.name);
+                }
+
+                for field in fields {
+                    println!("    {}: {} -> {}", field.field, field.from, field.to);
+                }
+            }
+            Change::Archive(room) => {
+                archived += 1;
+                println!("- archive {} {:?}", room.room_id, room.name);
+
+                if let Some(meeting_title) = &room.meeting_title {
+                    println!("    ends meeting {:?}", meeting_title);
+                }
+            }
+        }
+    }
+
+    println!(
+        "Plan: {} to create, {} to update, {} to archive",
+        created, updated, archived
+    );
+}
+
+async fn apply_changes(db: &DBPool, changes: &[Change<'_>]) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    // Renaming rooms one at a time can clash with a name another room gives up later on, like
+    // when two rooms swap names. The names are checked once everything is applied.
+    sqlx::query("SET CONSTRAINTS rooms_name_key DEFERRED")
+        .execute(traced(&mut tx))
+        .await?;
+
+    for change in changes {
+        match change {
+            Change::Create(definition) => {
+                sqlx::query(
+                    "INSERT INTO rooms(
+                      name, room_id, capacity, time_limit, 
+                      link, comments, timezone, location
+                    ) 
+                    VALUES 
+                      (
+                        $1, $2, $3, $4 :: interval, $5, $6, 
+                        COALESCE($7, 'UTC'), 
+                        $8
+                      )",
+                )
+                .bind(&definition.name)
+                .bind(&definition.room_id)
+                .bind(definition.capacity)
+                .bind(Duration::from_secs(definition.time_limit * 60))
+                .bind(&definition.link)
+                .bind(&definition.comments)
+                .bind(&definition.timezone)
+                .bind(&definition.location)
+                .execute(traced(&mut tx))
+                .await?;
+            }
+            Change::Update {
+                room, definition, ..
+            } => {
+                sqlx::query(
+                    "UPDATE 
+                      rooms 
+                    SET 
+                      name = $2, 
+                      capacity = $3, 
+                      time_limit = $4 :: interval, 
+                      link = $5, 
+                      comments = $6, 
+                      timezone = COALESCE($7, 'UTC'), 
+                      location = $8, 
+                      archived_at = NULL 
+                    WHERE 
+                      id = $1",
+                )
+                .bind(room.id)
+                .bind(&definition.name)
+                .bind(definition.capacity)
+                .bind(Duration::from_secs(definition.time_limit * 60))
+                .bind(&definition.link)
+                .bind(&definition.comments)
+                .bind(&definition.timezone)
+                .bind(&definition.location)
+                .execute(traced(&mut tx))
+                .await?;
+            }
+            Change::Archive(room) => {
+                // Archived rooms can't stay occupied, their meeting ends now
+                sqlx::query(
+                    "WITH ended AS (
+                      DELETE FROM 
+                        occupancies 
+                      WHERE 
+                        occupied_room_id = $1 RETURNING *
+                    ) 
+                    INSERT INTO occupancy_history(
+                      occupied_room_id, started_at, ended_at, 
+                      occupied_until, meeting_title, comments, 
+                      headcount, booked_by, no_show
+                    ) 
+                    SELECT 
+                      occupied_room_id, 
+                      started_at, 
+                      NOW(), 
+                      occupied_until, 
+                      meeting_title, 
+                      comments, 
+                      headcount, 
+                      booked_by, 
+                      FALSE 
+                    FROM 
+                      ended",
+                )
+                .bind(room.id)
+                .execute(traced(&mut tx))
+                .await?;
+
+                sqlx::query("UPDATE rooms SET archived_at = NOW() WHERE id = $1")
+                    .bind(room.id)
+                    .execute(traced(&mut tx))
+                    .await?;
+            }
+        }
+    }
+
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{diff_room, plan_changes, Change};
+    use crate::models::{RoomDefinition, SyncedRoom};
+
+    fn definition(name: &str, room_id: &str) -> RoomDefinition {
+        RoomDefinition {
+            name: name.to_string(),
+            room_id: room_id.to_string(),
+            capacity: 8,
+            time_limit: 90,
+            link: format!("https://zoom.us/j/{room_id}"),
+            comments: String::new(),
+            timezone: None,
+            location: None,
+        }
+    }
+
+    fn synced(name: &str, room_id: &str) -> SyncedRoom {
+        SyncedRoom {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            room_id: room_id.to_string(),
+            capacity: Some(8),
+            time_limit: Some(90),
+            link: format!("https://zoom.us/j/{room_id}"),
+            comments: None,
+            timezone: String::from("UTC"),
+            location: None,
+            archived: false,
+            meeting_title: None,
+        }
+    }
+
+    /// One line per change, like `create k2`, `update everest name` or `archive k2 ending Standup`
+    fn summary(changes: &[Change]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|change| match change {
+                Change::Create(definition) => format!("create {}", definition.room_id),
+                Change::Update { room, fields, .. } => {
+                    let fields: Vec<&str> = fields.iter().map(|field| field.field).collect();
+
+                    format!("update {} {}", room.room_id, fields.join(" "))
+                        .trim_end()
+                        .to_string()
+                }
+                Change::Archive(room) => match &room.meeting_title {
+                    Some(meeting_title) => {
+                        format!("archive {} ending {}", room.room_id, meeting_title)
+                    }
+                    None => format!("archive {}", room.room_id),
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diffs_only_changed_fields() {
+        let room = synced("Everest", "everest");
+        assert!(diff_room(&room, &definition("Everest", "everest")).is_empty());
+
+        let changed = RoomDefinition {
+            capacity: 10,
+            timezone: Some(String::from("Europe/Berlin")),
+            location: Some(String::from("HQ")),
+            ..definition("Everest", "everest")
+        };
+        let fields = diff_room(&room, &changed);
+        let described: Vec<String> = fields
+            .iter()
+            .map(|field| format!("{}: {} -> {}", field.field, field.from, field.to))
+            .collect();
+
+        assert_eq!(
+            described,
+            [
+                "capacity: 8 -> 10",
+                "timezone: \"UTC\" -> \"Europe/Berlin\"",
+                "location: none -> \"HQ\"",
+            ]
+        );
+
+        // Rooms created before capacities were required have none
+        let room = SyncedRoom {
+            capacity: None,
+            comments: Some(String::new()),
+            ..synced("Everest", "everest")
+        };
+        let fields = diff_room(&room, &definition("Everest", "everest"));
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].from, "none");
+    }
+
+    #[test]
+    fn plans_creates_updates_restores_and_archives() {
+        let existing = [
+            synced("Everest", "everest"),
+            synced("Lhotse", "lhotse"),
+            SyncedRoom {
+                meeting_title: Some(String::from("Standup")),
+                ..synced("Makalu", "makalu")
+            },
+            SyncedRoom {
+                archived: true,
+                ..synced("Denali", "denali")
+            },
+            SyncedRoom {
+                archived: true,
+                ..synced("Aconcagua", "aconcagua")
+            },
+        ];
+        let definitions = [
+            definition("Everest", "everest"),
+            definition("K2", "k2"),
+            definition("Denali", "denali"),
+        ];
+
+        let changes = plan_changes(&definitions, &existing).unwrap();
+
+        assert_eq!(
+            summary(&changes),
+            [
+                "create k2",
+                "update denali",
+                "archive lhotse",
+                "archive makalu ending Standup"
+            ]
+        );
+    }
+
+    #[test]
+    fn plans_name_swaps_and_rotations() {
+        let existing = [
+            synced("Everest", "everest"),
+            synced("K2", "k2"),
+            synced("Denali", "denali"),
+        ];
+        let definitions = [
+            definition("K2", "everest"),
+            definition("Denali", "k2"),
+            definition("Everest", "denali"),
+        ];
+
+        let changes = plan_changes(&definitions, &existing).unwrap();
+
+        assert_eq!(
+            summary(&changes),
+            [
+                "update everest name",
+                "update k2 name",
+                "update denali name"
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_names_held_by_rooms_not_in_the_file() {
+        let existing = [
+            synced("Everest", "everest"),
+            SyncedRoom {
+                archived: true,
+                ..synced("K2", "k2")
+            },
+        ];
+
+        // Archived rooms keep their name
+        let definitions = [definition("Everest", "everest"), definition("K2", "godwin")];
+        let err = plan_changes(&definitions, &existing).err().unwrap();
+        assert_eq!(
+            err,
+            "Room name \"K2\" is already used by room \"k2\", which is not in the file"
+        );
+
+        // Unless the room holding it is renamed by the same file
+        let definitions = [
+            definition("Everest", "everest"),
+            definition("K2", "godwin"),
+            definition("Chogori", "k2"),
+        ];
+        let changes = plan_changes(&definitions, &existing).unwrap();
+        assert_eq!(summary(&changes), ["create godwin", "update k2 name"]);
+    }
+}
@@ -0,0 +1,257 @@
+use std::env;
+
+use futures::{future::BoxFuture, stream::BoxStream, StreamExt};
+use hyper::HeaderMap;
+use opentelemetry::{
+    propagation::{Extractor, TextMapPropagator},
+    trace::{TraceError, TracerProvider as _},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{self as sdktrace, TracerProvider},
+    Resource,
+};
+use sqlx::{database::HasStatement, Database, Describe, Either, Execute, Executor};
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Exports spans over OTLP/gRPC when `OTEL_EXPORTER_OTLP_ENDPOINT` is set, e.g. to a collector
+/// listening on http://localhost:4317. Spans aren't recorded at all without it.
+pub fn otlp_tracer_provider() -> Result<Option<TracerProvider>, TraceError> {
+    let endpoint = match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(endpoint) if !endpoint.is_empty() => endpoint,
+        _ => return Ok(None),
+    };
+
+    let exporter = opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(endpoint)
+        .build_span_exporter()?;
+
+    let provider =
+        tracer_provider(TracerProvider::builder().with_batch_exporter(exporter, runtime::Tokio));
+
+    Ok(Some(provider))
+}
+
+/// Finishes a provider with the resource every exported span is tagged with. `OTEL_SERVICE_NAME`
+/// overrides the service name.
+pub fn tracer_provider(builder: sdktrace::Builder) -> TracerProvider {
+    let service_name = env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| String::from("zoomer-api"));
+
+    builder
+        .with_config(
+            sdktrace::config()
+                .with_resource(Resource::new([KeyValue::new("service.name", service_name)])),
+        )
+        .build()
+}
+
+/// Turns the API's tracing spans into OpenTelemetry spans
+pub fn layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, sdktrace::Tracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("zoomer_api"))
+}
+
+/// The trace a request is part of, from its W3C `traceparent` and `tracestate` headers
+pub fn remote_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Value of the `db.system` attribute on query spans
+pub trait DbSystem {
+    const NAME: &'static str;
+}
+
+impl DbSystem for sqlx::Postgres {
+    const NAME: &'static str = "postgresql";
+}
+
+#[cfg(feature = "sqlite")]
+impl DbSystem for sqlx::Sqlite {
+    const NAME: &'static str = "sqlite";
+}
+
+/// Runs every query on `executor` in a span of its own. On a pool the span includes waiting for
+/// a connection.
+pub fn traced<'c, E: Executor<'c>>(executor: E) -> Traced<E> {
+    Traced(executor)
+}
+
+#[derive(Debug)]
+pub struct Traced<E>(E);
+
+fn query_span<DB: DbSystem>(sql: &str) -> Span {
+    let statement = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    let operation = statement
+        .split(' ')
+        .next()
+        .unwrap_or_default()
+        .to_uppercase();
+
+    tracing::info_span!(
+        "db.query",
+        otel.name = operation,
+        otel.kind = "client",
+        db.system = DB::NAME,
+        db.statement = statement,
+    )
+}
+
+impl<'c, E> Executor<'c> for Traced<E>
+where
+    E: Executor<'c>,
+    E::Database: DbSystem,
+{
+    type Database = E::Database;
+
+    fn fetch_many<'e, 'q: 'e, Q>(
+        self,
+        query: Q,
+    ) -> BoxStream<
+        'e,
+        Result<
+            Either<<Self::Database as Database>::QueryResult, <Self::Database as Database>::Row>,
+            sqlx::Error,
+        >,
+    >
+    where
+        'c: 'e,
+        Q: Execute<'q, Self::Database> + 'q,
+    {
+        let span = query_span::<Self::Database>(query.sql());
+        let rows = self.0.fetch_many(query);
+
+        // The span ends when the rows are dropped, after the last one has been read
+        rows.map(move |row| {
+            let _span = &span;
+
+            row
+        })
+        .boxed()
+    }
+
+    fn fetch_optional<'e, 'q: 'e, Q>(
+        self,
+        query: Q,
+    ) -> BoxFuture<'e, Result<Option<<Self::Database as Database>::Row>, sqlx::Error>>
+    where
+        'c: 'e,
+        Q: Execute<'q, Self::Database> + 'q,
+    {
+        let span = query_span::<Self::Database>(query.sql());
+
+        Box::pin(self.0.fetch_optional(query).instrument(span))
+    }
+
+    fn prepare_with<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+        parameters: &'e [<Self::Database as Database>::TypeInfo],
+    ) -> BoxFuture<'e, Result<<Self::Database as HasStatement<'q>>::Statement, sqlx::Error>>
+    where
+        'c: 'e,
+    {
+        self.0.prepare_with(sql, parameters)
+    }
+
+    fn describe<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+    ) -> BoxFuture<'e, Result<Describe<Self::Database>, sqlx::Error>>
+    where
+        'c: 'e,
+    {
+        self.0.describe(sql)
+    }
+}
+
+/// Records spans in memory instead of exporting them, for as long as the returned guard lives
+#[cfg(test)]
+pub fn record_spans() -> (
+    opentelemetry_sdk::testing::trace::InMemorySpanExporter,
+    TracerProvider,
+    tracing::subscriber::DefaultGuard,
+) {
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporterBuilder;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let exporter = InMemorySpanExporterBuilder::new().build();
+    let provider =
+        tracer_provider(TracerProvider::builder().with_simple_exporter(exporter.clone()));
+    let subscriber = tracing_subscriber::registry().with(layer(&provider));
+
+    (
+        exporter,
+        provider,
+        tracing::subscriber::set_default(subscriber),
+    )
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use opentelemetry::{trace::SpanKind, Value};
+    use sqlx::SqlitePool;
+    use tracing::Instrument;
+
+    use super::{record_spans, traced};
+
+    #[tokio::test]
+    async fn records_a_span_per_query() {
+        let (exporter, provider, _guard) = record_spans();
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        async {
+            sqlx::query("SELECT 1")
+                .fetch_one(traced(&pool))
+                .await
+                .unwrap();
+            sqlx::query("select   2 \n union select 3")
+                .fetch_all(traced(&pool))
+                .await
+                .unwrap();
+        }
+        .instrument(tracing::info_span!("request"))
+        .await;
+
+        provider.force_flush();
+        let spans = exporter.get_finished_spans().unwrap();
+        let request = spans.iter().find(|span| span.name == "request").unwrap();
+        let queries: Vec<_> = spans
+            .iter()
+            .filter(|span| span.span_kind == SpanKind::Client)
+            .collect();
+
+        assert_eq!(queries.len(), 2);
+
+        for query in &queries {
+            assert_eq!(query.name, "SELECT");
+            assert_eq!(query.parent_span_id, request.span_context.span_id());
+        }
+
+        let statement = queries[1]
+            .attributes
+            .iter()
+            .find(|attribute| attribute.key.as_str() == "db.statement")
+            .map(|attribute| attribute.value.clone());
+        assert_eq!(statement, Some(Value::from("select 2 union select 3")));
+    }
+}
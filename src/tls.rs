@@ -0,0 +1,423 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use futures::future::poll_fn;
+use hyper::server::{
+    accept::{self, Accept},
+    conn::{AddrIncoming, AddrStream},
+};
+use tokio::sync::mpsc;
+use tokio_rustls::{
+    rustls::{
+        server::{ClientHello, ResolvesServerCert},
+        sign::{self, CertifiedKey},
+        Certificate, PrivateKey, ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use warp::{
+    http::{header::LOCATION, uri::Authority, StatusCode},
+    path::FullPath,
+    reply::Response,
+    Reply,
+};
+
+use crate::{config::Config, server::RemoteAddr};
+
+/// How often the certificate and key files are checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a client gets to finish its TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Modification times of the certificate and key files
+type Modified = (Option<SystemTime>, Option<SystemTime>);
+
+/// Hands out the certificate read from `cert_path` and `key_path`, swapped for the new one
+/// whenever the files are replaced, without restarting the server.
+pub struct CertResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    loaded: Mutex<Modified>,
+}
+
+impl CertResolver {
+    /// The certificate configured with `tls_cert_path` and `tls_key_path`, if any
+    pub fn from_config(config: &Config) -> Result<Option<Self>, String> {
+        match (&config.tls_cert_path, &config.tls_key_path) {
+            (Some(cert_path), Some(key_path)) => Self::load(cert_path, key_path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn load(cert_path: &Path, key_path: &Path) -> Result<Self, String> {
+        let loaded = modified(cert_path, key_path);
+        let current = load_certified_key(cert_path, key_path)?;
+
+        Ok(CertResolver {
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            current: RwLock::new(Arc::new(current)),
+            loaded: Mutex::new(loaded),
+        })
+    }
+
+    /// Reads the files again, keeping the current certificate when they can't be used
+    pub fn reload(&self) -> Result<(), String> {
+        let loaded = modified(&self.cert_path, &self.key_path);
+        let current = load_certified_key(&self.cert_path, &self.key_path)?;
+
+        *self.current.write().unwrap() = Arc::new(current);
+        *self.loaded.lock().unwrap() = loaded;
+
+        Ok(())
+    }
+
+    fn modified(&self) -> Modified {
+        modified(&self.cert_path, &self.key_path)
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn modified(cert_path: &Path, key_path: &Path) -> Modified {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+
+    (modified(cert_path), modified(key_path))
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey, String> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .map_err(|e| format!("Couldn't parse {}: {}", cert_path.display(), e))?;
+
+    if certs.is_empty() {
+        return Err(format!("No certificates in {}", cert_path.display()));
+    }
+
+    let key = rustls_pemfile::read_all(&mut open(key_path)?)
+        .map_err(|e| format!("Couldn't parse {}: {}", key_path.display(), e))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key in {}", key_path.display()))?;
+
+    let key = sign::any_supported_type(&key)
+        .map_err(|e| format!("Unsupported private key in {}: {}", key_path.display(), e))?;
+
+    Ok(CertifiedKey::new(
+        certs.into_iter().map(Certificate).collect(),
+        key,
+    ))
+}
+
+/// Reloads the certificate once its files changed and then stayed the same for a whole
+/// `period`, so a new certificate isn't picked up before its key has been written too.
+pub async fn reload_on_change(resolver: Arc<CertResolver>, period: Duration) {
+    let mut interval = tokio::time::interval(period);
+    let mut seen = resolver.modified();
+
+    loop {
+        interval.tick().await;
+
+        let modified = resolver.modified();
+
+        if modified != seen {
+            seen = modified;
+            continue;
+        }
+
+        if modified == *resolver.loaded.lock().unwrap() {
+            continue;
+        }
+
+        match resolver.reload() {
+            Ok(()) => tracing::info!("Reloaded the TLS certificate"),
+            Err(e) => {
+                // Not retried until the files change again
+                *resolver.loaded.lock().unwrap() = modified;
+
+                tracing::error!(error = %e, "Failed to reload the TLS certificate, keeping the current one");
+            }
+        }
+    }
+}
+
+impl RemoteAddr for TlsStream<AddrStream> {
+    fn remote_addr(&self) -> SocketAddr {
+        self.get_ref().0.remote_addr()
+    }
+}
+
+/// HTTP/2 and HTTP/1.1 over TLS with the certificate `resolver` hands out
+pub fn server_config(resolver: Arc<CertResolver>) -> Arc<ServerConfig> {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Arc::new(config)
+}
+
+/// Accepts the connections on `incoming` over TLS. Every handshake runs in a task of its own,
+/// so a slow client doesn't hold up the others. Connections stop being accepted once the
+/// returned acceptor is dropped.
+pub fn accept(
+    mut incoming: AddrIncoming,
+    config: Arc<ServerConfig>,
+) -> impl Accept<Conn = TlsStream<AddrStream>, Error = io::Error> {
+    let acceptor = TlsAcceptor::from(config);
+    let (tx, mut rx) = mpsc::channel(32);
+
+    tokio::spawn(async move {
+        loop {
+            let conn = tokio::select! {
+                conn = poll_fn(|cx| std::pin::Pin::new(&mut incoming).poll_accept(cx)) => conn,
+                _ = tx.closed() => break,
+            };
+
+            let stream = match conn {
+                Some(Ok(stream)) => stream,
+                Some(Err(e)) => {
+                    tracing::error!(error = %e, "Failed to accept a connection");
+                    continue;
+                }
+                None => break,
+            };
+
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        tx.send(Ok(stream)).await.ok();
+                    }
+                    Ok(Err(e)) => tracing::debug!(error = %e, "TLS handshake failed"),
+                    Err(_) => tracing::debug!("TLS handshake timed out"),
+                }
+            });
+        }
+    });
+
+    accept::from_stream(futures::stream::poll_fn(move |cx| rx.poll_recv(cx)))
+}
+
+/// Sends the client to the same path and query over HTTPS on `https_port`
+pub fn redirect_to_https(
+    https_port: u16,
+    host: Option<String>,
+    path: FullPath,
+    query: Option<String>,
+) -> Response {
+    let host = host.and_then(|host| host.parse::<Authority>().ok());
+
+    let Some(host) = host.as_ref().map(Authority::host) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let port = if https_port == 443 {
+        String::new()
+    } else {
+        format!(":{}", https_port)
+    };
+
+    let query = query.map(|query| format!("?{}", query)).unwrap_or_default();
+
+    let location = format!("https://{}{}{}{}", host, port, path.as_str(), query);
+
+    warp::reply::with_header(
+        warp::reply::with_status(warp::reply(), StatusCode::PERMANENT_REDIRECT),
+        LOCATION,
+        location,
+    )
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        sync::oneshot,
+    };
+    use tokio_rustls::{
+        rustls::{Certificate, ClientConfig, RootCertStore, ServerName},
+        TlsConnector,
+    };
+    use uuid::Uuid;
+    use warp::Filter;
+
+    use super::{reload_on_change, server_config, CertResolver};
+    use crate::server::serve;
+
+    /// Writes a new self-signed certificate for localhost into `dir`, returning it as DER
+    fn write_certificate(dir: &Path) -> Vec<u8> {
+        let cert = rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+
+        fs::write(dir.join("cert.pem"), cert.serialize_pem().unwrap()).unwrap();
+        fs::write(dir.join("key.pem"), cert.serialize_private_key_pem()).unwrap();
+
+        cert.serialize_der().unwrap()
+    }
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("zoomer-tls-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    /// The certificate the resolver hands out, as DER
+    fn current_certificate(resolver: &CertResolver) -> Vec<u8> {
+        resolver.current.read().unwrap().cert[0].0.clone()
+    }
+
+    /// The certificate in `path` as DER, signatures differ every time rcgen serializes
+    fn read_certificate(path: &Path) -> Vec<u8> {
+        let pem = fs::read(path).unwrap();
+
+        rustls_pemfile::certs(&mut pem.as_slice())
+            .unwrap()
+            .remove(0)
+    }
+
+    /// GET / over TLS, trusting only `trusted`
+    async fn get(addr: SocketAddr, trusted: &[u8]) -> std::io::Result<String> {
+        let mut roots = RootCertStore::empty();
+        roots.add(&Certificate(trusted.to_vec())).unwrap();
+
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await?;
+
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await?;
+
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).await?;
+
+        Ok(resp)
+    }
+
+    #[tokio::test]
+    async fn serves_https_with_the_reloaded_certificate() {
+        let dir = temp_dir();
+
+        let first = write_certificate(&dir);
+        let resolver =
+            Arc::new(CertResolver::load(&dir.join("cert.pem"), &dir.join("key.pem")).unwrap());
+
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+
+        tokio::spawn(serve(
+            warp::any().map(|| "ok"),
+            addr,
+            Some(server_config(resolver.clone())),
+            async {
+                shutdown_rx.await.ok();
+            },
+            Duration::from_secs(1),
+        ));
+
+        let mut resp = Err(std::io::ErrorKind::NotConnected.into());
+
+        for _ in 0..50 {
+            resp = get(addr, &first).await;
+
+            if resp.is_ok() {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let resp = resp.unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert!(resp.ends_with("ok"));
+
+        let second = write_certificate(&dir);
+        resolver.reload().unwrap();
+
+        assert!(get(addr, &first).await.is_err());
+        assert!(get(addr, &second).await.unwrap().ends_with("ok"));
+
+        shutdown.send(()).unwrap();
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn keeps_the_certificate_until_its_key_is_written() {
+        let dir = temp_dir();
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+
+        write_certificate(&dir);
+        let first = read_certificate(&cert_path);
+        let resolver = Arc::new(CertResolver::load(&cert_path, &key_path).unwrap());
+
+        let period = Duration::from_millis(20);
+        let reloader = tokio::spawn(reload_on_change(resolver.clone(), period));
+
+        // A new certificate next to the first half of its key
+        let second = rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+        let key = second.serialize_private_key_pem();
+        fs::write(&cert_path, second.serialize_pem().unwrap()).unwrap();
+        fs::write(&key_path, &key[..key.len() / 2]).unwrap();
+
+        tokio::time::sleep(period * 10).await;
+        assert_eq!(current_certificate(&resolver), first);
+
+        fs::write(&key_path, &key).unwrap();
+
+        let second = read_certificate(&cert_path);
+
+        for _ in 0..100 {
+            if current_certificate(&resolver) == second {
+                break;
+            }
+
+            tokio::time::sleep(period).await;
+        }
+
+        assert_eq!(current_certificate(&resolver), second);
+
+        reloader.abort();
+        fs::remove_dir_all(&dir).ok();
+    }
+}